@@ -0,0 +1,338 @@
+use crate::objetos::{Objeto, Tipo};
+use crate::paquete::{self, Paquete};
+use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ffi::CStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, ErrorKind, Read, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// La base de datos de objetos de un repositorio: objetos sueltos en `objects/xx/yyyy...` y
+/// packfiles en `objects/pack/`. Quien la usa no necesita saber dónde está guardado cada objeto.
+pub(crate) struct BaseObjetos {
+    directorio: PathBuf,
+    // Los índices de los paquetes se cargan la primera vez que hacen falta
+    paquetes: OnceCell<Vec<Paquete>>,
+}
+
+impl BaseObjetos {
+    /// Abre la base de objetos del directorio git indicado (por ejemplo `.git` o un repo bare)
+    pub(crate) fn abrir(directorio_git: impl AsRef<Path>) -> BaseObjetos {
+        BaseObjetos {
+            directorio: directorio_git.as_ref().join("objects"),
+            paquetes: OnceCell::new(),
+        }
+    }
+
+    /// Lee un objeto. Los sueltos se descomprimen a medida que se leen; los empacados se
+    /// reconstruyen en memoria.
+    pub(crate) fn leer(&self, hash: &str) -> Result<Objeto<Box<dyn BufRead>>> {
+        validar_hash(hash)?;
+        let ruta = self.ruta_suelto(hash);
+        if ruta.is_file() {
+            return leer_suelto(&ruta);
+        }
+
+        let (tipo, datos) = self.leer_empacado(hash)?;
+        Ok(Objeto {
+            tipo,
+            tamaño_esperado: datos.len() as u64,
+            lector: Box::new(Cursor::new(datos)),
+        })
+    }
+
+    /// Lee un objeto completo en memoria
+    pub(crate) fn leer_completo(&self, hash: &str) -> Result<(Tipo, Vec<u8>)> {
+        let mut objeto = self.leer(hash)?;
+        let mut datos = Vec::new();
+        objeto.lector.read_to_end(&mut datos)?;
+        if datos.len() as u64 != objeto.tamaño_esperado {
+            anyhow::bail!(
+                "El objeto {} está truncado: se esperaban {} bytes, se leyeron {}",
+                hash,
+                objeto.tamaño_esperado,
+                datos.len()
+            );
+        }
+        Ok((objeto.tipo, datos))
+    }
+
+    /// Guarda un objeto suelto (si no existía ya) y devuelve su hash
+    pub(crate) fn escribir(&self, tipo: Tipo, contenido: &[u8]) -> Result<String> {
+        let hash = calcular_hash(tipo, contenido);
+        if self.existe(&hash) {
+            return Ok(hash);
+        }
+
+        // Se escribe en un temporal del mismo directorio y se renombra: un proceso interrumpido
+        // nunca deja un objeto truncado con el nombre definitivo
+        let ruta = self.ruta_suelto(&hash);
+        let directorio = ruta.parent().expect("la ruta tiene directorio");
+        fs::create_dir_all(directorio)?;
+
+        let mut comprimido = ZlibEncoder::new(Vec::new(), Compression::default());
+        write!(comprimido, "{} {}\0", tipo, contenido.len())?;
+        comprimido.write_all(contenido)?;
+        escribir_renombrando(directorio, &ruta, &comprimido.finish()?)
+            .with_context(|| format!("No se pudo escribir el objeto {}", hash))?;
+
+        Ok(hash)
+    }
+
+    /// Guarda un packfile en `objects/pack/` junto con un `.idx` generado a partir de él y
+    /// devuelve cuántos objetos contiene. El paquete se copia a disco a medida que se lee y se
+    /// indexa desde ahí, así que la memoria usada no depende de su tamaño.
+    pub(crate) fn guardar_paquete(&mut self, datos: &mut impl Read) -> Result<usize> {
+        let directorio = self.directorio.join("pack");
+        fs::create_dir_all(&directorio)?;
+        let (ruta_temporal, archivo) = crear_temporal(&directorio)?;
+
+        let resultado = (|| -> Result<usize> {
+            let mut escritor = BufWriter::new(archivo);
+            io::copy(datos, &mut escritor).context("No se pudo recibir el packfile")?;
+            escritor.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+            let (objetos, checksum) = paquete::indexar(&ruta_temporal, self)?;
+            let idx = paquete::generar_idx(&objetos, &checksum);
+
+            // Primero el .pack y luego el .idx: un .idx solo se tiene en cuenta si su .pack existe
+            let nombre = format!("pack-{}", hex::encode(checksum));
+            fs::set_permissions(&ruta_temporal, fs::Permissions::from_mode(0o444))?;
+            fs::rename(&ruta_temporal, directorio.join(format!("{}.pack", nombre)))?;
+            escribir_renombrando(&directorio, &directorio.join(format!("{}.idx", nombre)), &idx)?;
+            Ok(objetos.len())
+        })();
+        if resultado.is_err() {
+            let _ = fs::remove_file(&ruta_temporal);
+        }
+
+        // La lista de paquetes se vuelve a cargar en la próxima lectura
+        self.paquetes.take();
+        resultado
+    }
+
+    pub(crate) fn existe(&self, hash: &str) -> bool {
+        if validar_hash(hash).is_err() {
+            return false;
+        }
+        self.ruta_suelto(hash).is_file() || self.buscar_en_paquetes(hash).is_ok_and(|r| r.is_some())
+    }
+
+    /// Todos los hashes de la base, sueltos y empacados, en orden y sin repetir
+    pub(crate) fn iterar(&self) -> Result<impl Iterator<Item = String>> {
+        let mut hashes: BTreeSet<String> = self.sueltos()?.into_iter().collect();
+        for paquete in self.paquetes()? {
+            hashes.extend(paquete.hashes().map(hex::encode));
+        }
+        Ok(hashes.into_iter())
+    }
+
+    /// Los hashes que empiezan por un prefijo hexadecimal en minúsculas de al menos 2 cifras. Solo
+    /// se leen el directorio `objects/<xx>/` de ese prefijo y, en cada paquete, el rango de su
+    /// primer byte.
+    pub(crate) fn buscar_prefijo(&self, prefijo: &str) -> Result<BTreeSet<String>> {
+        let primer_byte = prefijo
+            .get(..2)
+            .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+            .with_context(|| format!("'{}' no es un prefijo de hash válido", prefijo))?;
+        let mut hashes = BTreeSet::new();
+
+        let directorio = self.directorio.join(&prefijo[..2]);
+        if directorio.is_dir() {
+            for objeto in fs::read_dir(&directorio)? {
+                let hash = format!("{}{}", &prefijo[..2], objeto?.file_name().to_string_lossy());
+                if hash.starts_with(prefijo) && validar_hash(&hash).is_ok() {
+                    hashes.insert(hash);
+                }
+            }
+        }
+        for paquete in self.paquetes()? {
+            hashes.extend(paquete.buscar_prefijo(primer_byte, prefijo));
+        }
+        Ok(hashes)
+    }
+
+    /// Lee un objeto suelto comprobando lo que `leer` da por bueno: que el contenido mida
+    /// exactamente lo que dice la cabecera (sin descomprimir más allá de un byte de sobra) y que
+    /// su hash coincida con el nombre del archivo
+    pub(crate) fn verificar_suelto(&self, hash: &str) -> Result<(Tipo, Vec<u8>)> {
+        validar_hash(hash)?;
+        let archivo = File::open(self.ruta_suelto(hash)).context("abrir en .git/objects")?;
+        let mut z = BufReader::new(ZlibDecoder::new(archivo));
+        let (tipo, tamaño) = leer_cabecera_suelto(&mut z)?;
+        let mut datos = Vec::new();
+        z.take(tamaño.saturating_add(1))
+            .read_to_end(&mut datos)
+            .context("el contenido comprimido está dañado")?;
+        match (datos.len() as u64).cmp(&tamaño) {
+            Ordering::Less => anyhow::bail!("truncado: la cabecera dice {} bytes y hay {}", tamaño, datos.len()),
+            Ordering::Greater => anyhow::bail!("la cabecera dice {} bytes pero el contenido es más largo", tamaño),
+            Ordering::Equal => {}
+        }
+        let calculado = calcular_hash(tipo, &datos);
+        if calculado != hash {
+            anyhow::bail!("el contenido tiene el hash {}", calculado);
+        }
+        Ok((tipo, datos))
+    }
+
+    /// Los hashes de los objetos sueltos
+    pub(crate) fn sueltos(&self) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        if self.directorio.is_dir() {
+            for entrada in fs::read_dir(&self.directorio)? {
+                let entrada = entrada?;
+                let prefijo = entrada.file_name().to_string_lossy().into_owned();
+                if prefijo.len() != 2 || !prefijo.chars().all(|c| c.is_ascii_hexdigit()) {
+                    continue;
+                }
+                for objeto in fs::read_dir(entrada.path())? {
+                    let hash = format!("{}{}", prefijo, objeto?.file_name().to_string_lossy());
+                    if validar_hash(&hash).is_ok() {
+                        hashes.push(hash);
+                    }
+                }
+            }
+        }
+        Ok(hashes)
+    }
+
+    /// El directorio `objects` de la base
+    pub(crate) fn directorio(&self) -> &Path {
+        &self.directorio
+    }
+
+    pub(crate) fn ruta_suelto(&self, hash: &str) -> PathBuf {
+        self.directorio.join(&hash[..2]).join(&hash[2..])
+    }
+
+    fn leer_empacado(&self, hash: &str) -> Result<(Tipo, Vec<u8>)> {
+        match self.buscar_en_paquetes(hash)? {
+            Some((paquete, offset)) => paquete.leer(offset, self),
+            None => anyhow::bail!("No se encontró el objeto {} en {}", hash, self.directorio.display()),
+        }
+    }
+
+    fn buscar_en_paquetes(&self, hash: &str) -> Result<Option<(&Paquete, u64)>> {
+        let mut hash_bytes = [0u8; 20];
+        hex::decode_to_slice(hash, &mut hash_bytes)?;
+        Ok(self
+            .paquetes()?
+            .iter()
+            .find_map(|paquete| paquete.buscar(&hash_bytes).map(|offset| (paquete, offset))))
+    }
+
+    /// Los paquetes de `objects/pack`, tal como estaban la primera vez que se consultaron
+    pub(crate) fn paquetes(&self) -> Result<&Vec<Paquete>> {
+        if let Some(paquetes) = self.paquetes.get() {
+            return Ok(paquetes);
+        }
+
+        let mut paquetes = Vec::new();
+        let directorio = self.directorio.join("pack");
+        if directorio.is_dir() {
+            let mut rutas: Vec<PathBuf> = fs::read_dir(&directorio)?
+                .map(|entrada| entrada.map(|e| e.path()))
+                .collect::<Result<_, _>>()?;
+            rutas.sort();
+            for ruta in rutas {
+                // Un .idx sin su .pack es un paquete a medio escribir o ya borrado
+                if ruta.extension().is_some_and(|ext| ext == "idx") && ruta.with_extension("pack").is_file() {
+                    paquetes.push(Paquete::abrir(&ruta)?);
+                }
+            }
+        }
+        Ok(self.paquetes.get_or_init(|| paquetes))
+    }
+}
+
+/// Hash SHA-1 de un objeto: se calcula sobre `<tipo> <tamaño>\0<contenido>`
+pub(crate) fn calcular_hash(tipo: Tipo, contenido: &[u8]) -> String {
+    hex::encode(calcular_hash_bytes(tipo, contenido))
+}
+
+pub(crate) fn calcular_hash_bytes(tipo: Tipo, contenido: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{} {}\0", tipo, contenido.len()));
+    hasher.update(contenido);
+    hasher.finalize().into()
+}
+
+// Los objetos y los paquetes no se modifican nunca: como git, se dejan de solo lectura
+pub(crate) fn escribir_renombrando(directorio: &Path, ruta: &Path, datos: &[u8]) -> Result<()> {
+    let (ruta_temporal, mut archivo) = crear_temporal(directorio)?;
+    let resultado = (|| -> Result<()> {
+        archivo.write_all(datos)?;
+        archivo.sync_all()?;
+        fs::set_permissions(&ruta_temporal, fs::Permissions::from_mode(0o444))?;
+        fs::rename(&ruta_temporal, ruta)?;
+        Ok(())
+    })();
+    if resultado.is_err() {
+        let _ = fs::remove_file(&ruta_temporal);
+    }
+    resultado
+}
+
+// `tmp_obj_<pid>_<n>` creado en exclusiva, para que dos procesos nunca compartan el temporal
+pub(crate) fn crear_temporal(directorio: &Path) -> Result<(PathBuf, File)> {
+    for intento in 0.. {
+        let ruta = directorio.join(format!("tmp_obj_{}_{}", std::process::id(), intento));
+        match OpenOptions::new().write(true).create_new(true).open(&ruta) {
+            Ok(archivo) => return Ok((ruta, archivo)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).with_context(|| format!("No se pudo crear {}", ruta.display())),
+        }
+    }
+    unreachable!("el bucle solo termina devolviendo")
+}
+
+/// Comprueba que un texto sea un hash completo: 40 cifras hexadecimales
+pub(crate) fn validar_hash(hash: &str) -> Result<()> {
+    if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("'{hash}' no es un hash de objeto válido");
+    }
+    Ok(())
+}
+
+fn leer_suelto(ruta: &Path) -> Result<Objeto<Box<dyn BufRead>>> {
+    let f = File::open(ruta).context("abrir en .git/objects")?;
+    let z = ZlibDecoder::new(f);
+    let mut z = BufReader::new(z);
+    let (tipo, tamaño) = leer_cabecera_suelto(&mut z)?;
+    // NOTA: esto no dará error si el archivo descomprimido es demasiado largo, pero al menos
+    // no spameará stdout y será vulnerable a un zipbomb. `verificar_suelto` sí lo comprueba.
+    let z = z.take(tamaño);
+    Ok(Objeto {
+        tipo,
+        tamaño_esperado: tamaño,
+        lector: Box::new(z),
+    })
+}
+
+fn leer_cabecera_suelto(z: &mut impl BufRead) -> Result<(Tipo, u64)> {
+    let mut buf = Vec::new();
+    // La cabecera más larga posible ("commit " y un u64) cabe de sobra en 32 bytes
+    z.take(32).read_until(0, &mut buf)
+        .context("leer cabecera desde .git/objects")?;
+    let cabecera = CStr::from_bytes_with_nul(&buf)
+        .context("la cabecera del archivo .git/objects no termina en NUL")?;
+    let cabecera = cabecera
+        .to_str()
+        .context("la cabecera del archivo .git/objects no es UTF-8 válido")?;
+    let Some((tipo, tamaño)) = cabecera.split_once(' ') else {
+        anyhow::bail!("La cabecera del archivo .git/objects no comenzó con un tipo conocido: '{cabecera}'");
+    };
+    let tipo = Tipo::desde_nombre(tipo)?;
+    let tamaño = tamaño
+        .parse::<u64>()
+        .with_context(|| format!("La cabecera del archivo .git/objects tiene un tamaño inválido: {tamaño}"))?;
+    Ok((tipo, tamaño))
+}
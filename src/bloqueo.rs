@@ -0,0 +1,79 @@
+use anyhow::{Context, Result};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// Escritura con el protocolo de lockfiles de git: se crea `<ruta>.lock` en exclusiva, el
+/// contenido nuevo se escribe ahí y al confirmar se renombra sobre `<ruta>`. Si el proceso muere
+/// a mitad, el archivo original queda intacto; si otro proceso tiene el lock, se falla sin tocar nada.
+pub(crate) struct Bloqueo {
+    ruta: PathBuf,
+    ruta_lock: PathBuf,
+    // None una vez confirmado
+    archivo: Option<File>,
+}
+
+impl Bloqueo {
+    pub(crate) fn adquirir(ruta: &Path) -> Result<Bloqueo> {
+        let mut nombre_lock = ruta.as_os_str().to_owned();
+        nombre_lock.push(".lock");
+        let ruta_lock = PathBuf::from(nombre_lock);
+
+        if let Some(padre) = ruta.parent() {
+            fs::create_dir_all(padre)?;
+        }
+        let archivo = match OpenOptions::new().write(true).create_new(true).open(&ruta_lock) {
+            Ok(archivo) => archivo,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => anyhow::bail!(
+                "No se pudo crear '{}': el archivo ya existe.\n\
+                 Parece que otro proceso está modificando este repositorio. Si no es así, \
+                 un proceso anterior terminó de forma inesperada: borra el archivo y vuelve a intentarlo.",
+                ruta_lock.display()
+            ),
+            Err(e) => {
+                return Err(e).with_context(|| format!("No se pudo crear '{}'", ruta_lock.display()))
+            }
+        };
+
+        Ok(Bloqueo {
+            ruta: ruta.to_path_buf(),
+            ruta_lock,
+            archivo: Some(archivo),
+        })
+    }
+
+    pub(crate) fn escribir(&mut self, datos: &[u8]) -> Result<()> {
+        let archivo = self.archivo.as_mut().expect("el bloqueo no se ha confirmado");
+        archivo
+            .write_all(datos)
+            .with_context(|| format!("No se pudo escribir '{}'", self.ruta_lock.display()))
+    }
+
+    /// Reemplaza el archivo original por el contenido escrito
+    pub(crate) fn confirmar(mut self) -> Result<()> {
+        let archivo = self.archivo.take().expect("el bloqueo no se ha confirmado");
+        archivo.sync_all()?;
+        drop(archivo);
+        if let Err(e) = fs::rename(&self.ruta_lock, &self.ruta) {
+            let _ = fs::remove_file(&self.ruta_lock);
+            return Err(e).with_context(|| format!("No se pudo actualizar '{}'", self.ruta.display()));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Bloqueo {
+    // Un bloqueo que no se confirmó (por un error o un `?`) se descarta sin tocar el original
+    fn drop(&mut self) {
+        if self.archivo.take().is_some() {
+            let _ = fs::remove_file(&self.ruta_lock);
+        }
+    }
+}
+
+/// Reemplaza el contenido de un archivo de forma atómica usando un `.lock`
+pub(crate) fn escribir_atomico(ruta: &Path, datos: &[u8]) -> Result<()> {
+    let mut bloqueo = Bloqueo::adquirir(ruta)?;
+    bloqueo.escribir(datos)?;
+    bloqueo.confirmar()
+}
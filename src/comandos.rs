@@ -4,4 +4,17 @@ pub mod listar_arbol;
 pub mod leer_arbol;
 pub mod escribir_arbol;
 pub mod commit_arbol;
-pub mod clonar; 
\ No newline at end of file
+pub mod agregar;
+pub mod estado;
+pub mod registro;
+pub mod rama;
+pub mod cambiar;
+pub mod etiqueta;
+pub mod clonar;
+pub mod traer;
+pub mod empujar;
+pub mod empaquetar_objetos;
+pub mod recolectar;
+pub mod verificar;
+pub mod configuracion;
+pub mod confirmar;
\ No newline at end of file
@@ -0,0 +1,183 @@
+use crate::base_objetos::BaseObjetos;
+use crate::configuracion::Configuracion;
+use crate::ignorar::Ignorados;
+use crate::indice::{EntradaIndice, Indice};
+use crate::objetos::Tipo;
+use anyhow::{Context, Result};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Con `forzar` también se agregan los archivos ignorados
+pub fn ejecutar(rutas: &[PathBuf], forzar: bool) -> Result<()> {
+    let ruta_indice = Path::new(".git/index");
+    let (mut indice, bloqueo) = Indice::leer_bloqueado(ruta_indice)?;
+    let base = BaseObjetos::abrir(".git");
+    let mut ignorados = Ignorados::cargar()?;
+    let confiar_en_ejecutable = Configuracion::cargar()?.obtener_bool("core.fileMode")?.unwrap_or(true);
+    // Lo que ya está en el índice se actualiza aunque coincida con un patrón de .gitignore
+    let rastreados: BTreeSet<String> = indice.entradas.iter().map(|e| e.ruta.clone()).collect();
+    let mut rutas_ignoradas = Vec::new();
+
+    for ruta in rutas {
+        let ruta = normalizar_ruta(ruta)?;
+        let ruta_fs = if ruta.is_empty() { PathBuf::from(".") } else { PathBuf::from(&ruta) };
+
+        // Nombrar explícitamente una ruta ignorada es un error, salvo con --force
+        let metadata = fs::symlink_metadata(&ruta_fs);
+        let es_directorio = metadata.as_ref().is_ok_and(|m| m.is_dir());
+        if !forzar
+            && !ruta.is_empty()
+            && !contiene_rastreados(&rastreados, &ruta)
+            && ignorados.ignorado(&ruta, es_directorio)
+        {
+            rutas_ignoradas.push(ruta);
+            continue;
+        }
+
+        match metadata {
+            Ok(metadata) if metadata.is_dir() => {
+                // Quitar del índice lo que ya no existe dentro del directorio
+                let prefijo = if ruta.is_empty() { String::new() } else { format!("{}/", ruta) };
+                let borrados: Vec<String> = indice
+                    .entradas
+                    .iter()
+                    .filter(|e| e.ruta.starts_with(&prefijo))
+                    .filter(|e| fs::symlink_metadata(&e.ruta).is_err())
+                    .map(|e| e.ruta.clone())
+                    .collect();
+                for borrado in borrados {
+                    indice.eliminar(&borrado);
+                }
+
+                let filtro = if forzar { None } else { Some(&mut ignorados) };
+                agregar_directorio(&base, &mut indice, &ruta_fs, filtro, &rastreados, confiar_en_ejecutable)?;
+            }
+            Ok(metadata) => agregar_archivo(&base, &mut indice, &ruta, &metadata, confiar_en_ejecutable)?,
+            Err(_) => {
+                // Un archivo borrado del directorio de trabajo se elimina del índice
+                let prefijo = format!("{}/", ruta);
+                let antes = indice.entradas.len();
+                indice
+                    .entradas
+                    .retain(|e| e.ruta != ruta && !e.ruta.starts_with(&prefijo));
+                if indice.entradas.len() == antes {
+                    anyhow::bail!("la ruta '{}' no coincide con ningún archivo", ruta);
+                }
+            }
+        }
+    }
+
+    indice.escribir_bloqueado(bloqueo)?;
+    if !rutas_ignoradas.is_empty() {
+        anyhow::bail!(
+            "Las siguientes rutas están ignoradas por algún archivo .gitignore:\n\t{}\nUsa --force si de verdad quieres agregarlas.",
+            rutas_ignoradas.join("\n\t")
+        );
+    }
+    Ok(())
+}
+
+// Sin `ignorados` (con --force) se agrega todo lo que hay en el directorio
+fn agregar_directorio(
+    base: &BaseObjetos,
+    indice: &mut Indice,
+    directorio: &Path,
+    mut ignorados: Option<&mut Ignorados>,
+    rastreados: &BTreeSet<String>,
+    confiar_en_ejecutable: bool,
+) -> Result<()> {
+    for entrada in fs::read_dir(directorio)? {
+        let entrada = entrada?;
+        // Evita procesar cualquier cosa dentro de .git/
+        if entrada.file_name() == ".git" {
+            continue;
+        }
+
+        let ruta_absoluta = entrada.path();
+        let ruta = ruta_absoluta
+            .strip_prefix(".")
+            .unwrap_or(&ruta_absoluta)
+            .to_str()
+            .context("la ruta no es UTF-8 válido")?
+            .to_string();
+        let metadata = fs::symlink_metadata(&ruta_absoluta)?;
+        if let Some(ignorados) = ignorados.as_deref_mut() {
+            if !contiene_rastreados(rastreados, &ruta) && ignorados.ignorado(&ruta, metadata.is_dir()) {
+                continue;
+            }
+        }
+
+        if metadata.is_dir() {
+            agregar_directorio(
+                base,
+                indice,
+                &ruta_absoluta,
+                ignorados.as_deref_mut(),
+                rastreados,
+                confiar_en_ejecutable,
+            )?;
+        } else {
+            agregar_archivo(base, indice, &ruta, &metadata, confiar_en_ejecutable)?;
+        }
+    }
+    Ok(())
+}
+
+// Si la ruta es un archivo rastreado o un directorio con algún archivo rastreado dentro
+fn contiene_rastreados(rastreados: &BTreeSet<String>, ruta: &str) -> bool {
+    let prefijo = format!("{}/", ruta);
+    rastreados.contains(ruta)
+        || rastreados
+            .range(prefijo.clone()..)
+            .next()
+            .is_some_and(|r| r.starts_with(&prefijo))
+}
+
+fn agregar_archivo(
+    base: &BaseObjetos,
+    indice: &mut Indice,
+    ruta: &str,
+    metadata: &fs::Metadata,
+    confiar_en_ejecutable: bool,
+) -> Result<()> {
+    // Los enlaces simbólicos se guardan como un blob con el destino del enlace
+    let contenido = if metadata.file_type().is_symlink() {
+        fs::read_link(ruta)?.to_string_lossy().into_owned().into_bytes()
+    } else {
+        fs::read(ruta).with_context(|| format!("No se pudo leer {}", ruta))?
+    };
+
+    let hash = base.escribir(Tipo::Blob, &contenido)?;
+    let mut hash_bytes = [0u8; 20];
+    hex::decode_to_slice(&hash, &mut hash_bytes)?;
+    let mut entrada = EntradaIndice::desde_metadata(ruta, metadata, hash_bytes);
+    if let Some(anterior) = indice.buscar(ruta).filter(|_| !confiar_en_ejecutable) {
+        entrada.conservar_modo_ejecutable(anterior.modo);
+    }
+    indice.agregar(entrada);
+    Ok(())
+}
+
+// Convierte la ruta recibida en una ruta relativa a la raíz del repositorio, con '/' como separador
+fn normalizar_ruta(ruta: &Path) -> Result<String> {
+    let mut partes: Vec<String> = Vec::new();
+    for componente in ruta.components() {
+        match componente {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                if partes.pop().is_none() {
+                    anyhow::bail!("'{}' está fuera del repositorio", ruta.display());
+                }
+            }
+            std::path::Component::Normal(parte) => partes.push(
+                parte
+                    .to_str()
+                    .context("la ruta no es UTF-8 válido")?
+                    .to_string(),
+            ),
+            _ => anyhow::bail!("'{}' está fuera del repositorio", ruta.display()),
+        }
+    }
+    Ok(partes.join("/"))
+}
@@ -0,0 +1,286 @@
+use crate::comandos::estado::{aplanar_arbol, hash_archivo};
+use crate::ignorar::Ignorados;
+use crate::indice::{EntradaIndice, Indice};
+use crate::objetos::{Commit, Objeto};
+use crate::referencias::{self, Head};
+use crate::revision;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+// ruta -> (modo, hash)
+type Archivos = BTreeMap<String, (String, String)>;
+
+pub fn ejecutar(objetivo: Option<&str>, crear: Option<&str>, separar: bool, forzar: bool) -> Result<()> {
+    // El índice queda bloqueado desde antes de mirar HEAD hasta que se escribe el nuevo, para que
+    // nadie lo cambie entre la comprobación de cambios locales y la escritura
+    let ruta_indice = Path::new(".git/index");
+    let (indice, bloqueo_indice) = Indice::leer_bloqueado(ruta_indice)?;
+
+    // Decidir a qué commit vamos y qué debe quedar en HEAD
+    let (hash_destino, nuevo_head) = match crear {
+        Some(nombre) => {
+            referencias::validar_nombre_rama(nombre)?;
+            let nombre_ref = format!("refs/heads/{}", nombre);
+            if referencias::resolver_referencia(&nombre_ref)?.is_some() {
+                anyhow::bail!("Ya existe una rama llamada '{}'", nombre);
+            }
+            let hash = revision::resolver_commit(objetivo.unwrap_or("HEAD"))?;
+            (hash, Head::Rama(nombre_ref))
+        }
+        None => {
+            let objetivo = objetivo.context("Indica la rama o el commit al que cambiar")?;
+            let nombre_ref = format!("refs/heads/{}", objetivo);
+            match referencias::resolver_referencia(&nombre_ref)? {
+                Some(hash) if !separar => (hash, Head::Rama(nombre_ref)),
+                _ => {
+                    let hash = revision::resolver_commit(objetivo)?;
+                    (hash.clone(), Head::Separado(hash))
+                }
+            }
+        }
+    };
+
+    let mut actual = Archivos::new();
+    if let Some(hash_actual) = referencias::resolver_head()? {
+        aplanar_arbol(&Commit::leer(&hash_actual)?.arbol, "", &mut actual)?;
+    }
+    let mut destino = Archivos::new();
+    aplanar_arbol(&Commit::leer(&hash_destino)?.arbol, "", &mut destino)?;
+
+    // Rutas que cambian entre el árbol actual y el de destino
+    let cambiadas: BTreeSet<String> = actual
+        .keys()
+        .chain(destino.keys())
+        .filter(|ruta| actual.get(*ruta) != destino.get(*ruta))
+        .cloned()
+        .collect();
+
+    if !forzar {
+        verificar_cambios_locales(&cambiadas, &actual, &destino, &indice, ruta_indice)?;
+    }
+
+    // Con --force se descarta todo lo local: se reescribe cada archivo del destino
+    let a_borrar: Vec<&String> = actual.keys().filter(|ruta| !destino.contains_key(*ruta)).collect();
+    let a_escribir: Vec<&String> = if forzar {
+        destino.keys().collect()
+    } else {
+        destino.keys().filter(|ruta| cambiadas.contains(*ruta)).collect()
+    };
+
+    for ruta in &a_borrar {
+        borrar_archivo(ruta)?;
+    }
+
+    let mut nuevo_indice = if forzar { Indice::default() } else { indice };
+    for ruta in &a_borrar {
+        nuevo_indice.eliminar(ruta);
+    }
+    for ruta in &a_escribir {
+        let (modo, hash) = &destino[*ruta];
+        escribir_archivo(ruta, modo, hash)?;
+    }
+
+    // Las entradas toman los metadatos de los archivos recién escritos
+    for ruta in &a_escribir {
+        let (_, hash) = &destino[*ruta];
+        let metadata = fs::symlink_metadata(ruta)?;
+        let mut hash_bytes = [0u8; 20];
+        hex::decode_to_slice(hash, &mut hash_bytes)?;
+        let mut entrada = EntradaIndice::desde_metadata(ruta, &metadata, hash_bytes);
+        // Los submódulos no tienen contenido en el directorio de trabajo
+        if destino[*ruta].0 == "160000" {
+            entrada.modo = 0o160000;
+        }
+        nuevo_indice.agregar(entrada);
+    }
+    nuevo_indice.escribir_bloqueado(bloqueo_indice)?;
+
+    match &nuevo_head {
+        Head::Rama(nombre_ref) => {
+            if crear.is_some() {
+                // Falla si alguien ha creado la rama después de comprobar que no existía
+                referencias::actualizar_referencia(nombre_ref, &hash_destino, None)?;
+            }
+            referencias::escribir_head(&nuevo_head)?;
+            println!("Cambiado a la rama '{}'", nombre_ref.trim_start_matches("refs/heads/"));
+        }
+        Head::Separado(hash) => {
+            referencias::escribir_head(&nuevo_head)?;
+            let titulo = Commit::leer(hash)?.mensaje.lines().next().unwrap_or("").to_string();
+            println!("HEAD ahora está en {} {}", &hash[..7], titulo);
+        }
+    }
+
+    Ok(())
+}
+
+// Un cambio local es seguro si coincide con el árbol actual o ya coincide con el de destino
+fn verificar_cambios_locales(
+    cambiadas: &BTreeSet<String>,
+    actual: &Archivos,
+    destino: &Archivos,
+    indice: &Indice,
+    ruta_indice: &Path,
+) -> Result<()> {
+    let mtime_indice = Indice::mtime(ruta_indice);
+    let entradas: BTreeMap<&str, &EntradaIndice> =
+        indice.entradas.iter().map(|e| (e.ruta.as_str(), e)).collect();
+    let mut en_conflicto = Vec::new();
+    let mut ignorados = Ignorados::cargar()?;
+
+    for ruta in cambiadas {
+        let en_actual = actual.get(ruta).map(|(_, hash)| hash.as_str());
+        let en_destino = destino.get(ruta).map(|(_, hash)| hash.as_str());
+        let entrada = entradas.get(ruta.as_str());
+
+        // Estado del índice
+        let en_indice = entrada.map(|e| hex::encode(e.hash));
+        if en_indice.as_deref() != en_actual && en_indice.as_deref() != en_destino {
+            en_conflicto.push(ruta.clone());
+            continue;
+        }
+
+        // Un archivo sin seguimiento que está ignorado se sobrescribe sin preguntar, como en git
+        if en_actual.is_none() && entrada.is_none() && ignorados.ignorado(ruta, false) {
+            continue;
+        }
+
+        // Estado del directorio de trabajo
+        let en_trabajo = match fs::symlink_metadata(ruta) {
+            Ok(metadata) if metadata.is_dir() => None,
+            Ok(metadata) => match entrada {
+                Some(entrada) if !entrada.necesita_rehash(&metadata, mtime_indice) => {
+                    Some(hex::encode(entrada.hash))
+                }
+                _ => Some(hex::encode(hash_archivo(ruta, &metadata)?)),
+            },
+            Err(_) => None,
+        };
+        if en_trabajo.as_deref() != en_actual && en_trabajo.as_deref() != en_destino {
+            en_conflicto.push(ruta.clone());
+            continue;
+        }
+
+        // Un directorio no puede ocupar el lugar de un archivo del destino si guarda algo que no
+        // se vaya a borrar ya (los archivos del árbol actual) ni esté ignorado
+        if en_destino.is_some()
+            && destino[ruta].0 != "160000"
+            && fs::symlink_metadata(ruta).is_ok_and(|m| m.is_dir())
+        {
+            sin_seguimiento(ruta, actual, &mut ignorados, &mut en_conflicto)?;
+        }
+
+        // Un archivo sin seguimiento no puede ocupar el lugar de un directorio del destino
+        if en_destino.is_some() {
+            for ancestro in Path::new(ruta).ancestors().skip(1) {
+                let nombre = ancestro.to_string_lossy();
+                if !nombre.is_empty()
+                    && !actual.contains_key(nombre.as_ref())
+                    && fs::symlink_metadata(ancestro).is_ok_and(|m| !m.is_dir())
+                    && !ignorados.ignorado(&nombre, false)
+                {
+                    en_conflicto.push(nombre.into_owned());
+                }
+            }
+        }
+    }
+
+    en_conflicto.sort();
+    en_conflicto.dedup();
+    if !en_conflicto.is_empty() {
+        let mut mensaje =
+            "Los cambios locales en los siguientes archivos se sobrescribirían al cambiar:".to_string();
+        for ruta in &en_conflicto {
+            mensaje.push_str(&format!("\n\t{}", ruta));
+        }
+        mensaje.push_str("\nConfirma o descarta tus cambios antes de cambiar de rama.");
+        anyhow::bail!(mensaje);
+    }
+    Ok(())
+}
+
+// Los archivos de un directorio que no son del árbol actual ni están ignorados
+fn sin_seguimiento(
+    directorio: &str,
+    actual: &Archivos,
+    ignorados: &mut Ignorados,
+    encontrados: &mut Vec<String>,
+) -> Result<()> {
+    for entrada in fs::read_dir(directorio)? {
+        let entrada = entrada?;
+        let ruta = format!("{}/{}", directorio, entrada.file_name().to_string_lossy());
+        let es_directorio = entrada.file_type()?.is_dir();
+        if actual.contains_key(&ruta) || ignorados.ignorado(&ruta, es_directorio) {
+            continue;
+        }
+        if es_directorio {
+            sin_seguimiento(&ruta, actual, ignorados, encontrados)?;
+        } else {
+            encontrados.push(ruta);
+        }
+    }
+    Ok(())
+}
+
+fn borrar_archivo(ruta: &str) -> Result<()> {
+    match fs::remove_file(ruta) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("No se pudo borrar {}", ruta)),
+    }
+
+    // Borrar los directorios que quedaron vacíos
+    let mut directorio = Path::new(ruta).parent();
+    while let Some(actual) = directorio {
+        if actual.as_os_str().is_empty() || fs::remove_dir(actual).is_err() {
+            break;
+        }
+        directorio = actual.parent();
+    }
+    Ok(())
+}
+
+fn escribir_archivo(ruta: &str, modo: &str, hash: &str) -> Result<()> {
+    let ruta = Path::new(ruta);
+    if let Some(padre) = ruta.parent() {
+        // Con --force un archivo puede estar ocupando el lugar de un directorio
+        for ancestro in padre.ancestors() {
+            if fs::symlink_metadata(ancestro).is_ok_and(|m| !m.is_dir()) {
+                fs::remove_file(ancestro)?;
+            }
+        }
+        fs::create_dir_all(padre)?;
+    }
+
+    // Los submódulos se representan con un directorio vacío
+    if modo == "160000" {
+        fs::create_dir_all(ruta)?;
+        return Ok(());
+    }
+
+    let mut objeto = Objeto::leer(hash)?;
+    let mut contenido = Vec::new();
+    objeto.lector.read_to_end(&mut contenido)?;
+
+    // Quitar lo que haya en la ruta (un enlace no se puede sobrescribir con fs::write). Un
+    // directorio en su lugar ya solo guarda archivos ignorados, o lo que sea con --force
+    match fs::symlink_metadata(ruta) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(ruta)?,
+        Ok(_) => fs::remove_file(ruta)?,
+        Err(_) => {}
+    }
+
+    if modo == "120000" {
+        let destino = String::from_utf8_lossy(&contenido).into_owned();
+        std::os::unix::fs::symlink(destino, ruta)?;
+    } else {
+        fs::write(ruta, &contenido)?;
+        let permisos = if modo == "100755" { 0o755 } else { 0o644 };
+        fs::set_permissions(ruta, fs::Permissions::from_mode(permisos))?;
+    }
+    Ok(())
+}
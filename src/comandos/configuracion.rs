@@ -0,0 +1,75 @@
+use crate::configuracion::{self, Ambito, Configuracion};
+use anyhow::{Context, Result};
+
+/// Qué hacer con la configuración (las opciones de `git config`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Accion {
+    /// <nombre> o --get: el último valor
+    Obtener,
+    /// --get-all: todos los valores
+    ObtenerTodos,
+    /// -l: todas las variables
+    Listar,
+    /// <nombre> <valor>: reemplaza el valor
+    Asignar,
+    /// --add: añade un valor más
+    Agregar,
+    /// --unset
+    Quitar,
+    /// --unset-all
+    QuitarTodos,
+}
+
+// Sin ámbito se lee la configuración combinada y se escribe en la del repositorio
+pub fn ejecutar(accion: Accion, ambito: Option<Ambito>, nombre: Option<&str>, valor: Option<&str>) -> Result<()> {
+    if accion == Accion::Listar {
+        let configuracion = cargar(ambito)?;
+        for entrada in configuracion.entradas() {
+            match &entrada.valor {
+                Some(valor) => println!("{}={}", entrada.nombre, valor),
+                None => println!("{}", entrada.nombre),
+            }
+        }
+        return Ok(());
+    }
+
+    let nombre = nombre.context("Falta el nombre de la variable")?;
+    let destino = ambito.unwrap_or(Ambito::Local);
+    match accion {
+        Accion::Obtener | Accion::ObtenerTodos => {
+            configuracion::normalizar_nombre(nombre)?;
+            let configuracion = cargar(ambito)?;
+            let valores = if accion == Accion::Obtener {
+                configuracion.obtener(nombre).into_iter().collect()
+            } else {
+                configuracion.obtener_todos(nombre)
+            };
+            // Como git, una variable que no existe solo se nota en el código de salida
+            if valores.is_empty() {
+                std::process::exit(1);
+            }
+            for valor in valores {
+                println!("{}", valor);
+            }
+        }
+        Accion::Asignar | Accion::Agregar => {
+            let valor = valor.context("Falta el valor")?;
+            configuracion::asignar(destino, nombre, valor, accion == Accion::Agregar)?;
+        }
+        Accion::Quitar | Accion::QuitarTodos => {
+            if configuracion::quitar(destino, nombre, accion == Accion::QuitarTodos)? == 0 {
+                // El mismo código que usa git cuando no hay nada que quitar
+                std::process::exit(5);
+            }
+        }
+        Accion::Listar => unreachable!("se trata antes"),
+    }
+    Ok(())
+}
+
+fn cargar(ambito: Option<Ambito>) -> Result<Configuracion> {
+    match ambito {
+        Some(ambito) => Configuracion::cargar_ambito(ambito),
+        None => Configuracion::cargar(),
+    }
+}
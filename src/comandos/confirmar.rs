@@ -0,0 +1,324 @@
+use crate::comandos::escribir_arbol;
+use crate::comandos::estado::aplanar_arbol;
+use crate::commit::{self, DatosCommit, Rol};
+use crate::configuracion::Configuracion;
+use crate::indice::Indice;
+use crate::objetos::{Commit, Objeto};
+use crate::referencias::{self, Head};
+use anyhow::{bail, Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+
+/// Un archivo que cambia entre el padre y el nuevo commit, con su (modo, hash) a cada lado
+struct Cambio {
+    ruta: String,
+    antes: Option<(String, String)>,
+    despues: Option<(String, String)>,
+}
+
+pub fn ejecutar(
+    mensajes: &[String],
+    permitir_vacio: bool,
+    enmendar: bool,
+    autor: Option<&str>,
+    fecha: Option<&str>,
+) -> Result<()> {
+    // Como `git commit`, el índice queda bloqueado hasta terminar (también mientras se edita el
+    // mensaje), así que lo que se confirma es exactamente lo que se leyó
+    let ruta_indice = Path::new(".git/index");
+    let (indice, _bloqueo_indice) = Indice::leer_bloqueado(ruta_indice)?;
+    let head = referencias::leer_head()?;
+    let hash_head = referencias::resolver_head()?;
+    // Sin índice, el árbol del commit quedaría vacío: sería borrar todo lo de HEAD sin quererlo
+    if hash_head.is_some() && !ruta_indice.exists() {
+        bail!("Falta .git/index aunque ya hay commits; confirmar ahora borraría todos los archivos.\nVuelve a preparar los archivos con 'agregar .'");
+    }
+    let hash_arbol = escribir_arbol::escribir_arbol_indice(&indice)?;
+
+    // Con --amend el commit nuevo ocupa el lugar de HEAD: hereda sus padres, su autor y, si no
+    // se da otro, su mensaje
+    let anterior = match (enmendar, &hash_head) {
+        (true, Some(hash)) => Some(Commit::leer(hash)?),
+        (true, None) => bail!("No hay ningún commit que enmendar"),
+        (false, _) => None,
+    };
+    let padres = match &anterior {
+        Some(anterior) => anterior.padres.clone(),
+        None => hash_head.iter().cloned().collect(),
+    };
+
+    let cambios = cambios(padres.first(), &indice)?;
+    // Una fusión nunca está vacía, aunque su árbol sea el del primer padre
+    if cambios.is_empty() && padres.len() <= 1 && !permitir_vacio {
+        if enmendar {
+            bail!("El commit enmendado quedaría vacío; usa --allow-empty si es lo que quieres");
+        }
+        bail!("Nada para confirmar (prepara cambios con agregar o usa --allow-empty)");
+    }
+
+    let firma_autor = match (&anterior, autor, fecha) {
+        (Some(anterior), None, None) => anterior.autor.clone(),
+        // Lo que no se indique se conserva del commit enmendado
+        (Some(anterior), autor, fecha) => {
+            let identidad = format!("{} <{}>", anterior.autor.nombre, anterior.autor.email);
+            let fecha_anterior = format!("{} {}", anterior.autor.timestamp, anterior.autor.zona_horaria);
+            commit::firma(Rol::Autor, Some(autor.unwrap_or(&identidad)), Some(fecha.unwrap_or(&fecha_anterior)))?
+        }
+        (None, autor, fecha) => commit::firma(Rol::Autor, autor, fecha)?,
+    };
+    let committer = commit::firma(Rol::Committer, None, None)?;
+
+    let mensaje = if mensajes.is_empty() {
+        let inicial = anterior.as_ref().map_or("", |anterior| anterior.mensaje.as_str());
+        editar_mensaje(inicial, &head, padres.is_empty(), &cambios)?
+    } else {
+        limpiar_mensaje(&mensajes.join("\n\n"), false)
+    };
+    if mensaje.is_empty() {
+        bail!("Se cancela el commit porque el mensaje está vacío");
+    }
+
+    let datos = DatosCommit {
+        hash_arbol,
+        padres,
+        autor: firma_autor,
+        committer,
+        mensaje,
+    };
+    let hash = commit::crear_commit(&datos)?;
+    // Si otro proceso movió la rama mientras tanto, el commit no se pierde pero no se publica
+    commit::actualizar_head(&hash, hash_head.as_deref())?;
+
+    // Como git, la fecha se muestra cuando no es la de ahora
+    mostrar_resumen(&hash, &head, &datos, enmendar || fecha.is_some(), &cambios)
+}
+
+// Los archivos que cambian entre el árbol del padre (o nada, en el primer commit) y el índice
+fn cambios(padre: Option<&String>, indice: &Indice) -> Result<Vec<Cambio>> {
+    let mut antes = BTreeMap::new();
+    if let Some(padre) = padre {
+        aplanar_arbol(&Commit::leer(padre)?.arbol, "", &mut antes)?;
+    }
+    let despues: BTreeMap<String, (String, String)> = indice
+        .entradas
+        .iter()
+        .map(|entrada| (entrada.ruta.clone(), (format!("{:o}", entrada.modo), hex::encode(entrada.hash))))
+        .collect();
+
+    let rutas: BTreeSet<&String> = antes.keys().chain(despues.keys()).collect();
+    Ok(rutas
+        .into_iter()
+        .filter(|ruta| antes.get(*ruta) != despues.get(*ruta))
+        .map(|ruta| Cambio {
+            ruta: ruta.clone(),
+            antes: antes.get(ruta).cloned(),
+            despues: despues.get(ruta).cloned(),
+        })
+        .collect())
+}
+
+// Escribe la plantilla en .git/COMMIT_EDITMSG, abre el editor y devuelve el mensaje sin los
+// comentarios
+fn editar_mensaje(inicial: &str, head: &Head, primero: bool, cambios: &[Cambio]) -> Result<String> {
+    let mut plantilla = String::from(inicial);
+    plantilla.push_str("\n# Escribe el mensaje del commit. Las líneas que empiezan con '#' se ignoran\n");
+    plantilla.push_str("# y un mensaje vacío cancela el commit.\n#\n");
+    match head {
+        Head::Rama(nombre_ref) => {
+            plantilla.push_str(&format!("# En la rama {}\n", nombre_ref.trim_start_matches("refs/heads/")))
+        }
+        Head::Separado(hash) => plantilla.push_str(&format!("# HEAD desacoplado en {}\n", &hash[..7.min(hash.len())])),
+    }
+    if primero {
+        plantilla.push_str("#\n# Commit inicial\n");
+    }
+    if !cambios.is_empty() {
+        plantilla.push_str("#\n# Cambios a confirmar:\n");
+        for cambio in cambios {
+            let estado = match (&cambio.antes, &cambio.despues) {
+                (None, _) => "nuevo archivo",
+                (_, None) => "borrado",
+                _ => "modificado",
+            };
+            plantilla.push_str(&format!("#\t{:<16}{}\n", format!("{}:", estado), cambio.ruta));
+        }
+    }
+    plantilla.push_str("#\n");
+
+    let ruta = Path::new(".git/COMMIT_EDITMSG");
+    fs::write(ruta, plantilla)?;
+    abrir_editor(ruta)?;
+    let editado = fs::read(ruta)?;
+    Ok(limpiar_mensaje(&String::from_utf8_lossy(&editado), true))
+}
+
+// El editor es el de GIT_EDITOR, core.editor, VISUAL o EDITOR, o vi. Como en git, se lanza a
+// través de la shell para que pueda llevar argumentos (`code --wait`)
+fn abrir_editor(ruta: &Path) -> Result<()> {
+    let configuracion = Configuracion::cargar()?;
+    let editor = env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| configuracion.obtener("core.editor").map(str::to_string))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .filter(|editor| !editor.is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let estado = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(ruta)
+        .status()
+        .with_context(|| format!("No se pudo abrir el editor '{}'", editor))?;
+    if !estado.success() {
+        bail!("El editor '{}' terminó con un error; se cancela el commit", editor);
+    }
+    Ok(())
+}
+
+/// Limpia un mensaje como git: quita los espacios al final de cada línea, junta varias líneas
+/// en blanco seguidas en una, quita las del principio y el final y, si se pide, las líneas de
+/// comentario. Si queda algo, termina en salto de línea.
+fn limpiar_mensaje(texto: &str, quitar_comentarios: bool) -> String {
+    let mut mensaje = String::new();
+    let mut blancas = 0;
+    for linea in texto.lines() {
+        if quitar_comentarios && linea.starts_with('#') {
+            continue;
+        }
+        let linea = linea.trim_end();
+        if linea.is_empty() {
+            blancas += 1;
+            continue;
+        }
+        if blancas > 0 && !mensaje.is_empty() {
+            mensaje.push('\n');
+        }
+        blancas = 0;
+        mensaje.push_str(linea);
+        mensaje.push('\n');
+    }
+    mensaje
+}
+
+// Como el de git: `[rama abc1234] Título`, el autor si no es quien hace el commit, la fecha si se
+// pide, las estadísticas y los archivos creados, borrados o que cambian de modo
+fn mostrar_resumen(hash: &str, head: &Head, datos: &DatosCommit, mostrar_fecha: bool, cambios: &[Cambio]) -> Result<()> {
+    let donde = match head {
+        Head::Rama(nombre_ref) => nombre_ref.trim_start_matches("refs/heads/").to_string(),
+        Head::Separado(_) => "HEAD desacoplado".to_string(),
+    };
+    let raiz = if datos.padres.is_empty() { " (commit-raíz)" } else { "" };
+    let titulo = datos.mensaje.lines().next().unwrap_or("");
+    println!("[{}{} {}] {}", donde, raiz, &hash[..7], titulo);
+    let (autor, committer) = (&datos.autor, &datos.committer);
+    if (&autor.nombre, &autor.email) != (&committer.nombre, &committer.email) {
+        println!(" Autor: {} <{}>", autor.nombre, autor.email);
+    }
+    if mostrar_fecha {
+        println!(" Fecha: {}", autor.fecha_legible());
+    }
+    if cambios.is_empty() {
+        return Ok(());
+    }
+
+    let (mut inserciones, mut eliminaciones) = (0, 0);
+    for cambio in cambios {
+        let (añadidas, quitadas) = lineas_cambiadas(cambio)?;
+        inserciones += añadidas;
+        eliminaciones += quitadas;
+    }
+    let plural = |n: usize, singular: &str, plural: &str| format!("{} {}", n, if n == 1 { singular } else { plural });
+    let mut estadisticas = format!(" {}", plural(cambios.len(), "archivo cambiado", "archivos cambiados"));
+    if inserciones > 0 || eliminaciones == 0 {
+        estadisticas.push_str(&format!(", {}", plural(inserciones, "inserción(+)", "inserciones(+)")));
+    }
+    if eliminaciones > 0 || inserciones == 0 {
+        estadisticas.push_str(&format!(", {}", plural(eliminaciones, "eliminación(-)", "eliminaciones(-)")));
+    }
+    println!("{}", estadisticas);
+
+    for cambio in cambios {
+        match (&cambio.antes, &cambio.despues) {
+            (None, Some((modo, _))) => println!(" crear modo {:0>6} {}", modo, cambio.ruta),
+            (Some((modo, _)), None) => println!(" borrar modo {:0>6} {}", modo, cambio.ruta),
+            (Some((antes, _)), Some((despues, _))) if antes != despues => {
+                println!(" cambio de modo {:0>6} => {:0>6} {}", antes, despues, cambio.ruta)
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Líneas añadidas y quitadas en un archivo. Los binarios (con algún byte nulo al principio,
+// como decide git) y los submódulos cuentan como cambiados pero sin líneas.
+fn lineas_cambiadas(cambio: &Cambio) -> Result<(usize, usize)> {
+    let leer = |lado: &Option<(String, String)>| -> Result<Option<Vec<u8>>> {
+        match lado {
+            None => Ok(Some(Vec::new())),
+            Some((modo, _)) if modo == "160000" => Ok(None),
+            Some((_, hash)) => {
+                let mut contenido = Vec::new();
+                Objeto::leer(hash)?.lector.read_to_end(&mut contenido)?;
+                Ok(Some(contenido))
+            }
+        }
+    };
+    let (Some(antes), Some(despues)) = (leer(&cambio.antes)?, leer(&cambio.despues)?) else {
+        return Ok((0, 0));
+    };
+    let binario = |contenido: &[u8]| contenido[..contenido.len().min(8000)].contains(&0);
+    if binario(&antes) || binario(&despues) {
+        return Ok((0, 0));
+    }
+
+    let antes: Vec<&[u8]> = antes.split_inclusive(|&c| c == b'\n').collect();
+    let despues: Vec<&[u8]> = despues.split_inclusive(|&c| c == b'\n').collect();
+    // Todo script de edición mínimo conserva las mismas líneas, así que basta con su longitud
+    let distancia = distancia_edicion(&antes, &despues);
+    let comunes = (antes.len() + despues.len() - distancia) / 2;
+    Ok((despues.len() - comunes, antes.len() - comunes))
+}
+
+// Número mínimo de líneas a insertar o borrar para pasar de `a` a `b`, con el algoritmo de
+// Myers: para cada número de ediciones d se guarda, en cada diagonal k = x - y, hasta dónde se
+// llega en `a`, y el primer d que alcanza el final de las dos es la distancia
+fn distancia_edicion(a: &[&[u8]], b: &[&[u8]]) -> usize {
+    // El principio y el final comunes no cuentan y suelen ser casi todo el archivo
+    let prefijo = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefijo..], &b[prefijo..]);
+    let sufijo = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[..a.len() - sufijo], &b[..b.len() - sufijo]);
+    if a.is_empty() || b.is_empty() {
+        return a.len() + b.len();
+    }
+
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let maximo = n + m;
+    let mut v = vec![0isize; 2 * maximo as usize + 2];
+    let indice = |k: isize| (k + maximo) as usize;
+    for d in 0..=maximo {
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[indice(k - 1)] < v[indice(k + 1)]) {
+                v[indice(k + 1)]
+            } else {
+                v[indice(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[indice(k)] = x;
+            if x >= n && y >= m {
+                return d as usize;
+            }
+        }
+    }
+    maximo as usize
+}
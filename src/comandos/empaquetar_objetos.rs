@@ -0,0 +1,32 @@
+use crate::base_objetos::BaseObjetos;
+use crate::empaquetar::{self, ObjetoAEmpaquetar, Opciones};
+use anyhow::Result;
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::Path;
+
+// Cada línea de stdin es `<hash> [<ruta>]`, la salida de `git rev-list --objects`. La ruta es
+// opcional y solo ayuda a elegir bases de delta.
+pub fn ejecutar(prefijo: &Path, opciones: Opciones) -> Result<()> {
+    let base = BaseObjetos::abrir(".git");
+    let mut objetos = Vec::new();
+    let mut vistos = HashSet::new();
+    for linea in std::io::stdin().lock().lines() {
+        let linea = linea?;
+        let (hash, ruta) = linea.split_once(' ').unwrap_or((&linea, ""));
+        if hash.is_empty() || !vistos.insert(hash.to_string()) {
+            continue;
+        }
+        if !base.existe(hash) {
+            anyhow::bail!("No existe el objeto {}", hash);
+        }
+        objetos.push(ObjetoAEmpaquetar {
+            hash: hash.to_string(),
+            ruta: ruta.to_string(),
+        });
+    }
+
+    let checksum = empaquetar::guardar(&objetos, &base, opciones, prefijo)?;
+    println!("{}", hex::encode(checksum));
+    Ok(())
+}
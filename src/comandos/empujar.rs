@@ -0,0 +1,230 @@
+use crate::base_objetos::BaseObjetos;
+use crate::comandos::traer;
+use crate::empaquetar::{self, Opciones};
+use crate::protocolo::{ActualizacionRemota, Receptor};
+use crate::referencias::{self, Head};
+use crate::revision;
+use anyhow::{Context, Result};
+
+// En las órdenes de receive-pack, el hash de una referencia que no existe
+const HASH_NULO: &str = "0000000000000000000000000000000000000000";
+
+pub fn ejecutar(remoto: Option<&str>, especificaciones: &[String], forzar_todas: bool) -> Result<()> {
+    let nombre_remoto = match remoto {
+        Some(remoto) => remoto.to_string(),
+        None => traer::remoto_predeterminado()?,
+    };
+    let (url, especificaciones_traer) = traer::leer_remoto(&nombre_remoto)?;
+    let receptor = Receptor::conectar(&url)?;
+    let base = BaseObjetos::abrir(".git");
+
+    // Sin especificaciones se empuja la rama actual a la rama del mismo nombre
+    let especificaciones = if especificaciones.is_empty() {
+        match referencias::leer_head()? {
+            Head::Rama(nombre_ref) => vec![nombre_ref],
+            Head::Separado(_) => anyhow::bail!("HEAD no está en ninguna rama: indica qué empujar"),
+        }
+    } else {
+        especificaciones.to_vec()
+    };
+
+    let mut actualizaciones = Vec::new();
+    let mut lineas = Vec::new();
+    let mut rechazadas = 0;
+    for texto in &especificaciones {
+        let envio = Envio::parsear(texto, forzar_todas)?;
+        let anterior = receptor
+            .referencias()
+            .iter()
+            .find(|r| r.nombre == envio.destino)
+            .map_or(HASH_NULO.to_string(), |r| r.hash.clone());
+        let nuevo = envio.origen.clone().unwrap_or(HASH_NULO.to_string());
+        let corto = nombre_corto(&envio.destino);
+
+        if anterior == nuevo {
+            continue;
+        }
+        // Se rechaza aquí lo que el servidor rechazaría igualmente, sin mandar el paquete
+        let motivo = if nuevo == HASH_NULO && !receptor.tiene_capacidad("delete-refs") {
+            Some("el servidor no permite borrar referencias")
+        } else if anterior == HASH_NULO || nuevo == HASH_NULO || envio.forzar {
+            None
+        } else if envio.destino.starts_with("refs/tags/") {
+            Some("ya existe")
+        } else if !base.existe(&anterior) {
+            Some("primero trae los cambios del remoto")
+        } else if !revision::es_ancestro(&anterior, &nuevo)? {
+            Some("no es fast-forward")
+        } else {
+            None
+        };
+        if let Some(motivo) = motivo {
+            lineas.push(format!(" ! {:<17} {} -> {}  ({})", "[rechazada]", envio.nombre_origen, corto, motivo));
+            rechazadas += 1;
+            continue;
+        }
+        let actualizacion = ActualizacionRemota {
+            nombre: envio.destino.clone(),
+            anterior,
+            nuevo,
+        };
+        actualizaciones.push((envio, actualizacion));
+    }
+
+    if !actualizaciones.is_empty() {
+        // El paquete lleva lo alcanzable desde lo que se empuja y no desde lo que el servidor ya
+        // tiene. Solo se omite si todas las órdenes son borrados.
+        let ordenes: Vec<ActualizacionRemota> = actualizaciones.iter().map(|(_, a)| a.clone()).collect();
+        let nuevos: Vec<String> = ordenes.iter().map(|a| a.nuevo.clone()).filter(|h| h != HASH_NULO).collect();
+        let paquete = if nuevos.is_empty() {
+            None
+        } else {
+            let conocidos: Vec<String> = receptor
+                .referencias()
+                .iter()
+                .map(|r| r.hash.clone())
+                .filter(|hash| base.existe(hash))
+                .collect();
+            let objetos = empaquetar::enumerar_objetos(&base, &nuevos, &conocidos)?;
+            // Los deltas se guardan como OFS_DELTA, que el servidor tiene que aceptar
+            let mut opciones = Opciones::default();
+            if !receptor.tiene_capacidad("ofs-delta") {
+                opciones.ventana = 0;
+            }
+            let mut datos = Vec::new();
+            empaquetar::escribir_paquete(&objetos, &base, opciones, &mut datos)?;
+            println!("Objetos enviados: {}", objetos.len());
+            Some(datos)
+        };
+
+        let resultados = receptor.enviar(&ordenes, paquete)?;
+        for (envio, actualizacion) in &actualizaciones {
+            let corto = nombre_corto(&envio.destino);
+            let resultado = resultados.iter().find(|(nombre, _)| *nombre == envio.destino);
+            if let Some((_, Some(motivo))) = resultado {
+                lineas.push(format!(
+                    " ! {:<17} {} -> {}  (rechazada por el remoto: {})",
+                    "[rechazada]", envio.nombre_origen, corto, motivo
+                ));
+                rechazadas += 1;
+                continue;
+            } else if resultado.is_none() {
+                lineas.push(format!(
+                    " ! {:<17} {} -> {}  (sin respuesta del servidor)",
+                    "[rechazada]", envio.nombre_origen, corto
+                ));
+                rechazadas += 1;
+                continue;
+            }
+
+            let (anterior, nuevo) = (&actualizacion.anterior, &actualizacion.nuevo);
+            let (marca, resumen, nota) = if nuevo == HASH_NULO {
+                ('-', "[borrada]".to_string(), String::new())
+            } else if anterior == HASH_NULO {
+                let tipo = if envio.destino.starts_with("refs/tags/") { "[nueva etiqueta]" } else { "[nueva rama]" };
+                ('*', tipo.to_string(), String::new())
+            } else if envio.forzar && !(base.existe(anterior) && revision::es_ancestro(anterior, nuevo)?) {
+                ('+', format!("{}...{}", &anterior[..7], &nuevo[..7]), "  (actualización forzada)".to_string())
+            } else {
+                (' ', format!("{}..{}", &anterior[..7], &nuevo[..7]), String::new())
+            };
+            if nuevo == HASH_NULO {
+                lineas.push(format!(" {} {:<17} {}", marca, resumen, corto));
+            } else {
+                lineas.push(format!(" {} {:<17} {} -> {}{}", marca, resumen, envio.nombre_origen, corto, nota));
+            }
+
+            // La rama remota local refleja lo que ahora tiene el servidor, como tras un `traer`
+            if let Some((seguimiento, _)) = especificaciones_traer.iter().find_map(|e| e.aplicar(&envio.destino)) {
+                if nuevo == HASH_NULO {
+                    referencias::borrar_referencia(&seguimiento)?;
+                } else {
+                    referencias::escribir_referencia(&seguimiento, nuevo)?;
+                }
+            }
+        }
+    }
+
+    if lineas.is_empty() {
+        println!("Todo al día");
+        return Ok(());
+    }
+    println!("A {}", url);
+    for linea in lineas {
+        println!("{}", linea);
+    }
+    if rechazadas > 0 {
+        anyhow::bail!(
+            "No se actualizaron {} referencias en {}: trae los cambios del remoto o usa --force",
+            rechazadas,
+            url
+        );
+    }
+    Ok(())
+}
+
+/// Una especificación de envío: `[+]<origen>[:<destino>]`. Con el origen vacío (`:<destino>`)
+/// se borra la referencia del servidor.
+struct Envio {
+    forzar: bool,
+    /// El objeto que se empuja, o None para borrar
+    origen: Option<String>,
+    /// Cómo se escribió el origen, para mostrarlo
+    nombre_origen: String,
+    /// Nombre completo de la referencia en el servidor
+    destino: String,
+}
+
+impl Envio {
+    fn parsear(texto: &str, forzar_todas: bool) -> Result<Envio> {
+        let (forzar, texto) = match texto.strip_prefix('+') {
+            Some(resto) => (true, resto),
+            None => (forzar_todas, texto),
+        };
+        let (origen, destino) = match texto.split_once(':') {
+            Some((origen, destino)) => (origen, Some(destino)),
+            None => (texto, None),
+        };
+
+        // Si el origen es una referencia local, determina en qué espacio va el destino
+        let referencia_origen = if origen.starts_with("refs/") {
+            Some(origen.to_string())
+        } else {
+            ["refs/heads/", "refs/tags/"]
+                .iter()
+                .map(|prefijo| format!("{}{}", prefijo, origen))
+                .find(|nombre| referencias::resolver_referencia(nombre).is_ok_and(|hash| hash.is_some()))
+        };
+        let destino = match destino {
+            Some(destino) if destino.starts_with("refs/") => destino.to_string(),
+            Some("") => anyhow::bail!("Especificación de envío sin destino: '{}'", texto),
+            Some(destino) => match &referencia_origen {
+                Some(nombre) if nombre.starts_with("refs/tags/") => format!("refs/tags/{}", destino),
+                _ => format!("refs/heads/{}", destino),
+            },
+            None => referencia_origen
+                .clone()
+                .with_context(|| format!("No se sabe a qué referencia del remoto empujar '{}': usa <origen>:<destino>", origen))?,
+        };
+        referencias::validar_nombre(&destino)?;
+
+        let origen_hash = if origen.is_empty() {
+            None
+        } else {
+            Some(revision::resolver(origen)?)
+        };
+        Ok(Envio {
+            forzar,
+            origen: origen_hash,
+            nombre_origen: nombre_corto(referencia_origen.as_deref().unwrap_or(origen)).to_string(),
+            destino,
+        })
+    }
+}
+
+fn nombre_corto(nombre: &str) -> &str {
+    nombre
+        .strip_prefix("refs/heads/")
+        .or_else(|| nombre.strip_prefix("refs/tags/"))
+        .unwrap_or(nombre)
+}
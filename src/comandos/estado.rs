@@ -0,0 +1,214 @@
+use crate::base_objetos::calcular_hash_bytes;
+use crate::bloqueo::Bloqueo;
+use crate::comandos::leer_arbol;
+use crate::configuracion::Configuracion;
+use crate::ignorar::Ignorados;
+use crate::indice::{EntradaIndice, Indice};
+use crate::objetos::{Commit, Tipo};
+use crate::referencias::{self, Head};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+pub fn ejecutar() -> Result<()> {
+    let ruta_indice = Path::new(".git/index");
+    // Como en git, refrescar el índice es opcional: si otro proceso lo tiene bloqueado, estado
+    // solo lo lee
+    let bloqueo = if ruta_indice.exists() { Bloqueo::adquirir(ruta_indice).ok() } else { None };
+    let mut indice = Indice::leer(ruta_indice)?;
+
+    match referencias::leer_head()? {
+        Head::Rama(nombre_ref) => {
+            println!("En la rama {}", nombre_ref.trim_start_matches("refs/heads/"))
+        }
+        Head::Separado(hash) => println!("HEAD desacoplado en {}", &hash[..7.min(hash.len())]),
+    }
+
+    // Árbol del commit actual aplanado a ruta -> (modo, hash)
+    let arbol_head = match referencias::resolver_head()? {
+        Some(hash_commit) => {
+            let mut archivos = BTreeMap::new();
+            aplanar_arbol(&Commit::leer(&hash_commit)?.arbol, "", &mut archivos)?;
+            archivos
+        }
+        None => {
+            println!("\nNo hay commits todavía");
+            BTreeMap::new()
+        }
+    };
+
+    let rastreados: BTreeSet<String> = indice.entradas.iter().map(|e| e.ruta.clone()).collect();
+
+    // HEAD contra el índice
+    let mut preparados = Vec::new();
+    for entrada in &indice.entradas {
+        match arbol_head.get(&entrada.ruta) {
+            None => preparados.push(("nuevo archivo", entrada.ruta.clone())),
+            Some((modo, hash)) => {
+                if *hash != hex::encode(entrada.hash) || *modo != format!("{:o}", entrada.modo) {
+                    preparados.push(("modificado", entrada.ruta.clone()));
+                }
+            }
+        }
+    }
+    for ruta in arbol_head.keys() {
+        if !rastreados.contains(ruta) {
+            preparados.push(("borrado", ruta.clone()));
+        }
+    }
+    preparados.sort_by(|a, b| a.1.cmp(&b.1));
+
+    // Índice contra el directorio de trabajo
+    let mtime_indice = Indice::mtime(ruta_indice);
+    let confiar_en_ejecutable = Configuracion::cargar()?.obtener_bool("core.fileMode")?.unwrap_or(true);
+    let mut no_preparados = Vec::new();
+    let mut indice_refrescado = false;
+    for entrada in indice.entradas.iter_mut() {
+        let metadata = match fs::symlink_metadata(&entrada.ruta) {
+            Ok(metadata) if !metadata.is_dir() => metadata,
+            _ => {
+                no_preparados.push(("borrado", entrada.ruta.clone()));
+                continue;
+            }
+        };
+
+        if !entrada.necesita_rehash(&metadata, mtime_indice) {
+            continue;
+        }
+
+        // Los metadatos cambiaron: solo ahora se compara el contenido
+        let hash = hash_archivo(&entrada.ruta, &metadata)?;
+        let mut nueva = EntradaIndice::desde_metadata(&entrada.ruta, &metadata, hash);
+        if !confiar_en_ejecutable {
+            nueva.conservar_modo_ejecutable(entrada.modo);
+        }
+        if hash != entrada.hash || nueva.modo != entrada.modo {
+            no_preparados.push(("modificado", entrada.ruta.clone()));
+        } else {
+            // Mismo contenido: actualizar los metadatos para no volver a calcular el hash
+            let flags = entrada.flags;
+            let flags_extendidos = entrada.flags_extendidos;
+            *entrada = nueva;
+            entrada.flags = flags;
+            entrada.flags_extendidos = flags_extendidos;
+            indice_refrescado = true;
+        }
+    }
+
+    // Archivos del directorio de trabajo que el índice no conoce
+    let mut sin_seguimiento = Vec::new();
+    let mut ignorados = Ignorados::cargar()?;
+    buscar_sin_seguimiento(Path::new("."), "", &rastreados, &mut ignorados, &mut sin_seguimiento)?;
+
+    if let (true, Some(bloqueo)) = (indice_refrescado, bloqueo) {
+        indice.escribir_bloqueado(bloqueo)?;
+    }
+
+    if !preparados.is_empty() {
+        println!("\nCambios a confirmar:");
+        for (estado, ruta) in &preparados {
+            println!("\t{:<16}{}", format!("{}:", estado), ruta);
+        }
+    }
+    if !no_preparados.is_empty() {
+        println!("\nCambios no preparados para confirmar:");
+        for (estado, ruta) in &no_preparados {
+            println!("\t{:<16}{}", format!("{}:", estado), ruta);
+        }
+    }
+    if !sin_seguimiento.is_empty() {
+        println!("\nArchivos sin seguimiento:");
+        for ruta in &sin_seguimiento {
+            println!("\t{}", ruta);
+        }
+    }
+    if preparados.is_empty() && no_preparados.is_empty() && sin_seguimiento.is_empty() {
+        println!("\nNada para confirmar, el árbol de trabajo está limpio");
+    }
+
+    Ok(())
+}
+
+/// Recorre un tree recursivamente y guarda cada archivo como ruta -> (modo, hash)
+pub(crate) fn aplanar_arbol(
+    hash_arbol: &str,
+    prefijo: &str,
+    archivos: &mut BTreeMap<String, (String, String)>,
+) -> Result<()> {
+    for entrada in leer_arbol::leer_entradas(hash_arbol)? {
+        let ruta = format!("{}{}", prefijo, entrada.nombre);
+        if entrada.es_arbol() {
+            aplanar_arbol(&entrada.hash, &format!("{}/", ruta), archivos)?;
+        } else {
+            archivos.insert(ruta, (entrada.modo, entrada.hash));
+        }
+    }
+    Ok(())
+}
+
+/// Calcula el hash que tendría el blob de un archivo del directorio de trabajo
+pub(crate) fn hash_archivo(ruta: &str, metadata: &fs::Metadata) -> Result<[u8; 20]> {
+    let contenido = if metadata.file_type().is_symlink() {
+        fs::read_link(ruta)?.to_string_lossy().into_owned().into_bytes()
+    } else {
+        fs::read(ruta).with_context(|| format!("No se pudo leer {}", ruta))?
+    };
+
+    Ok(calcular_hash_bytes(Tipo::Blob, &contenido))
+}
+
+// Los directorios que no contienen ningún archivo rastreado se muestran como "dir/". Lo ignorado
+// no se muestra, salvo que ya esté rastreado.
+fn buscar_sin_seguimiento(
+    directorio: &Path,
+    prefijo: &str,
+    rastreados: &BTreeSet<String>,
+    ignorados: &mut Ignorados,
+    resultado: &mut Vec<String>,
+) -> Result<()> {
+    let mut entradas: Vec<_> = fs::read_dir(directorio)?.collect::<Result<_, _>>()?;
+    entradas.sort_by_key(|e| e.file_name());
+
+    for entrada in entradas {
+        let nombre = entrada.file_name().to_string_lossy().into_owned();
+        let ruta = format!("{}{}", prefijo, nombre);
+
+        // Evita procesar cualquier cosa dentro de .git/
+        if nombre == ".git" {
+            continue;
+        }
+
+        if entrada.file_type()?.is_dir() {
+            let prefijo_sub = format!("{}/", ruta);
+            let tiene_rastreados = rastreados
+                .range(prefijo_sub.clone()..)
+                .next()
+                .is_some_and(|r| r.starts_with(&prefijo_sub));
+            if tiene_rastreados {
+                buscar_sin_seguimiento(&entrada.path(), &prefijo_sub, rastreados, ignorados, resultado)?;
+            } else if !ignorados.ignorado(&ruta, true) && contiene_archivos(&entrada.path(), &prefijo_sub, ignorados)? {
+                resultado.push(prefijo_sub);
+            }
+        } else if !rastreados.contains(&ruta) && !ignorados.ignorado(&ruta, false) {
+            resultado.push(ruta);
+        }
+    }
+    Ok(())
+}
+
+// git no muestra como no rastreados los directorios vacíos ni los que solo tienen archivos ignorados
+fn contiene_archivos(directorio: &Path, prefijo: &str, ignorados: &mut Ignorados) -> Result<bool> {
+    for entrada in fs::read_dir(directorio)? {
+        let entrada = entrada?;
+        let ruta = format!("{}{}", prefijo, entrada.file_name().to_string_lossy());
+        let es_directorio = entrada.file_type()?.is_dir();
+        if ignorados.ignorado(&ruta, es_directorio) {
+            continue;
+        }
+        if !es_directorio || contiene_archivos(&entrada.path(), &format!("{}/", ruta), ignorados)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
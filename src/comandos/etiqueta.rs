@@ -0,0 +1,142 @@
+use crate::base_objetos::BaseObjetos;
+use crate::commit::{self, Rol};
+use crate::objetos::{Commit, Etiqueta, Objeto, Tipo};
+use crate::referencias;
+use crate::revision;
+use anyhow::{Context, Result};
+
+pub fn ejecutar(
+    nombres: &[String],
+    listar: bool,
+    con_mensaje: bool,
+    borrar: bool,
+    anotada: bool,
+    mensaje: Option<&str>,
+    forzar: bool,
+) -> Result<()> {
+    if borrar {
+        if nombres.is_empty() {
+            anyhow::bail!("Indica la etiqueta que se quiere borrar");
+        }
+        for nombre in nombres {
+            borrar_etiqueta(nombre)?;
+        }
+        return Ok(());
+    }
+
+    if listar || con_mensaje || nombres.is_empty() {
+        return listar_etiquetas(nombres.first().map(String::as_str), con_mensaje);
+    }
+
+    let (nombre, objetivo) = match nombres {
+        [nombre] => (nombre.as_str(), "HEAD"),
+        [nombre, objetivo] => (nombre.as_str(), objetivo.as_str()),
+        _ => anyhow::bail!("Uso: etiqueta [-a] [-m <mensaje>] <nombre> [<revision>]"),
+    };
+
+    // -m implica una etiqueta anotada, igual que en git
+    if anotada || mensaje.is_some() {
+        let mensaje = mensaje.context("Las etiquetas anotadas necesitan un mensaje (-m)")?;
+        crear_etiqueta(nombre, objetivo, Some(mensaje), forzar)
+    } else {
+        crear_etiqueta(nombre, objetivo, None, forzar)
+    }
+}
+
+fn listar_etiquetas(patron: Option<&str>, con_mensaje: bool) -> Result<()> {
+    for (nombre_ref, hash) in referencias::listar_referencias("refs/tags/")? {
+        let nombre = nombre_ref.trim_start_matches("refs/tags/");
+        if let Some(patron) = patron {
+            if !coincide_patron(patron, nombre) {
+                continue;
+            }
+        }
+        if con_mensaje {
+            println!("{:<15} {}", nombre, primera_linea(&hash)?);
+        } else {
+            println!("{}", nombre);
+        }
+    }
+    Ok(())
+}
+
+// Como `git tag -n`: el mensaje de la etiqueta anotada o el título del commit etiquetado
+fn primera_linea(hash: &str) -> Result<String> {
+    let mensaje = match Objeto::leer(hash)?.tipo {
+        Tipo::Tag => Etiqueta::leer(hash)?.mensaje,
+        Tipo::Commit => Commit::leer(hash)?.mensaje,
+        _ => String::new(),
+    };
+    Ok(mensaje.lines().next().unwrap_or("").to_string())
+}
+
+fn crear_etiqueta(nombre: &str, objetivo: &str, mensaje: Option<&str>, forzar: bool) -> Result<()> {
+    referencias::validar_nombre(nombre)?;
+    let nombre_ref = format!("refs/tags/{}", nombre);
+    let existente = referencias::resolver_referencia(&nombre_ref)?;
+    if existente.is_some() && !forzar {
+        anyhow::bail!("La etiqueta '{}' ya existe", nombre);
+    }
+
+    let hash_objetivo = revision::resolver(objetivo)
+        .with_context(|| format!("'{}' no es un nombre de objeto válido", objetivo))?;
+
+    let hash = match mensaje {
+        // Etiqueta ligera: la referencia apunta directamente al objeto
+        None => hash_objetivo,
+        Some(mensaje) => {
+            let tipo = Objeto::leer(&hash_objetivo)?.tipo;
+            let tagger = commit::firma(Rol::Committer, None, None)?;
+
+            let mut contenido = format!("object {}\n", hash_objetivo);
+            contenido.push_str(&format!("type {}\n", tipo));
+            contenido.push_str(&format!("tag {}\n", nombre));
+            contenido.push_str(&format!("tagger {}\n", tagger));
+            contenido.push('\n');
+            contenido.push_str(mensaje.trim_end());
+            contenido.push('\n');
+
+            // Comprobar que el objeto generado se puede volver a leer
+            Etiqueta::parsear(contenido.as_bytes())?;
+            BaseObjetos::abrir(".git").escribir(Tipo::Tag, contenido.as_bytes())?
+        }
+    };
+
+    // Solo si la etiqueta sigue como estaba al comprobarla
+    referencias::actualizar_referencia(&nombre_ref, &hash, existente.as_deref())?;
+    Ok(())
+}
+
+fn borrar_etiqueta(nombre: &str) -> Result<()> {
+    referencias::validar_nombre(nombre)?;
+    let nombre_ref = format!("refs/tags/{}", nombre);
+    let hash = referencias::resolver_referencia(&nombre_ref)?
+        .with_context(|| format!("No se encontró la etiqueta '{}'", nombre))?;
+    referencias::borrar_referencia(&nombre_ref)?;
+    println!("Etiqueta '{}' borrada (era {})", nombre, &hash[..7]);
+    Ok(())
+}
+
+// Comodines de shell: '*' cualquier secuencia y '?' un carácter
+fn coincide_patron(patron: &str, texto: &str) -> bool {
+    let patron: Vec<char> = patron.chars().collect();
+    let texto: Vec<char> = texto.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut ultimo_asterisco = None;
+    while t < texto.len() {
+        if p < patron.len() && (patron[p] == '?' || patron[p] == texto[t]) {
+            p += 1;
+            t += 1;
+        } else if p < patron.len() && patron[p] == '*' {
+            ultimo_asterisco = Some((p, t));
+            p += 1;
+        } else if let Some((pa, ta)) = ultimo_asterisco {
+            p = pa + 1;
+            t = ta + 1;
+            ultimo_asterisco = Some((pa, ta + 1));
+        } else {
+            return false;
+        }
+    }
+    patron[p..].iter().all(|&c| c == '*')
+}
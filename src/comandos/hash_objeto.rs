@@ -1,9 +1,8 @@
+use crate::base_objetos::{calcular_hash, BaseObjetos};
+use crate::objetos::Tipo;
 use anyhow::{Context, Result};
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use sha1::{Digest, Sha1};
-use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 
 pub fn ejecutar(escribir: bool, ruta: &Path) -> Result<()> {
@@ -12,29 +11,12 @@ pub fn ejecutar(escribir: bool, ruta: &Path) -> Result<()> {
     let mut contenido = Vec::new();
     archivo.read_to_end(&mut contenido)?;
     
-    // Calcular el hash SHA-1 del objeto
-    let mut hasher = Sha1::new();
-    hasher.update(format!("blob {}\0", contenido.len()));
-    hasher.update(&contenido);
-    let hash = hasher.finalize();
-    let hash_str = format!("{:x}", hash);
-    
-    // Si la bandera -w está establecida, escribir el objeto en .git/objects
-    if escribir {
-        // Crear el directorio de objetos si no existe
-        let objeto_dir = format!(".git/objects/{}", &hash_str[..2]);
-        fs::create_dir_all(&objeto_dir)?;
-        
-        // Abrir el archivo de objeto para escritura
-        let objeto_ruta = format!("{}/{}", objeto_dir, &hash_str[2..]);
-        let objeto_archivo = File::create(&objeto_ruta)?;
-        
-        // Comprimir y escribir el objeto
-        let mut encoder = ZlibEncoder::new(objeto_archivo, Compression::default());
-        write!(encoder, "blob {}\0", contenido.len())?;
-        encoder.write_all(&contenido)?;
-        encoder.finish()?;
-    }
+    // Calcular el hash SHA-1 del objeto y, con -w, guardarlo en la base de objetos
+    let hash_str = if escribir {
+        BaseObjetos::abrir(".git").escribir(Tipo::Blob, &contenido)?
+    } else {
+        calcular_hash(Tipo::Blob, &contenido)
+    };
     
     // Imprimir el hash
     println!("{}", hash_str);
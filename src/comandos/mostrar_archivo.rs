@@ -1,20 +1,106 @@
+use crate::comandos::leer_arbol;
 use crate::objetos::{Objeto, Tipo};
+use crate::revision;
 use anyhow::Result;
-use std::io::{Read, Write};
+use std::io::{BufRead, Read, Write};
 
-pub fn ejecutar(mostrar_bonito: bool, hash_objeto: &str) -> Result<()> {
-    if !mostrar_bonito {
-        anyhow::bail!("Solo se admite la opción -p");
+/// Qué mostrar de un objeto (las opciones de `git cat-file`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Modo {
+    /// -p: contenido en formato legible
+    Bonito,
+    /// -t: tipo del objeto
+    Tipo,
+    /// -s: tamaño del contenido
+    Tamaño,
+    /// -e: solo el código de salida indica si el objeto existe
+    Existe,
+    /// --batch: cabecera y contenido de cada objeto leído de stdin
+    Lote,
+    /// --batch-check: solo la cabecera de cada objeto leído de stdin
+    LoteCabecera,
+}
+
+pub fn ejecutar(modo: Modo, hash_objeto: Option<&str>) -> Result<()> {
+    if matches!(modo, Modo::Lote | Modo::LoteCabecera) {
+        return procesar_lote(modo == Modo::Lote);
+    }
+
+    let Some(hash_objeto) = hash_objeto else {
+        anyhow::bail!("Falta el objeto a mostrar");
+    };
+
+    if modo == Modo::Existe {
+        // Como git, sin mensajes: solo el código de salida
+        let existe = revision::resolver(hash_objeto)
+            .and_then(|hash| Objeto::leer(&hash).map(|_| ()))
+            .is_ok();
+        if !existe {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let hash = revision::resolver(hash_objeto)?;
+    let mut objeto = Objeto::leer(&hash)?;
+    let mut salida = std::io::stdout().lock();
+    match modo {
+        Modo::Tipo => writeln!(salida, "{}", objeto.tipo)?,
+        Modo::Tamaño => writeln!(salida, "{}", objeto.tamaño_esperado)?,
+        _ => {
+            let mut contenido = Vec::new();
+            objeto.lector.read_to_end(&mut contenido)?;
+
+            if objeto.tipo == Tipo::Arbol {
+                for entrada in leer_arbol::parsear_entradas(&contenido)? {
+                    let tipo = if entrada.es_arbol() {
+                        "tree"
+                    } else if entrada.modo == "160000" {
+                        "commit"
+                    } else {
+                        "blob"
+                    };
+                    writeln!(salida, "{:0>6} {} {}\t{}", entrada.modo, tipo, entrada.hash, entrada.nombre)?;
+                }
+            } else {
+                // Blobs, commits y tags se muestran tal cual están guardados
+                salida.write_all(&contenido)?;
+            }
+        }
     }
 
-    let mut objeto = Objeto::leer(hash_objeto)?;
-    if objeto.tipo == Tipo::Blob {
-        let mut contenido = Vec::new();
-        objeto.lector.read_to_end(&mut contenido)?;
-        std::io::stdout().write_all(&contenido)?;
-    } else {
-        anyhow::bail!("Solo se admiten objetos de tipo blob");
+    Ok(())
+}
+
+// Cada línea de stdin es un nombre de objeto; la salida sigue el formato de git:
+// "<hash> <tipo> <tamaño>" seguido del contenido en modo --batch
+fn procesar_lote(con_contenido: bool) -> Result<()> {
+    let entrada = std::io::stdin().lock();
+    let mut salida = std::io::stdout().lock();
+
+    for linea in entrada.lines() {
+        let linea = linea?;
+        let nombre = linea.trim();
+        if nombre.is_empty() {
+            continue;
+        }
+
+        let objeto = revision::resolver(nombre).and_then(|hash| Ok((Objeto::leer(&hash)?, hash)));
+        let Ok((mut objeto, hash)) = objeto else {
+            writeln!(salida, "{} missing", nombre)?;
+            continue;
+        };
+
+        writeln!(salida, "{} {} {}", hash, objeto.tipo, objeto.tamaño_esperado)?;
+        if con_contenido {
+            let mut contenido = Vec::new();
+            objeto.lector.read_to_end(&mut contenido)?;
+            salida.write_all(&contenido)?;
+            writeln!(salida)?;
+        }
+        // Quien usa el modo lote suele esperar la respuesta antes de enviar la siguiente línea
+        salida.flush()?;
     }
 
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,131 @@
+use crate::referencias::{self, Head};
+use crate::revision;
+use anyhow::{Context, Result};
+
+pub fn ejecutar(nombres: &[String], borrar: bool, renombrar: bool, forzar: bool) -> Result<()> {
+    if borrar {
+        if nombres.is_empty() {
+            anyhow::bail!("Indica la rama que se quiere borrar");
+        }
+        for nombre in nombres {
+            borrar_rama(nombre, forzar)?;
+        }
+        return Ok(());
+    }
+
+    if renombrar {
+        return match nombres {
+            [nuevo] => {
+                let Head::Rama(actual) = referencias::leer_head()? else {
+                    anyhow::bail!("HEAD no apunta a ninguna rama, indica cuál renombrar");
+                };
+                renombrar_rama(actual.trim_start_matches("refs/heads/"), nuevo, forzar)
+            }
+            [viejo, nuevo] => renombrar_rama(viejo, nuevo, forzar),
+            _ => anyhow::bail!("Uso: rama -m [<rama-vieja>] <rama-nueva>"),
+        };
+    }
+
+    match nombres {
+        [] => listar_ramas(),
+        [nombre] => crear_rama(nombre, "HEAD", forzar),
+        [nombre, inicio] => crear_rama(nombre, inicio, forzar),
+        _ => anyhow::bail!("Uso: rama [<nombre> [<inicio>]]"),
+    }
+}
+
+fn listar_ramas() -> Result<()> {
+    let head = referencias::leer_head()?;
+    if let Head::Separado(hash) = &head {
+        println!("* (HEAD desacoplado en {})", &hash[..7.min(hash.len())]);
+    }
+    for nombre_ref in referencias::listar_referencias("refs/heads/")?.keys() {
+        let marca = if head == Head::Rama(nombre_ref.clone()) { '*' } else { ' ' };
+        println!("{} {}", marca, nombre_ref.trim_start_matches("refs/heads/"));
+    }
+    Ok(())
+}
+
+fn crear_rama(nombre: &str, inicio: &str, forzar: bool) -> Result<()> {
+    referencias::validar_nombre_rama(nombre)?;
+    let nombre_ref = format!("refs/heads/{}", nombre);
+
+    let existente = referencias::resolver_referencia(&nombre_ref)?;
+    if existente.is_some() {
+        if !forzar {
+            anyhow::bail!("Ya existe una rama llamada '{}'", nombre);
+        }
+        if referencias::leer_head()? == Head::Rama(nombre_ref.clone()) {
+            anyhow::bail!("No se puede forzar la actualización de la rama actual '{}'", nombre);
+        }
+    }
+
+    let hash = revision::resolver_commit(inicio)
+        .with_context(|| format!("'{}' no es un nombre de commit válido", inicio))?;
+    // Solo si la rama sigue como estaba al comprobarla
+    referencias::actualizar_referencia(&nombre_ref, &hash, existente.as_deref())?;
+    Ok(())
+}
+
+fn borrar_rama(nombre: &str, forzar: bool) -> Result<()> {
+    referencias::validar_nombre_rama(nombre)?;
+    let nombre_ref = format!("refs/heads/{}", nombre);
+    let hash = referencias::resolver_referencia(&nombre_ref)?
+        .with_context(|| format!("No se encontró la rama '{}'", nombre))?;
+
+    if referencias::leer_head()? == Head::Rama(nombre_ref.clone()) {
+        anyhow::bail!("No se puede borrar la rama '{}' porque es la rama actual", nombre);
+    }
+
+    // Sin forzar, solo se borran ramas cuyo trabajo ya está incluido en HEAD
+    if !forzar {
+        let fusionada = match referencias::resolver_head()? {
+            Some(head) => revision::es_ancestro(&hash, &head)?,
+            None => false,
+        };
+        if !fusionada {
+            anyhow::bail!(
+                "La rama '{}' no está completamente fusionada.\nSi estás seguro de querer borrarla, usa 'rama -D {}'.",
+                nombre,
+                nombre
+            );
+        }
+    }
+
+    referencias::borrar_referencia(&nombre_ref)?;
+    println!("Rama {} borrada (era {}).", nombre, &hash[..7]);
+    Ok(())
+}
+
+fn renombrar_rama(viejo: &str, nuevo: &str, forzar: bool) -> Result<()> {
+    referencias::validar_nombre_rama(viejo)?;
+    referencias::validar_nombre_rama(nuevo)?;
+    let ref_vieja = format!("refs/heads/{}", viejo);
+    let ref_nueva = format!("refs/heads/{}", nuevo);
+    let head = referencias::leer_head()?;
+    let es_actual = head == Head::Rama(ref_vieja.clone());
+
+    // Una rama recién creada sin commits solo existe en HEAD
+    let hash = referencias::resolver_referencia(&ref_vieja)?;
+    if hash.is_none() && !es_actual {
+        anyhow::bail!("No se encontró la rama '{}'", viejo);
+    }
+    if ref_vieja == ref_nueva {
+        return Ok(());
+    }
+    let existente = referencias::resolver_referencia(&ref_nueva)?;
+    if existente.is_some() && !forzar {
+        anyhow::bail!("Ya existe una rama llamada '{}'", nuevo);
+    }
+
+    if let Some(hash) = hash {
+        // Primero la rama nueva, y solo si sigue como estaba al comprobarla: si no se puede
+        // crear, la vieja sigue intacta
+        referencias::actualizar_referencia(&ref_nueva, &hash, existente.as_deref())?;
+        referencias::borrar_referencia(&ref_vieja)?;
+    }
+    if es_actual {
+        referencias::escribir_head(&Head::Rama(ref_nueva))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+use crate::base_objetos::BaseObjetos;
+use crate::empaquetar::{self, ObjetoAEmpaquetar, Opciones};
+use crate::objetos::Objeto;
+use crate::referencias::{self, OrigenRaiz};
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+// Los objetos inalcanzables más recientes que esto se conservan: pueden ser de un comando que
+// todavía está en marcha (un `agregar` antes del commit) o algo que se quiera recuperar
+const GRACIA_POR_DEFECTO: &str = "2.weeks.ago";
+
+pub fn ejecutar(podar: Option<&str>) -> Result<()> {
+    let limite = parsear_limite(podar.unwrap_or(GRACIA_POR_DEFECTO))?;
+    let base = BaseObjetos::abrir(".git");
+    let antes = tamaño_directorio(base.directorio())?;
+
+    // Todo lo alcanzable desde las referencias, el reflog y el índice va al paquete nuevo
+    let objetos = objetos_alcanzables(&base)?;
+    let alcanzables: HashSet<&str> = objetos.iter().map(|o| o.hash.as_str()).collect();
+    let mut nombre_nuevo = String::new();
+    if !objetos.is_empty() {
+        let prefijo = base.directorio().join("pack/pack");
+        let checksum = empaquetar::guardar(&objetos, &base, Opciones::default(), &prefijo)?;
+        nombre_nuevo = format!("pack-{}.pack", hex::encode(checksum));
+        println!("Objetos empaquetados: {} en {}", objetos.len(), nombre_nuevo);
+    }
+
+    // Los paquetes anteriores sobran. Sus objetos inalcanzables se sacan como sueltos si el
+    // paquete es reciente, para que sigan el mismo plazo que el resto.
+    let mut paquetes_borrados = 0;
+    for paquete in base.paquetes()? {
+        let ruta = paquete.ruta();
+        if ruta.file_name().is_some_and(|nombre| *nombre == *nombre_nuevo) || ruta.with_extension("keep").exists() {
+            continue;
+        }
+        let modificado = fs::metadata(ruta)?.modified()?;
+        if modificado > limite {
+            for hash in paquete.hashes().map(hex::encode) {
+                if !alcanzables.contains(hash.as_str()) && !base.ruta_suelto(&hash).exists() {
+                    let (tipo, datos) = base.leer_completo(&hash)?;
+                    base.escribir(tipo, &datos)?;
+                    File::open(base.ruta_suelto(&hash))?.set_modified(modificado)?;
+                }
+            }
+        }
+        // Primero el .idx: un .pack sin .idx no se usa, al revés sería un paquete roto
+        for extension in ["idx", "bitmap", "rev", "pack"] {
+            let ruta_archivo = ruta.with_extension(extension);
+            if ruta_archivo.exists() {
+                fs::remove_file(&ruta_archivo)
+                    .with_context(|| format!("No se pudo borrar {}", ruta_archivo.display()))?;
+            }
+        }
+        paquetes_borrados += 1;
+    }
+    if paquetes_borrados > 0 {
+        println!("Paquetes reemplazados: {}", paquetes_borrados);
+    }
+    // Las etiquetas se pelan al empaquetar las referencias, leyendo ya del paquete nuevo
+    Objeto::recargar_base();
+
+    // Los sueltos alcanzables ya están en el paquete; los inalcanzables se borran pasado el plazo
+    let mut empaquetados = 0;
+    let mut podados = 0;
+    for hash in base.sueltos()? {
+        let ruta = base.ruta_suelto(&hash);
+        if alcanzables.contains(hash.as_str()) {
+            empaquetados += 1;
+        } else if fs::metadata(&ruta)?.modified()? <= limite {
+            podados += 1;
+        } else {
+            continue;
+        }
+        fs::remove_file(&ruta)?;
+        if let Some(directorio) = ruta.parent() {
+            let _ = fs::remove_dir(directorio);
+        }
+    }
+    println!("Objetos sueltos borrados: {} ya empaquetados, {} inalcanzables", empaquetados, podados);
+
+    let referencias_empaquetadas = referencias::empaquetar_referencias()?;
+    println!("Referencias empaquetadas: {}", referencias_empaquetadas);
+
+    let despues = tamaño_directorio(base.directorio())?;
+    println!(
+        "Espacio usado por los objetos: {} -> {} (ahorrados {})",
+        formatear_tamaño(antes),
+        formatear_tamaño(despues),
+        formatear_tamaño(antes.saturating_sub(despues))
+    );
+    Ok(())
+}
+
+// Los objetos alcanzables desde las raíces; los blobs del índice se añaden sueltos, con su ruta
+// para que el empaquetador los agrupe bien al buscar deltas
+fn objetos_alcanzables(base: &BaseObjetos) -> Result<Vec<ObjetoAEmpaquetar>> {
+    let mut desde_historia = Vec::new();
+    let mut del_indice = Vec::new();
+    for raiz in referencias::raices(base)? {
+        match raiz.origen {
+            OrigenRaiz::Indice(ruta) => del_indice.push((raiz.hash, ruta)),
+            OrigenRaiz::Referencia(nombre) if !base.existe(&raiz.hash) => anyhow::bail!(
+                "Falta el objeto {} al que apunta {}: el repositorio está dañado",
+                raiz.hash,
+                nombre
+            ),
+            _ => desde_historia.push(raiz.hash),
+        }
+    }
+
+    let mut objetos = empaquetar::enumerar_objetos(base, &desde_historia, &[])?;
+    let mut vistos: HashSet<String> = objetos.iter().map(|o| o.hash.clone()).collect();
+    for (hash, ruta) in del_indice {
+        if base.existe(&hash) && vistos.insert(hash.clone()) {
+            objetos.push(ObjetoAEmpaquetar { hash, ruta });
+        }
+    }
+    Ok(objetos)
+}
+
+// Acepta "now", "never" y "<n>.<unidad>.ago" con segundos, minutos, horas, días o semanas en
+// inglés, como `git gc --prune`. Devuelve el instante a partir del cual un objeto es reciente.
+fn parsear_limite(texto: &str) -> Result<SystemTime> {
+    let ahora = SystemTime::now();
+    match texto {
+        "now" => return Ok(ahora),
+        "never" => return Ok(SystemTime::UNIX_EPOCH),
+        _ => {}
+    }
+    let error = || format!("Plazo inválido: '{}' (usa now, never o por ejemplo 2.weeks.ago)", texto);
+    let sin_ago = texto.strip_suffix(".ago").unwrap_or(texto);
+    let (cantidad, unidad) = sin_ago.split_once('.').with_context(error)?;
+    let cantidad: u64 = cantidad.parse().ok().with_context(error)?;
+    let segundos = match unidad.trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        _ => anyhow::bail!(error()),
+    };
+    // Un plazo enorme no puede desbordar ni irse antes de lo que SystemTime representa
+    cantidad
+        .checked_mul(segundos)
+        .and_then(|total| ahora.checked_sub(Duration::from_secs(total)))
+        .with_context(error)
+}
+
+fn tamaño_directorio(directorio: &Path) -> Result<u64> {
+    let mut total = 0;
+    let mut pendientes = vec![directorio.to_path_buf()];
+    while let Some(actual) = pendientes.pop() {
+        for entrada in fs::read_dir(&actual)? {
+            let entrada = entrada?;
+            let metadata = entrada.metadata()?;
+            if metadata.is_dir() {
+                pendientes.push(entrada.path());
+            } else {
+                total += metadata.len();
+            }
+        }
+    }
+    Ok(total)
+}
+
+fn formatear_tamaño(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
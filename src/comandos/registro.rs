@@ -0,0 +1,197 @@
+use crate::objetos::Commit;
+use crate::referencias;
+use crate::revision;
+use anyhow::{Context, Result};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+pub fn ejecutar(revision: Option<&str>, una_linea: bool, limite: Option<usize>, grafo: bool) -> Result<()> {
+    let inicio = match revision {
+        Some(revision) => revision::resolver_commit(revision)?,
+        None => referencias::resolver_head()?
+            .context("La rama actual todavía no tiene commits")?,
+    };
+
+    let commits = if grafo {
+        orden_topologico(&inicio, limite)?
+    } else {
+        orden_por_fecha(&inicio, limite)?
+    };
+
+    let mut columnas = Vec::new();
+    for (hash, commit) in &commits {
+        // Sin --graph los prefijos quedan vacíos
+        let fila = if grafo {
+            dibujar_grafo(&mut columnas, hash, &commit.padres)
+        } else {
+            FilaGrafo::default()
+        };
+        let prefijo_cuerpo = &fila.cuerpo;
+
+        for linea in &fila.previas {
+            println!("{}", linea.trim_end());
+        }
+        let titulo = commit.mensaje.lines().next().unwrap_or("");
+        if una_linea {
+            println!("{}{} {}", fila.commit, &hash[..7], titulo);
+        } else {
+            println!("{}commit {}", fila.commit, hash);
+        }
+        for linea in &fila.transiciones {
+            println!("{}", linea.trim_end());
+        }
+        if una_linea {
+            continue;
+        }
+
+        if commit.padres.len() > 1 {
+            let cortos: Vec<&str> = commit.padres.iter().map(|p| &p[..7]).collect();
+            println!("{}Fusión: {}", prefijo_cuerpo, cortos.join(" "));
+        }
+        println!("{}Autor: {} <{}>", prefijo_cuerpo, commit.autor.nombre, commit.autor.email);
+        println!("{}Fecha: {}", prefijo_cuerpo, commit.autor.fecha_legible());
+        println!("{}", prefijo_cuerpo.trim_end());
+        for linea in commit.mensaje.trim_end().lines() {
+            println!("{}    {}", prefijo_cuerpo, linea);
+        }
+        println!("{}", prefijo_cuerpo.trim_end());
+    }
+
+    Ok(())
+}
+
+// Como git log: el commit más reciente (por fecha del committer) primero
+fn orden_por_fecha(inicio: &str, limite: Option<usize>) -> Result<Vec<(String, Commit)>> {
+    let mut pendientes = BinaryHeap::new();
+    let mut vistos = HashSet::new();
+    let mut resultado = Vec::new();
+
+    let commit = Commit::leer(inicio)?;
+    pendientes.push((commit.committer.timestamp, inicio.to_string()));
+    vistos.insert(inicio.to_string());
+    let mut cargados = HashMap::from([(inicio.to_string(), commit)]);
+
+    while let Some((_, hash)) = pendientes.pop() {
+        if limite.is_some_and(|n| resultado.len() >= n) {
+            break;
+        }
+        let commit = cargados.remove(&hash).expect("todo commit pendiente está cargado");
+        for padre in &commit.padres {
+            if vistos.insert(padre.clone()) {
+                let commit_padre = Commit::leer(padre)?;
+                pendientes.push((commit_padre.committer.timestamp, padre.clone()));
+                cargados.insert(padre.clone(), commit_padre);
+            }
+        }
+        resultado.push((hash, commit));
+    }
+    Ok(resultado)
+}
+
+// Para --graph ningún commit puede aparecer antes que sus hijos
+fn orden_topologico(inicio: &str, limite: Option<usize>) -> Result<Vec<(String, Commit)>> {
+    // Cargar todo el historial alcanzable y contar los hijos de cada commit
+    let mut commits: HashMap<String, Commit> = HashMap::new();
+    let mut hijos_pendientes: HashMap<String, usize> = HashMap::new();
+    let mut pila = vec![inicio.to_string()];
+    while let Some(hash) = pila.pop() {
+        if commits.contains_key(&hash) {
+            continue;
+        }
+        let commit = Commit::leer(&hash)?;
+        for padre in &commit.padres {
+            *hijos_pendientes.entry(padre.clone()).or_insert(0) += 1;
+            pila.push(padre.clone());
+        }
+        commits.insert(hash, commit);
+    }
+
+    // Kahn: entre los commits listos se elige el más reciente
+    let mut listos = BinaryHeap::new();
+    listos.push((commits[inicio].committer.timestamp, inicio.to_string()));
+    let mut resultado = Vec::new();
+    while let Some((_, hash)) = listos.pop() {
+        if limite.is_some_and(|n| resultado.len() >= n) {
+            break;
+        }
+        let commit = commits.remove(&hash).expect("cada commit se visita una vez");
+        for padre in &commit.padres {
+            let pendientes = hijos_pendientes.get_mut(padre).expect("contado al cargar");
+            *pendientes -= 1;
+            if *pendientes == 0 {
+                listos.push((commits[padre].committer.timestamp, padre.clone()));
+            }
+        }
+        resultado.push((hash, commit));
+    }
+    Ok(resultado)
+}
+
+/// Prefijos de grafo para un commit
+#[derive(Default)]
+struct FilaGrafo {
+    /// Líneas que se imprimen antes del commit (ramas que convergen en él)
+    previas: Vec<String>,
+    commit: String,
+    /// Líneas que se imprimen después del commit (ramas que abre una fusión)
+    transiciones: Vec<String>,
+    cuerpo: String,
+}
+
+/// Actualiza las columnas del grafo con un commit. Cada columna guarda el commit que espera.
+fn dibujar_grafo(columnas: &mut Vec<String>, hash: &str, padres: &[String]) -> FilaGrafo {
+    let mut fila = FilaGrafo::default();
+
+    // Varias columnas esperaban este commit: se juntan en la primera
+    let posiciones: Vec<usize> = (0..columnas.len()).filter(|&i| columnas[i] == hash).collect();
+    for &i in posiciones.iter().skip(1).rev() {
+        fila.previas.push(dibujar_fila(columnas.len(), |j| if j == i { "/" } else { "|" }));
+        columnas.remove(i);
+    }
+    let indice = match posiciones.first() {
+        Some(&primera) => primera,
+        None => {
+            columnas.push(hash.to_string());
+            columnas.len() - 1
+        }
+    };
+    fila.commit = dibujar_fila(columnas.len(), |j| if j == indice { "*" } else { "|" });
+
+    match padres.split_first() {
+        None => {
+            columnas.remove(indice);
+        }
+        Some((primero, otros)) => {
+            columnas[indice] = primero.clone();
+            // Cada padre adicional de una fusión abre una columna nueva a la derecha
+            let mut nuevas = 0;
+            for padre in otros {
+                if !columnas.contains(padre) {
+                    columnas.insert(indice + 1 + nuevas, padre.clone());
+                    nuevas += 1;
+                }
+            }
+            if nuevas > 0 {
+                fila.transiciones.push(dibujar_fila(columnas.len(), |j| {
+                    if j > indice && j <= indice + nuevas { "\\" } else { "|" }
+                }));
+            }
+        }
+    }
+
+    fila.cuerpo = dibujar_fila(columnas.len(), |_| "|");
+    fila
+}
+
+fn dibujar_fila(cantidad: usize, simbolo: impl Fn(usize) -> &'static str) -> String {
+    let mut fila = String::new();
+    for j in 0..cantidad {
+        let simbolo = simbolo(j);
+        // Las diagonales se dibujan entre dos columnas
+        if simbolo == "/" || simbolo == "\\" {
+            fila.pop();
+        }
+        fila.push_str(simbolo);
+        fila.push(' ');
+    }
+    fila
+}
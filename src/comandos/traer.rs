@@ -0,0 +1,228 @@
+use crate::base_objetos::BaseObjetos;
+use crate::configuracion::Configuracion;
+use crate::objetos::{Commit, Etiqueta, Objeto, Tipo};
+use crate::protocolo::Servidor;
+use crate::referencias::{self, Head};
+use crate::revision;
+use anyhow::{Context, Result};
+use std::collections::{BinaryHeap, HashSet};
+
+// Cuántos commits locales se anuncian como "have" como máximo
+const LIMITE_COMUNES: usize = 256;
+
+pub fn ejecutar(remoto: Option<&str>) -> Result<()> {
+    let nombre_remoto = match remoto {
+        Some(remoto) => remoto.to_string(),
+        None => remoto_predeterminado()?,
+    };
+    let (url, especificaciones) = leer_remoto(&nombre_remoto)?;
+
+    let servidor = Servidor::conectar(&url)?;
+    let mut prefijos: Vec<&str> = especificaciones.iter().map(|e| e.origen.as_str()).collect();
+    prefijos.push("refs/tags/");
+    let anunciadas = servidor.listar_referencias(&prefijos)?;
+
+    // Cada rama del servidor que cubre una especificación va a su referencia local
+    let mut actualizaciones = Vec::new();
+    for referencia in &anunciadas {
+        if let Some((destino, forzar)) = especificaciones.iter().find_map(|e| e.aplicar(&referencia.nombre)) {
+            actualizaciones.push((referencia, destino, forzar));
+        }
+    }
+
+    // Se piden los commits que no tenemos; los "have" permiten al servidor mandar solo lo que
+    // falta, con deltas contra objetos que ya están en la base local (un paquete "thin")
+    let mut base = BaseObjetos::abrir(".git");
+    let mut deseados: Vec<String> = Vec::new();
+    for (referencia, _, _) in &actualizaciones {
+        if !base.existe(&referencia.hash) && !deseados.contains(&referencia.hash) {
+            deseados.push(referencia.hash.clone());
+        }
+    }
+    if !deseados.is_empty() {
+        let comunes = commits_locales(&base)?;
+        let cantidad = servidor.descargar_paquete(&deseados, &comunes, &mut base)?;
+        println!("Objetos recibidos: {}", cantidad);
+        // `Commit::leer` usa la base del proceso, que puede tener cargada la lista de paquetes de
+        // antes de la descarga
+        Objeto::recargar_base();
+    }
+
+    let mut lineas = Vec::new();
+    let mut rechazadas = 0;
+    for (referencia, destino, forzar) in &actualizaciones {
+        let anterior = referencias::resolver_referencia(destino)?;
+        let corto_destino = destino
+            .strip_prefix("refs/remotes/")
+            .or_else(|| destino.strip_prefix("refs/heads/"))
+            .unwrap_or(destino);
+        let corto_origen = referencia.nombre.trim_start_matches("refs/heads/");
+        let nuevo = &referencia.hash;
+
+        let (marca, resumen, nota) = match anterior.as_deref() {
+            None => ('*', "[nueva rama]".to_string(), ""),
+            Some(anterior) if anterior == nuevo => continue,
+            Some(anterior) if revision::es_ancestro(anterior, nuevo)? => {
+                (' ', format!("{}..{}", &anterior[..7], &nuevo[..7]), "")
+            }
+            Some(anterior) if *forzar => {
+                ('+', format!("{}...{}", &anterior[..7], &nuevo[..7]), "  (actualización forzada)")
+            }
+            Some(_) => {
+                lineas.push(format!(" ! {:<17} {} -> {}  (no es fast-forward)", "[rechazada]", corto_origen, corto_destino));
+                rechazadas += 1;
+                continue;
+            }
+        };
+        // Solo si nadie la ha movido desde que se comprobó el fast-forward
+        referencias::actualizar_referencia(destino, nuevo, anterior.as_deref())?;
+        lineas.push(format!(" {} {:<17} {} -> {}{}", marca, resumen, corto_origen, corto_destino, nota));
+    }
+
+    // Como git, se traen las etiquetas que apuntan a objetos que ya tenemos; las anotadas las
+    // incluye el servidor en el paquete (capacidad include-tag)
+    for referencia in anunciadas.iter().filter(|r| r.nombre.starts_with("refs/tags/")) {
+        let apuntado = referencia.pelado.as_ref().unwrap_or(&referencia.hash);
+        if referencias::resolver_referencia(&referencia.nombre)?.is_some()
+            || !base.existe(&referencia.hash)
+            || !base.existe(apuntado)
+        {
+            continue;
+        }
+        referencias::actualizar_referencia(&referencia.nombre, &referencia.hash, None)?;
+        let nombre = referencia.nombre.trim_start_matches("refs/tags/");
+        lineas.push(format!(" * {:<17} {} -> {}", "[nueva etiqueta]", nombre, nombre));
+    }
+
+    if !lineas.is_empty() {
+        println!("Desde {}", url);
+        for linea in lineas {
+            println!("{}", linea);
+        }
+    }
+    if rechazadas > 0 {
+        anyhow::bail!("No se actualizaron {} referencias: usa '+' en la especificación fetch para forzarlas", rechazadas);
+    }
+    Ok(())
+}
+
+/// Una especificación de referencias como `+refs/heads/*:refs/remotes/origin/*`
+pub(crate) struct Especificacion {
+    /// Con `+` se aceptan actualizaciones que no son fast-forward
+    forzar: bool,
+    origen: String,
+    destino: String,
+    // Si origen y destino terminan en `*` (entonces se guardan sin él)
+    patron: bool,
+}
+
+impl Especificacion {
+    fn parsear(texto: &str) -> Result<Especificacion> {
+        let (forzar, texto) = match texto.strip_prefix('+') {
+            Some(resto) => (true, resto),
+            None => (false, texto),
+        };
+        let (origen, destino) = texto
+            .split_once(':')
+            .with_context(|| format!("Especificación de referencias inválida: '{}'", texto))?;
+        let patron = origen.ends_with('*');
+        if patron != destino.ends_with('*') || origen.matches('*').count() > 1 || destino.matches('*').count() > 1 {
+            anyhow::bail!("Especificación de referencias no soportada: '{}'", texto);
+        }
+        Ok(Especificacion {
+            forzar,
+            origen: origen.trim_end_matches('*').to_string(),
+            destino: destino.trim_end_matches('*').to_string(),
+            patron,
+        })
+    }
+
+    /// La referencia local que corresponde a una del servidor, si esta especificación la cubre
+    pub(crate) fn aplicar(&self, nombre: &str) -> Option<(String, bool)> {
+        if self.patron {
+            let resto = nombre.strip_prefix(&self.origen)?;
+            Some((format!("{}{}", self.destino, resto), self.forzar))
+        } else if nombre == self.origen {
+            Some((self.destino.clone(), self.forzar))
+        } else {
+            None
+        }
+    }
+}
+
+/// URL y especificaciones `fetch` de `[remote "<nombre>"]` en la configuración
+pub(crate) fn leer_remoto(nombre: &str) -> Result<(String, Vec<Especificacion>)> {
+    let configuracion = Configuracion::cargar()?;
+    let url = configuracion
+        .obtener(&format!("remote.{}.url", nombre))
+        .with_context(|| format!("No existe el remoto '{}' en la configuración", nombre))?
+        .to_string();
+    let mut especificaciones = configuracion
+        .obtener_todos(&format!("remote.{}.fetch", nombre))
+        .into_iter()
+        .map(Especificacion::parsear)
+        .collect::<Result<Vec<_>>>()?;
+    if especificaciones.is_empty() {
+        especificaciones.push(Especificacion::parsear(&format!("+refs/heads/*:refs/remotes/{}/*", nombre))?);
+    }
+    Ok((url, especificaciones))
+}
+
+/// El remoto de la rama actual (`branch.<rama>.remote`), u origin si no tiene
+pub(crate) fn remoto_predeterminado() -> Result<String> {
+    if let Head::Rama(nombre_ref) = referencias::leer_head()? {
+        let rama = nombre_ref.trim_start_matches("refs/heads/");
+        if let Some(remoto) = Configuracion::cargar()?.obtener(&format!("branch.{}.remote", rama)) {
+            return Ok(remoto.to_string());
+        }
+    }
+    Ok("origin".to_string())
+}
+
+// Los commits locales más recientes alcanzables desde cualquier referencia, para anunciarlos
+// como "have". Se recorren por fecha, como git, y se corta en LIMITE_COMUNES.
+fn commits_locales(base: &BaseObjetos) -> Result<Vec<String>> {
+    let mut pendientes = BinaryHeap::new();
+    let mut vistos = HashSet::new();
+    for hash in referencias::listar_referencias("refs/")?.into_values() {
+        if let Some(commit) = pelar_commit(base, &hash)? {
+            if vistos.insert(commit.clone()) {
+                let (_, datos) = base.leer_completo(&commit)?;
+                pendientes.push((Commit::parsear(&datos)?.committer.timestamp, commit));
+            }
+        }
+    }
+
+    let mut comunes = Vec::new();
+    while let Some((_, hash)) = pendientes.pop() {
+        if comunes.len() >= LIMITE_COMUNES {
+            break;
+        }
+        let (_, datos) = base.leer_completo(&hash)?;
+        for padre in Commit::parsear(&datos)?.padres {
+            // Un clon superficial o un repositorio incompleto puede no tener todos los padres
+            if base.existe(&padre) && vistos.insert(padre.clone()) {
+                let (_, datos_padre) = base.leer_completo(&padre)?;
+                pendientes.push((Commit::parsear(&datos_padre)?.committer.timestamp, padre));
+            }
+        }
+        comunes.push(hash);
+    }
+    Ok(comunes)
+}
+
+// Las etiquetas pueden apuntar a otra etiqueta o a algo que no es un commit
+fn pelar_commit(base: &BaseObjetos, hash: &str) -> Result<Option<String>> {
+    let mut hash = hash.to_string();
+    loop {
+        if !base.existe(&hash) {
+            return Ok(None);
+        }
+        let (tipo, datos) = base.leer_completo(&hash)?;
+        match tipo {
+            Tipo::Commit => return Ok(Some(hash)),
+            Tipo::Tag => hash = Etiqueta::parsear(&datos)?.objeto,
+            _ => return Ok(None),
+        }
+    }
+}
@@ -0,0 +1,277 @@
+use crate::base_objetos::{self, BaseObjetos};
+use crate::objetos::Tipo;
+use crate::referencias;
+use anyhow::{Context, Result};
+use std::collections::{HashMap, HashSet};
+
+// Los modos que escribe git hoy. 100664 (de versiones muy antiguas) y 040000 se aceptan con
+// un aviso, como hace `git fsck`; cualquier otro es un error.
+const MODOS_VALIDOS: [&str; 5] = ["100644", "100755", "120000", "40000", "160000"];
+const MODOS_TOLERADOS: [&str; 2] = ["100664", "040000"];
+
+/// Lo que se sabe de un objeto tras comprobarlo: su tipo y los objetos que menciona, con el
+/// tipo que deben tener
+struct Comprobado {
+    tipo: Tipo,
+    enlaces: Vec<(String, Tipo)>,
+}
+
+pub fn ejecutar() -> Result<()> {
+    let base = BaseObjetos::abrir(".git");
+    let mut errores = 0;
+
+    // Reindexar cada paquete comprueba su checksum, los CRC32, que cada objeto se descomprima
+    // y que su .idx corresponda a lo que hay dentro
+    let mut empacados = 0;
+    for paquete in base.paquetes()? {
+        match paquete.verificar(&base) {
+            Ok(cantidad) => empacados += cantidad,
+            Err(e) => {
+                eprintln!("error: {}: {:#}", paquete.ruta().display(), e);
+                errores += 1;
+            }
+        }
+    }
+
+    let sueltos: HashSet<String> = base.sueltos()?.into_iter().collect();
+    let mut objetos: HashMap<String, Comprobado> = HashMap::new();
+    let mut total = 0;
+    for hash in base.iterar()? {
+        total += 1;
+        let leido = if sueltos.contains(&hash) {
+            base.verificar_suelto(&hash)
+        } else {
+            base.leer_completo(&hash).and_then(|(tipo, datos)| {
+                let calculado = base_objetos::calcular_hash(tipo, &datos);
+                if calculado != hash {
+                    anyhow::bail!("el contenido empacado tiene el hash {}", calculado);
+                }
+                Ok((tipo, datos))
+            })
+        };
+        let (tipo, datos) = match leido {
+            Ok(leido) => leido,
+            Err(e) => {
+                eprintln!("error: objeto {} dañado: {:#}", hash, e);
+                errores += 1;
+                continue;
+            }
+        };
+
+        let mut avisos = Vec::new();
+        let enlaces = match tipo {
+            Tipo::Blob => Ok(Vec::new()),
+            Tipo::Arbol => comprobar_arbol(&datos, &mut avisos),
+            Tipo::Commit => comprobar_commit(&datos),
+            Tipo::Tag => comprobar_etiqueta(&datos),
+        };
+        for aviso in avisos {
+            eprintln!("aviso: {} {}: {}", tipo, hash, aviso);
+        }
+        let enlaces = enlaces.unwrap_or_else(|e| {
+            eprintln!("error: {} {}: {:#}", tipo, hash, e);
+            errores += 1;
+            Vec::new()
+        });
+        objetos.insert(hash, Comprobado { tipo, enlaces });
+    }
+
+    // Conectividad: todo lo alcanzable desde las raíces tiene que existir y ser del tipo que
+    // espera quien lo menciona
+    let mut pendientes = Vec::new();
+    for raiz in referencias::raices(&base)? {
+        if objetos.contains_key(&raiz.hash) || base.existe(&raiz.hash) {
+            pendientes.push(raiz.hash);
+        } else {
+            eprintln!("error: {} apunta a {}, que no existe", raiz.origen, raiz.hash);
+            errores += 1;
+        }
+    }
+    let mut alcanzables = HashSet::new();
+    while let Some(hash) = pendientes.pop() {
+        if !alcanzables.insert(hash.clone()) {
+            continue;
+        }
+        // Un objeto dañado ya se ha contado como error: no se puede seguir por él
+        let Some(comprobado) = objetos.get(&hash) else {
+            continue;
+        };
+        for (destino, esperado) in &comprobado.enlaces {
+            match objetos.get(destino) {
+                Some(objeto) if objeto.tipo != *esperado => {
+                    eprintln!(
+                        "error: {} {} menciona {} como {}, pero es un {}",
+                        comprobado.tipo, hash, destino, esperado, objeto.tipo
+                    );
+                    errores += 1;
+                }
+                Some(_) => pendientes.push(destino.clone()),
+                None if base.existe(destino) => {}
+                None => {
+                    eprintln!("error: falta {} {} (mencionado por {} {})", esperado, destino, comprobado.tipo, hash);
+                    errores += 1;
+                }
+            }
+        }
+    }
+
+    // Colgantes son los inalcanzables a los que no menciona ningún otro objeto: las puntas de lo
+    // que se ha quedado sin referencia. No es un error.
+    let mencionados: HashSet<&String> =
+        objetos.values().flat_map(|o| o.enlaces.iter().map(|(hash, _)| hash)).collect();
+    let mut colgantes: Vec<(&String, Tipo)> = objetos
+        .iter()
+        .filter(|(hash, _)| !alcanzables.contains(*hash) && !mencionados.contains(hash))
+        .map(|(hash, objeto)| (hash, objeto.tipo))
+        .collect();
+    colgantes.sort_by(|a, b| a.0.cmp(b.0));
+    for (hash, tipo) in colgantes {
+        println!("colgante {} {}", tipo, hash);
+    }
+
+    println!(
+        "Objetos comprobados: {} ({} sueltos, {} empacados), {} alcanzables",
+        total,
+        sueltos.len(),
+        empacados,
+        alcanzables.len()
+    );
+    if errores > 0 {
+        anyhow::bail!("Se encontraron {} errores: el repositorio está dañado", errores);
+    }
+    Ok(())
+}
+
+// Cada entrada es `<modo> <nombre>\0<hash de 20 bytes>`. Las entradas van ordenadas por nombre
+// comparando los directorios como si terminaran en '/', que es como las ordena git.
+fn comprobar_arbol(datos: &[u8], avisos: &mut Vec<String>) -> Result<Vec<(String, Tipo)>> {
+    let mut enlaces = Vec::new();
+    let mut nombres = HashSet::new();
+    let mut clave_anterior: Option<Vec<u8>> = None;
+    let mut resto = datos;
+    while !resto.is_empty() {
+        let espacio = resto.iter().position(|&b| b == b' ').context("entrada sin modo")?;
+        let modo = String::from_utf8_lossy(&resto[..espacio]).into_owned();
+        let modo = modo.as_str();
+        let nulo = resto.iter().position(|&b| b == 0).context("entrada sin terminar")?;
+        if nulo < espacio || resto.len() < nulo + 21 {
+            anyhow::bail!("entrada truncada");
+        }
+        let nombre = &resto[espacio + 1..nulo];
+        let hash = hex::encode(&resto[nulo + 1..nulo + 21]);
+        resto = &resto[nulo + 21..];
+        let legible = String::from_utf8_lossy(nombre);
+
+        if MODOS_TOLERADOS.contains(&modo) {
+            avisos.push(format!("'{}' tiene el modo no estándar {}", legible, modo));
+        } else if !MODOS_VALIDOS.contains(&modo) {
+            anyhow::bail!("'{}' tiene un modo inválido: '{}'", legible, modo);
+        }
+        if nombre.is_empty() {
+            anyhow::bail!("hay una entrada sin nombre");
+        }
+        if nombre.contains(&b'/') || [&b"."[..], b"..", b".git"].contains(&nombre) {
+            anyhow::bail!("nombre de entrada inválido: '{}'", legible);
+        }
+        if !nombres.insert(nombre) {
+            anyhow::bail!("'{}' aparece dos veces", legible);
+        }
+
+        let es_arbol = modo.trim_start_matches('0') == "40000";
+        let mut clave = nombre.to_vec();
+        if es_arbol {
+            clave.push(b'/');
+        }
+        if clave_anterior.as_ref().is_some_and(|anterior| *anterior > clave) {
+            anyhow::bail!("las entradas no están ordenadas ('{}' va antes)", legible);
+        }
+        clave_anterior = Some(clave);
+
+        match modo {
+            "160000" => {}
+            _ if es_arbol => enlaces.push((hash, Tipo::Arbol)),
+            _ => enlaces.push((hash, Tipo::Blob)),
+        }
+    }
+    Ok(enlaces)
+}
+
+// La cabecera de un commit es `tree`, cero o más `parent`, `author` y `committer`, en ese orden;
+// después puede haber otras (encoding, gpgsig, mergetag...) que no se comprueban
+fn comprobar_commit(datos: &[u8]) -> Result<Vec<(String, Tipo)>> {
+    let mut lineas = cabecera(datos)?.into_iter().peekable();
+    let mut enlaces = vec![(valor_hash(lineas.next(), "tree")?, Tipo::Arbol)];
+    while lineas.peek().is_some_and(|linea| linea.starts_with("parent ")) {
+        enlaces.push((valor_hash(lineas.next(), "parent")?, Tipo::Commit));
+    }
+    comprobar_firma(valor(lineas.next(), "author")?).context("línea 'author' inválida")?;
+    comprobar_firma(valor(lineas.next(), "committer")?).context("línea 'committer' inválida")?;
+    Ok(enlaces)
+}
+
+// `object`, `type` y `tag` en ese orden, y un `tagger` opcional (los tags muy antiguos no lo
+// tienen)
+fn comprobar_etiqueta(datos: &[u8]) -> Result<Vec<(String, Tipo)>> {
+    let mut lineas = cabecera(datos)?.into_iter().peekable();
+    let objeto = valor_hash(lineas.next(), "object")?;
+    let tipo = Tipo::desde_nombre(valor(lineas.next(), "type")?)?;
+    if valor(lineas.next(), "tag")?.is_empty() {
+        anyhow::bail!("la etiqueta no tiene nombre");
+    }
+    if let Some(tagger) = lineas.peek().and_then(|linea| linea.strip_prefix("tagger ")) {
+        comprobar_firma(tagger).context("línea 'tagger' inválida")?;
+    }
+    Ok(vec![(objeto, tipo)])
+}
+
+// Las líneas de la cabecera, hasta la línea en blanco que la separa del mensaje
+fn cabecera(datos: &[u8]) -> Result<Vec<&str>> {
+    let fin = datos
+        .windows(2)
+        .position(|par| par == b"\n\n")
+        .map_or(datos.len(), |posicion| posicion + 1);
+    let texto = std::str::from_utf8(&datos[..fin]).context("la cabecera no es UTF-8")?;
+    if !texto.ends_with('\n') {
+        anyhow::bail!("la cabecera no termina en salto de línea");
+    }
+    Ok(texto.lines().collect())
+}
+
+fn valor<'a>(linea: Option<&'a str>, clave: &str) -> Result<&'a str> {
+    linea
+        .and_then(|linea| linea.strip_prefix(clave)?.strip_prefix(' '))
+        .with_context(|| format!("falta la línea '{}' o no está en su sitio", clave))
+}
+
+fn valor_hash(linea: Option<&str>, clave: &str) -> Result<String> {
+    let hash = valor(linea, clave)?;
+    if hash.len() != 40 || !hash.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        anyhow::bail!("'{}' no es un hash válido en la línea '{}'", hash, clave);
+    }
+    Ok(hash.to_string())
+}
+
+// `Nombre <email> 1700000000 +0100`, sin lo que `Firma::parsear` tolera: fecha sin zona, zona
+// sin signo o con otra longitud, ceros a la izquierda en la fecha
+fn comprobar_firma(firma: &str) -> Result<()> {
+    let (nombre, resto) = firma.split_once('<').context("falta el email")?;
+    if !nombre.is_empty() && !nombre.ends_with(' ') {
+        anyhow::bail!("falta el espacio antes del email");
+    }
+    let (email, resto) = resto.split_once('>').context("el email no se cierra")?;
+    if email.contains('<') || nombre.contains('>') {
+        anyhow::bail!("'<' o '>' fuera de lugar");
+    }
+    let (fecha, zona) = resto
+        .strip_prefix(' ')
+        .and_then(|resto| resto.split_once(' '))
+        .context("falta la fecha o la zona horaria")?;
+    if fecha.is_empty() || !fecha.bytes().all(|b| b.is_ascii_digit()) || (fecha.len() > 1 && fecha.starts_with('0')) {
+        anyhow::bail!("fecha inválida: '{}'", fecha);
+    }
+    let digitos = zona.strip_prefix(['+', '-']).unwrap_or("");
+    if digitos.len() != 4 || !digitos.bytes().all(|b| b.is_ascii_digit()) {
+        anyhow::bail!("zona horaria inválida: '{}'", zona);
+    }
+    Ok(())
+}
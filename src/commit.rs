@@ -1,139 +1,148 @@
-use anyhow::{Context, Result};
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use sha1::{Digest, Sha1};
-use std::fs::{self, File};
-use std::io::Write;
-use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::base_objetos::BaseObjetos;
+use crate::configuracion::Configuracion;
+use crate::fecha;
+use crate::objetos::{Firma, Objeto, Tipo};
+use crate::referencias::{self, Head};
+use anyhow::{bail, Context, Result};
+use std::env;
 
 pub(crate) struct DatosCommit {
     pub(crate) hash_arbol: String,
-    pub(crate) hash_padre: Option<String>,
+    /// Ninguno en el primer commit, uno normalmente y varios en una fusión
+    pub(crate) padres: Vec<String>,
+    pub(crate) autor: Firma,
+    pub(crate) committer: Firma,
+    /// Se guarda tal cual, así que normalmente debe terminar en salto de línea
     pub(crate) mensaje: String,
 }
 
+/// Escribe el objeto commit, después de comprobar que el árbol y los padres existen y son de
+/// ese tipo. No mueve HEAD: para eso está `actualizar_head()`.
 pub(crate) fn crear_commit(datos: &DatosCommit) -> Result<String> {
+    comprobar_tipo(&datos.hash_arbol, Tipo::Arbol)?;
+    for padre in &datos.padres {
+        comprobar_tipo(padre, Tipo::Commit)?;
+    }
+
     // Generar el contenido del commit
     let mut contenido = format!("tree {}\n", datos.hash_arbol);
     
-    // Agregar la referencia al padre si existe
-    if let Some(ref hash_padre) = datos.hash_padre {
+    // Una línea por cada padre, en orden: el primero es la rama en la que se fusiona
+    for hash_padre in &datos.padres {
         contenido.push_str(&format!("parent {}\n", hash_padre));
     }
     
     // Información del autor y committer
-    let autor = obtener_autor()?;
-    let timestamp = obtener_timestamp()?;
-    
-    contenido.push_str(&format!("author {} {}\n", autor, timestamp));
-    contenido.push_str(&format!("committer {} {}\n", autor, timestamp));
-    contenido.push_str("\n");
+    contenido.push_str(&format!("author {}\n", datos.autor));
+    contenido.push_str(&format!("committer {}\n", datos.committer));
+    contenido.push('\n');
     contenido.push_str(&datos.mensaje);
-    contenido.push_str("\n");
-    
-    // Calcular el hash SHA-1 del commit
-    let mut hasher = Sha1::new();
-    hasher.update(format!("commit {}\0", contenido.len()));
-    hasher.update(&contenido);
-    let hash = hasher.finalize();
-    let hash_str = hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
     
     // Escribir el objeto commit
-    let objeto_dir = format!(".git/objects/{}", &hash_str[..2]);
-    fs::create_dir_all(&objeto_dir)?;
-    
-    let objeto_ruta = format!("{}/{}", objeto_dir, &hash_str[2..]);
-    if !Path::new(&objeto_ruta).exists() {
-        let objeto_archivo = File::create(&objeto_ruta)?;
-        let mut encoder = ZlibEncoder::new(objeto_archivo, Compression::default());
-        
-        // Escribir el encabezado
-        write!(encoder, "commit {}\0", contenido.len())?;
-        
-        // Escribir el contenido
-        encoder.write_all(contenido.as_bytes())?;
-        encoder.finish()?;
+    BaseObjetos::abrir(".git").escribir(Tipo::Commit, contenido.as_bytes())
+}
+
+fn comprobar_tipo(hash: &str, esperado: Tipo) -> Result<()> {
+    let tipo = Objeto::leer(hash)
+        .with_context(|| format!("El objeto {} no existe", hash))?
+        .tipo;
+    if tipo != esperado {
+        bail!("El objeto {} es un {}, no un {}", hash, tipo, esperado);
     }
-    
-    // Actualizar la referencia HEAD
-    actualizar_head(&hash_str)?;
-    
-    Ok(hash_str)
+    Ok(())
 }
 
-fn obtener_autor() -> Result<String> {
-    // Intentar obtener el nombre y email de la configuración de Git
-    let config_global = dirs::home_dir()
-        .map(|home| home.join(".gitconfig"))
-        .filter(|path| path.exists());
-    
-    if let Some(config_path) = config_global {
-        if let Ok(contenido) = fs::read_to_string(config_path) {
-            // Buscar la sección [user]
-            let nombre = contenido.lines()
-                .skip_while(|line| !line.contains("[user]"))
-                .take_while(|line| !line.contains("["))
-                .find_map(|line| {
-                    if line.contains("name =") {
-                        Some(line.split('=').nth(1)?.trim().to_string())
-                    } else {
-                        None
-                    }
-                });
-            
-            let email = contenido.lines()
-                .skip_while(|line| !line.contains("[user]"))
-                .take_while(|line| !line.contains("["))
-                .find_map(|line| {
-                    if line.contains("email =") {
-                        Some(line.split('=').nth(1)?.trim().to_string())
-                    } else {
-                        None
-                    }
-                });
-            
-            if let (Some(nombre), Some(email)) = (nombre, email) {
-                return Ok(format!("{} <{}>", nombre, email));
-            }
+/// Quién firma un objeto: el autor del cambio o quien lo guarda en el repositorio (el committer,
+/// que también es quien firma las etiquetas anotadas)
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Rol {
+    Autor,
+    Committer,
+}
+
+impl Rol {
+    // Prefijo de sus variables de entorno y sección de la configuración
+    fn nombres(self) -> (&'static str, &'static str) {
+        match self {
+            Rol::Autor => ("GIT_AUTHOR", "author"),
+            Rol::Committer => ("GIT_COMMITTER", "committer"),
         }
     }
-    
-    // Si no se pudo obtener de la configuración, usar un valor predeterminado
-    Ok("Usuario Git <usuario@ejemplo.com>".to_string())
 }
 
-fn obtener_timestamp() -> Result<String> {
-    let segundos = SystemTime::now()
-        .duration_since(UNIX_EPOCH)?
-        .as_secs();
-    
-    // Por simplicidad, usamos UTC (zona horaria +0000)
-    let zona_horaria = "+0000";
-    
-    Ok(format!("{} {}", segundos, zona_horaria))
+/// La firma de `rol` como la calcula git. El nombre y el email salen de GIT_AUTHOR_NAME y
+/// GIT_AUTHOR_EMAIL (GIT_COMMITTER_* para el committer), de author.name y author.email
+/// (committer.*) o de user.name y user.email; la fecha, de GIT_AUTHOR_DATE (GIT_COMMITTER_DATE)
+/// o del reloj, con la zona horaria local. `identidad` (`Nombre <email>`) y `fecha` tienen
+/// prioridad sobre todo lo anterior.
+pub(crate) fn firma(rol: Rol, identidad: Option<&str>, fecha: Option<&str>) -> Result<Firma> {
+    let (entorno, seccion) = rol.nombres();
+    let (nombre, email) = match identidad {
+        Some(identidad) => parsear_identidad(identidad)?,
+        None => {
+            let configuracion = Configuracion::cargar()?;
+            let buscar = |campo: &str, clave: &str| {
+                env::var(format!("{}_{}", entorno, campo))
+                    .ok()
+                    .or_else(|| configuracion.obtener(&format!("{}.{}", seccion, clave)).map(str::to_string))
+                    .or_else(|| configuracion.obtener(&format!("user.{}", clave)).map(str::to_string))
+                    .map(|valor| limpiar(&valor))
+                    .filter(|valor| !valor.is_empty())
+            };
+            let nombre = buscar("NAME", "name");
+            let email = buscar("EMAIL", "email").or_else(|| env::var("EMAIL").ok().map(|email| limpiar(&email)));
+            let (Some(nombre), Some(email)) = (nombre, email) else {
+                let quien = match rol {
+                    Rol::Autor => "el autor",
+                    Rol::Committer => "quien hace el commit",
+                };
+                bail!(
+                    "No se sabe quién es {}. Configúralo con:\n\n  configuracion --global user.name \"Tu Nombre\"\n  configuracion --global user.email tu@ejemplo.com",
+                    quien
+                );
+            };
+            (nombre, email)
+        }
+    };
+
+    let fecha = fecha.map(str::to_string).or_else(|| env::var(format!("{}_DATE", entorno)).ok());
+    let (timestamp, desplazamiento) = match fecha {
+        Some(fecha) => fecha::parsear(&fecha)?,
+        None => fecha::actual()?,
+    };
+    Ok(Firma {
+        nombre,
+        email,
+        timestamp,
+        zona_horaria: fecha::zona_horaria(desplazamiento),
+    })
 }
 
-fn actualizar_head(hash_commit: &str) -> Result<()> {
-    // Leer el archivo HEAD para determinar a qué referencia apunta
-    let head_contenido = fs::read_to_string(".git/HEAD")
-        .context("No se pudo leer el archivo .git/HEAD")?;
-    
-    if head_contenido.starts_with("ref: ") {
-        // HEAD apunta a una referencia (rama)
-        let ref_path = head_contenido.trim_start_matches("ref: ").trim();
-        let ref_dir = Path::new(ref_path).parent()
-            .context("Formato de referencia inválido en HEAD")?;
-        
-        // Crear el directorio si no existe
-        fs::create_dir_all(format!(".git/{}", ref_dir.display()))?;
-        
-        // Escribir el hash del commit en la referencia
-        fs::write(format!(".git/{}", ref_path), format!("{}\n", hash_commit))?;
-    } else {
+// `Nombre <email>`, como en `--author`
+fn parsear_identidad(texto: &str) -> Result<(String, String)> {
+    let error = || format!("Autor no válido: '{}' (usa \"Nombre <email>\")", texto);
+    let (nombre, resto) = texto.split_once('<').with_context(error)?;
+    let email = resto.trim_end().strip_suffix('>').with_context(error)?;
+    let (nombre, email) = (limpiar(nombre), limpiar(email));
+    if nombre.is_empty() {
+        bail!(error());
+    }
+    Ok((nombre, email))
+}
+
+// Como git, quita de un nombre o un email los caracteres que romperían la firma ('<', '>' y
+// saltos de línea) y los espacios de los extremos
+fn limpiar(texto: &str) -> String {
+    texto.replace(['<', '>', '\n'], "").trim().to_string()
+}
+
+/// Mueve HEAD (o la rama a la que apunta) a un commit, siempre que siga donde estaba cuando se
+/// leyó (`anterior`, None si la rama todavía no tenía commits)
+pub(crate) fn actualizar_head(hash_commit: &str, anterior: Option<&str>) -> Result<()> {
+    match referencias::leer_head()? {
+        // HEAD apunta a una rama: se mueve la rama
+        Head::Rama(nombre_ref) => referencias::actualizar_referencia(&nombre_ref, hash_commit, anterior),
         // HEAD está en estado detached, solo actualizar HEAD
-        fs::write(".git/HEAD", format!("{}\n", hash_commit))?;
+        Head::Separado(_) => referencias::actualizar_referencia("HEAD", hash_commit, anterior),
     }
-    
-    Ok(())
 }
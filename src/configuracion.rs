@@ -0,0 +1,734 @@
+use crate::bloqueo::Bloqueo;
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Como git, más de 10 include.path anidados se toman por un ciclo
+const PROFUNDIDAD_INCLUDES: usize = 10;
+
+/// De dónde sale una variable. Se leen en este orden y, si se repite una variable, manda la
+/// última leída.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Ambito {
+    /// /etc/gitconfig (o $GIT_CONFIG_SYSTEM); no se lee con GIT_CONFIG_NOSYSTEM
+    Sistema,
+    /// $XDG_CONFIG_HOME/git/config y ~/.gitconfig (o solo $GIT_CONFIG_GLOBAL)
+    Global,
+    /// .git/config
+    Local,
+}
+
+impl Ambito {
+    // Los archivos del ámbito, de menor a mayor prioridad
+    fn archivos(self) -> Vec<PathBuf> {
+        match self {
+            Ambito::Sistema => {
+                let desactivado = env::var("GIT_CONFIG_NOSYSTEM")
+                    .is_ok_and(|valor| parsear_bool(Some(&valor)).unwrap_or(false));
+                if desactivado {
+                    return Vec::new();
+                }
+                vec![env::var_os("GIT_CONFIG_SYSTEM").map_or(PathBuf::from("/etc/gitconfig"), PathBuf::from)]
+            }
+            Ambito::Global => {
+                if let Some(ruta) = env::var_os("GIT_CONFIG_GLOBAL") {
+                    return vec![PathBuf::from(ruta)];
+                }
+                let xdg = directorio_xdg().map(|directorio| directorio.join("git/config"));
+                let personal = dirs::home_dir().map(|home| home.join(".gitconfig"));
+                xdg.into_iter().chain(personal).collect()
+            }
+            Ambito::Local => vec![PathBuf::from(".git/config")],
+        }
+    }
+
+    /// El archivo en el que se escriben las variables de este ámbito
+    pub(crate) fn archivo(self) -> Result<PathBuf> {
+        match self {
+            Ambito::Global => env::var_os("GIT_CONFIG_GLOBAL")
+                .map(PathBuf::from)
+                .or_else(|| dirs::home_dir().map(|home| home.join(".gitconfig")))
+                .context("No se encuentra el directorio personal"),
+            // Sin esto, el .lock crearía un .git nuevo fuera de un repositorio
+            Ambito::Local if !Path::new(".git").is_dir() => anyhow::bail!("No estás en un repositorio git"),
+            _ => self.archivos().pop().context("La configuración del sistema está desactivada"),
+        }
+    }
+}
+
+/// Una variable leída: el nombre normalizado (`seccion.subseccion.clave`, con la sección y la
+/// clave en minúsculas) y su valor, que es None si la variable aparece sin '='
+pub(crate) struct Entrada {
+    pub(crate) nombre: String,
+    pub(crate) valor: Option<String>,
+}
+
+/// Todas las variables de los archivos leídos, en orden
+pub(crate) struct Configuracion {
+    entradas: Vec<Entrada>,
+}
+
+impl Configuracion {
+    /// La configuración del sistema, la global y la del repositorio, por ese orden
+    pub(crate) fn cargar() -> Result<Configuracion> {
+        let mut configuracion = Configuracion { entradas: Vec::new() };
+        for ambito in [Ambito::Sistema, Ambito::Global, Ambito::Local] {
+            configuracion.leer_ambito(ambito)?;
+        }
+        Ok(configuracion)
+    }
+
+    /// Solo los archivos de un ámbito y, como `git config --local` y compañía, sin seguir los
+    /// include.path
+    pub(crate) fn cargar_ambito(ambito: Ambito) -> Result<Configuracion> {
+        let mut configuracion = Configuracion { entradas: Vec::new() };
+        for archivo in ambito.archivos() {
+            configuracion.leer_archivo(&archivo, None)?;
+        }
+        Ok(configuracion)
+    }
+
+    fn leer_ambito(&mut self, ambito: Ambito) -> Result<()> {
+        for archivo in ambito.archivos() {
+            self.leer_archivo(&archivo, Some(0))?;
+        }
+        Ok(())
+    }
+
+    // Un archivo que no existe no aporta nada. Los include.path se leen en el punto en que
+    // aparecen, así que lo que viene después en el archivo que incluye tiene prioridad. Con
+    // `profundidad` a None no se siguen.
+    fn leer_archivo(&mut self, ruta: &Path, profundidad: Option<usize>) -> Result<()> {
+        let Ok(contenido) = fs::read(ruta) else {
+            return Ok(());
+        };
+        let texto = String::from_utf8_lossy(&contenido);
+        for elemento in analizar(&texto, ruta)? {
+            let Elemento::Variable(variable) = elemento else {
+                continue;
+            };
+            let nombre = variable.nombre();
+            let incluido = match (&variable.valor, nombre.as_str(), profundidad) {
+                (Some(valor), "include.path", Some(profundidad)) => Some((expandir_ruta(valor)?, profundidad)),
+                _ => None,
+            };
+            self.entradas.push(Entrada {
+                nombre,
+                valor: variable.valor,
+            });
+
+            if let Some((incluido, profundidad)) = incluido {
+                if profundidad >= PROFUNDIDAD_INCLUDES {
+                    anyhow::bail!("Demasiados include.path anidados en {}: ¿hay un ciclo?", ruta.display());
+                }
+                // Una ruta relativa es relativa al archivo que la incluye
+                let incluido = ruta.parent().map_or(incluido.clone(), |directorio| directorio.join(&incluido));
+                self.leer_archivo(&incluido, Some(profundidad + 1))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn entradas(&self) -> &[Entrada] {
+        &self.entradas
+    }
+
+    /// El último valor de una variable. Una variable sin '=' vale "".
+    pub(crate) fn obtener(&self, nombre: &str) -> Option<&str> {
+        self.buscar(nombre).last().map(|entrada| entrada.valor.as_deref().unwrap_or(""))
+    }
+
+    /// Todos los valores de una variable que puede repetirse (como `remote.<nombre>.fetch`)
+    pub(crate) fn obtener_todos(&self, nombre: &str) -> Vec<&str> {
+        self.buscar(nombre)
+            .map(|entrada| entrada.valor.as_deref().unwrap_or(""))
+            .collect()
+    }
+
+    pub(crate) fn obtener_bool(&self, nombre: &str) -> Result<Option<bool>> {
+        match self.buscar(nombre).last() {
+            Some(entrada) => parsear_bool(entrada.valor.as_deref())
+                .map(Some)
+                .with_context(|| format!("Valor booleano inválido para {}", entrada.nombre)),
+            None => Ok(None),
+        }
+    }
+
+    /// Una ruta, expandiendo `~/` al directorio personal
+    pub(crate) fn obtener_ruta(&self, nombre: &str) -> Result<Option<PathBuf>> {
+        self.obtener(nombre).map(expandir_ruta).transpose()
+    }
+
+    fn buscar<'a>(&'a self, nombre: &str) -> impl Iterator<Item = &'a Entrada> {
+        let nombre = normalizar_nombre(nombre).unwrap_or_default();
+        self.entradas.iter().filter(move |entrada| entrada.nombre == nombre)
+    }
+}
+
+/// Da a `nombre` el valor `valor` en el archivo del ámbito. Sin `agregar`, reemplaza el valor
+/// que hubiera (y falla si hay varios); con `agregar`, añade uno más. El resto del archivo,
+/// comentarios incluidos, queda como estaba.
+pub(crate) fn asignar(ambito: Ambito, nombre: &str, valor: &str, agregar: bool) -> Result<()> {
+    let ruta = ambito.archivo()?;
+    let mut bloqueo = Bloqueo::adquirir(&ruta)?;
+    let texto = asignar_en_texto(leer_para_modificar(&ruta)?, &ruta, nombre, valor, agregar)?;
+    bloqueo.escribir(texto.as_bytes())?;
+    bloqueo.confirmar()
+}
+
+// `asignar` sobre el contenido de un archivo; la ruta solo sirve para los mensajes de error
+fn asignar_en_texto(mut texto: String, ruta: &Path, nombre: &str, valor: &str, agregar: bool) -> Result<String> {
+    let nombre = normalizar_nombre(nombre)?;
+    let (seccion, subseccion, clave) = partir_nombre(&nombre);
+    let elementos = analizar(&texto, ruta)?;
+
+    let linea = format!("{} = {}", clave, formatear_valor(valor));
+    let existentes: Vec<&Variable> = variables(&elementos).filter(|v| v.nombre() == nombre).collect();
+    match existentes.as_slice() {
+        [unica] if !agregar => texto.replace_range(unica.inicio..unica.fin, &linea),
+        [_, _, ..] if !agregar => anyhow::bail!(
+            "{} tiene varios valores: usa --add para añadir otro o --unset-all para quitarlos",
+            nombre
+        ),
+        _ => {
+            // Después de la última variable de la sección o, si no tiene, de su cabecera
+            let posicion = variables(&elementos)
+                .filter(|v| v.seccion == seccion && v.subseccion.as_deref() == subseccion)
+                .map(|v| v.fin)
+                .last()
+                .or_else(|| {
+                    elementos.iter().rev().find_map(|elemento| match elemento {
+                        Elemento::Cabecera { seccion: s, subseccion: sub, fin }
+                            if *s == seccion && sub.as_deref() == subseccion =>
+                        {
+                            Some(*fin)
+                        }
+                        _ => None,
+                    })
+                });
+            let mut nuevo = String::new();
+            let posicion = match posicion {
+                Some(posicion) => fin_de_linea(&texto, posicion),
+                None => {
+                    nuevo.push_str(&match subseccion {
+                        Some(subseccion) => format!("[{} \"{}\"]\n", seccion, escapar_subseccion(subseccion)),
+                        None => format!("[{}]\n", seccion),
+                    });
+                    texto.len()
+                }
+            };
+            if posicion == texto.len() && !texto.is_empty() && !texto.ends_with('\n') {
+                nuevo.insert(0, '\n');
+            }
+            nuevo.push_str(&format!("\t{}\n", linea));
+            texto.insert_str(posicion, &nuevo);
+        }
+    }
+    Ok(texto)
+}
+
+/// Quita `nombre` del archivo del ámbito. Sin `todos`, falla si tiene varios valores. Devuelve
+/// cuántos valores se quitaron.
+pub(crate) fn quitar(ambito: Ambito, nombre: &str, todos: bool) -> Result<usize> {
+    let ruta = ambito.archivo()?;
+    let mut bloqueo = Bloqueo::adquirir(&ruta)?;
+    let (texto, quitados) = quitar_en_texto(leer_para_modificar(&ruta)?, &ruta, nombre, todos)?;
+    if quitados > 0 {
+        bloqueo.escribir(texto.as_bytes())?;
+        bloqueo.confirmar()?;
+    }
+    Ok(quitados)
+}
+
+// `quitar` sobre el contenido de un archivo: el texto resultante y cuántos valores se quitaron
+fn quitar_en_texto(mut texto: String, ruta: &Path, nombre: &str, todos: bool) -> Result<(String, usize)> {
+    let nombre = normalizar_nombre(nombre)?;
+    let elementos = analizar(&texto, ruta)?;
+
+    let existentes: Vec<&Variable> = variables(&elementos).filter(|v| v.nombre() == nombre).collect();
+    if existentes.len() > 1 && !todos {
+        anyhow::bail!("{} tiene varios valores: usa --unset-all para quitarlos todos", nombre);
+    }
+    // De atrás hacia delante, para que las posiciones que quedan sigan valiendo
+    for variable in existentes.iter().rev() {
+        let inicio_linea = texto[..variable.inicio].rfind('\n').map_or(0, |posicion| posicion + 1);
+        let fin_linea = fin_de_linea(&texto, variable.fin);
+        let antes = &texto[inicio_linea..variable.inicio];
+        let despues = texto[variable.fin..fin_linea].trim();
+        if antes.trim().is_empty() && (despues.is_empty() || despues.starts_with(['#', ';'])) {
+            texto.replace_range(inicio_linea..fin_linea, "");
+        } else {
+            texto.replace_range(variable.inicio..variable.fin, "");
+        }
+    }
+
+    Ok((texto, existentes.len()))
+}
+
+/// Comprueba un nombre de variable y lo normaliza: la sección y la clave no distinguen
+/// mayúsculas y la subsección sí
+pub(crate) fn normalizar_nombre(nombre: &str) -> Result<String> {
+    let error = || format!("Nombre de variable inválido: '{}' (tiene que ser seccion.clave)", nombre);
+    let (seccion, resto) = nombre.split_once('.').with_context(error)?;
+    let (subseccion, clave) = match resto.rsplit_once('.') {
+        Some((subseccion, clave)) => (Some(subseccion), clave),
+        None => (None, resto),
+    };
+    let valido = |texto: &str| !texto.is_empty() && texto.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+    if !valido(seccion) || !valido(clave) || !clave.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        anyhow::bail!(error());
+    }
+    let seccion = seccion.to_ascii_lowercase();
+    let clave = clave.to_ascii_lowercase();
+    Ok(match subseccion {
+        Some(subseccion) => format!("{}.{}.{}", seccion, subseccion, clave),
+        None => format!("{}.{}", seccion, clave),
+    })
+}
+
+/// true, yes, on y cualquier número distinto de 0 son verdaderos; false, no, off, 0 y el valor
+/// vacío, falsos. Una variable sin '=' es verdadera.
+pub(crate) fn parsear_bool(valor: Option<&str>) -> Result<bool> {
+    let Some(valor) = valor else {
+        return Ok(true);
+    };
+    match valor.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" | "" => Ok(false),
+        numero => match numero.parse::<i64>() {
+            Ok(numero) => Ok(numero != 0),
+            Err(_) => anyhow::bail!("'{}' no es un booleano", valor),
+        },
+    }
+}
+
+/// $XDG_CONFIG_HOME, o ~/.config si no está definido
+pub(crate) fn directorio_xdg() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .filter(|valor| !valor.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")))
+}
+
+fn expandir_ruta(valor: &str) -> Result<PathBuf> {
+    match valor.strip_prefix('~') {
+        Some(resto) if resto.is_empty() || resto.starts_with('/') => {
+            let home = dirs::home_dir().context("No se encuentra el directorio personal")?;
+            Ok(home.join(resto.trim_start_matches('/')))
+        }
+        _ => Ok(PathBuf::from(valor)),
+    }
+}
+
+fn leer_para_modificar(ruta: &Path) -> Result<String> {
+    match fs::read(ruta) {
+        Ok(contenido) => String::from_utf8(contenido).with_context(|| format!("{} no es UTF-8", ruta.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e).with_context(|| format!("No se pudo leer {}", ruta.display())),
+    }
+}
+
+// (sección, subsección, clave) de un nombre ya normalizado
+fn partir_nombre(nombre: &str) -> (&str, Option<&str>, &str) {
+    let (seccion, resto) = nombre.split_once('.').expect("nombre normalizado");
+    match resto.rsplit_once('.') {
+        Some((subseccion, clave)) => (seccion, Some(subseccion), clave),
+        None => (seccion, None, resto),
+    }
+}
+
+// Las comillas hacen falta para conservar espacios al principio o al final y para '#' y ';',
+// que si no empezarían un comentario
+fn formatear_valor(valor: &str) -> String {
+    let mut escapado = String::new();
+    for c in valor.chars() {
+        match c {
+            '\\' => escapado.push_str("\\\\"),
+            '"' => escapado.push_str("\\\""),
+            '\n' => escapado.push_str("\\n"),
+            '\t' => escapado.push_str("\\t"),
+            c => escapado.push(c),
+        }
+    }
+    if valor.starts_with(' ') || valor.ends_with(' ') || valor.contains(['#', ';']) {
+        format!("\"{}\"", escapado)
+    } else {
+        escapado
+    }
+}
+
+fn escapar_subseccion(subseccion: &str) -> String {
+    subseccion.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// La posición después del siguiente salto de línea (o el final del texto)
+fn fin_de_linea(texto: &str, desde: usize) -> usize {
+    texto[desde..].find('\n').map_or(texto.len(), |posicion| desde + posicion + 1)
+}
+
+enum Elemento {
+    Cabecera {
+        seccion: String,
+        subseccion: Option<String>,
+        /// Posición siguiente a ']'
+        fin: usize,
+    },
+    Variable(Variable),
+}
+
+/// Una variable tal como está en el archivo, con lo que ocupa (desde la clave hasta el final
+/// del valor, sin el comentario) para poder reemplazarla o quitarla
+struct Variable {
+    seccion: String,
+    subseccion: Option<String>,
+    clave: String,
+    valor: Option<String>,
+    inicio: usize,
+    fin: usize,
+}
+
+impl Variable {
+    fn nombre(&self) -> String {
+        match &self.subseccion {
+            Some(subseccion) => format!("{}.{}.{}", self.seccion, subseccion, self.clave),
+            None => format!("{}.{}", self.seccion, self.clave),
+        }
+    }
+}
+
+fn variables(elementos: &[Elemento]) -> impl Iterator<Item = &Variable> {
+    elementos.iter().filter_map(|elemento| match elemento {
+        Elemento::Variable(variable) => Some(variable),
+        Elemento::Cabecera { .. } => None,
+    })
+}
+
+// La sintaxis de git-config: cabeceras `[seccion]`, `[seccion "subseccion"]` o la antigua
+// `[seccion.subseccion]`, variables `clave = valor` o solo `clave`, y comentarios con '#' o ';'
+fn analizar(texto: &str, archivo: &Path) -> Result<Vec<Elemento>> {
+    let bytes = texto.as_bytes();
+    let error = |linea: usize| anyhow::anyhow!("Línea {} inválida en {}", linea, archivo.display());
+    let mut elementos = Vec::new();
+    let mut seccion: Option<(String, Option<String>)> = None;
+    let mut linea = 1;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\n' => {
+                linea += 1;
+                i += 1;
+            }
+            c if c.is_ascii_whitespace() => i += 1,
+            b'#' | b';' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'[' => {
+                let (nombre, subseccion, fin) = analizar_cabecera(texto, i).ok_or_else(|| error(linea))?;
+                seccion = Some((nombre.clone(), subseccion.clone()));
+                elementos.push(Elemento::Cabecera {
+                    seccion: nombre,
+                    subseccion,
+                    fin,
+                });
+                i = fin;
+            }
+            c if c.is_ascii_alphabetic() => {
+                let Some((nombre_seccion, subseccion)) = &seccion else {
+                    return Err(error(linea));
+                };
+                let inicio = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+                    i += 1;
+                }
+                let clave = texto[inicio..i].to_ascii_lowercase();
+                let mut fin = i;
+                while i < bytes.len() && matches!(bytes[i], b' ' | b'\t' | b'\r') {
+                    i += 1;
+                }
+                let valor = match bytes.get(i) {
+                    Some(b'=') => {
+                        let (valor, fin_valor, lineas) = analizar_valor(bytes, i + 1).ok_or_else(|| error(linea))?;
+                        linea += lineas;
+                        i = fin_valor;
+                        fin = fin_valor;
+                        Some(valor)
+                    }
+                    None | Some(b'\n' | b'#' | b';') => None,
+                    Some(_) => return Err(error(linea)),
+                };
+                elementos.push(Elemento::Variable(Variable {
+                    seccion: nombre_seccion.clone(),
+                    subseccion: subseccion.clone(),
+                    clave,
+                    valor,
+                    inicio,
+                    fin,
+                }));
+            }
+            _ => return Err(error(linea)),
+        }
+    }
+    Ok(elementos)
+}
+
+// (sección, subsección, posición siguiente a ']'), o None si la cabecera está mal formada
+fn analizar_cabecera(texto: &str, inicio: usize) -> Option<(String, Option<String>, usize)> {
+    let bytes = texto.as_bytes();
+    let mut i = inicio + 1;
+    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-' || bytes[i] == b'.') {
+        i += 1;
+    }
+    let nombre = texto[inicio + 1..i].to_ascii_lowercase();
+    if nombre.is_empty() {
+        return None;
+    }
+    match bytes.get(i)? {
+        // La forma antigua `[seccion.subseccion]` no distingue mayúsculas en la subsección
+        b']' => Some(match nombre.split_once('.') {
+            Some((seccion, subseccion)) => (seccion.to_string(), Some(subseccion.to_string()), i + 1),
+            None => (nombre, None, i + 1),
+        }),
+        b' ' | b'\t' if !nombre.contains('.') => {
+            while matches!(bytes.get(i), Some(b' ' | b'\t')) {
+                i += 1;
+            }
+            if bytes.get(i) != Some(&b'"') {
+                return None;
+            }
+            i += 1;
+            let mut subseccion = Vec::new();
+            loop {
+                match *bytes.get(i)? {
+                    b'"' => break,
+                    b'\n' => return None,
+                    b'\\' => {
+                        i += 1;
+                        subseccion.push(*bytes.get(i).filter(|&&c| c != b'\n')?);
+                    }
+                    c => subseccion.push(c),
+                }
+                i += 1;
+            }
+            if bytes.get(i + 1) != Some(&b']') {
+                return None;
+            }
+            Some((nombre, Some(String::from_utf8_lossy(&subseccion).into_owned()), i + 2))
+        }
+        _ => None,
+    }
+}
+
+// El valor que empieza en `inicio` (justo después del '='): (valor, posición siguiente a su
+// último carácter, líneas de continuación). Fuera de comillas, los espacios del principio y del final se
+// descartan y los de en medio se conservan; las comillas no forman parte del valor; '\' escapa
+// \n, \t, \b, '\' y '"', y al final de una línea la continúa en la siguiente.
+fn analizar_valor(bytes: &[u8], inicio: usize) -> Option<(String, usize, usize)> {
+    let mut valor = Vec::new();
+    let mut espacios = 0;
+    let mut comillas = false;
+    let mut lineas = 0;
+    let mut i = inicio;
+    let mut fin = inicio;
+    while let Some(&c) = bytes.get(i) {
+        if c == b'\r' && bytes.get(i + 1) == Some(&b'\n') {
+            i += 1;
+            continue;
+        }
+        if c == b'\n' || (!comillas && (c == b'#' || c == b';')) {
+            break;
+        }
+        if !comillas && c.is_ascii_whitespace() {
+            if !valor.is_empty() {
+                espacios += 1;
+            }
+            i += 1;
+            continue;
+        }
+        valor.resize(valor.len() + espacios, b' ');
+        espacios = 0;
+        match c {
+            b'\\' => {
+                let mut escapado = *bytes.get(i + 1)?;
+                if escapado == b'\r' && bytes.get(i + 2) == Some(&b'\n') {
+                    i += 1;
+                    escapado = b'\n';
+                }
+                match escapado {
+                    b'\n' => lineas += 1,
+                    b'n' => valor.push(b'\n'),
+                    b't' => valor.push(b'\t'),
+                    b'b' => valor.push(0x08),
+                    b'\\' | b'"' => valor.push(escapado),
+                    _ => return None,
+                }
+                i += 2;
+            }
+            b'"' => {
+                comillas = !comillas;
+                i += 1;
+            }
+            c => {
+                valor.push(c);
+                i += 1;
+            }
+        }
+        fin = i;
+    }
+    if comillas {
+        return None;
+    }
+    Some((String::from_utf8_lossy(&valor).into_owned(), fin, lineas))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn configuracion(texto: &str) -> Configuracion {
+        let entradas = analizar(texto, Path::new("prueba"))
+            .unwrap()
+            .into_iter()
+            .filter_map(|elemento| match elemento {
+                Elemento::Variable(variable) => Some(Entrada {
+                    nombre: variable.nombre(),
+                    valor: variable.valor,
+                }),
+                Elemento::Cabecera { .. } => None,
+            })
+            .collect();
+        Configuracion { entradas }
+    }
+
+    fn asignar_texto(texto: &str, nombre: &str, valor: &str, agregar: bool) -> Result<String> {
+        asignar_en_texto(texto.to_string(), Path::new("prueba"), nombre, valor, agregar)
+    }
+
+    fn quitar_texto(texto: &str, nombre: &str, todos: bool) -> Result<(String, usize)> {
+        quitar_en_texto(texto.to_string(), Path::new("prueba"), nombre, todos)
+    }
+
+    #[test]
+    fn comillas_y_comentarios() {
+        let configuracion = configuracion(
+            "[prueba]\n\
+             \tsimple = hola mundo   # comentario\n\
+             \tentre = \"  con # y ; dentro  \"\n\
+             \tmezcla = a\" b \"c ; otro comentario\n",
+        );
+        assert_eq!(configuracion.obtener("prueba.simple"), Some("hola mundo"));
+        assert_eq!(configuracion.obtener("prueba.entre"), Some("  con # y ; dentro  "));
+        assert_eq!(configuracion.obtener("prueba.mezcla"), Some("a b c"));
+    }
+
+    #[test]
+    fn escapes() {
+        let configuracion = configuracion("[prueba]\n\tvalor = a\\tb\\nc\\\\d\\\"e\\bf\n");
+        assert_eq!(configuracion.obtener("prueba.valor"), Some("a\tb\nc\\d\"e\x08f"));
+        assert!(analizar("[prueba]\n\tvalor = a\\qb\n", Path::new("prueba")).is_err());
+        assert!(analizar("[prueba]\n\tvalor = \"sin cerrar\n", Path::new("prueba")).is_err());
+    }
+
+    #[test]
+    fn continuaciones() {
+        let configuracion = configuracion("[prueba]\n\tvalor = uno \\\n  dos\\\r\ntres\n\tsiguiente = x\n");
+        assert_eq!(configuracion.obtener("prueba.valor"), Some("uno   dostres"));
+        assert_eq!(configuracion.obtener("prueba.siguiente"), Some("x"));
+    }
+
+    #[test]
+    fn subsecciones() {
+        let configuracion = configuracion(
+            "[Remote \"Origen\"]\n\turl = a\n\
+             [remote.Viejo]\n\turl = b\n\
+             [rama \"con \\\"comillas\\\" y \\\\\"]\n\tx = c\n",
+        );
+        // La sección y la clave no distinguen mayúsculas; la subsección sí
+        assert_eq!(configuracion.obtener("REMOTE.Origen.URL"), Some("a"));
+        assert_eq!(configuracion.obtener("remote.origen.url"), None);
+        // La forma antigua pasa la subsección a minúsculas
+        assert_eq!(configuracion.obtener("remote.viejo.url"), Some("b"));
+        assert_eq!(configuracion.obtener("rama.con \"comillas\" y \\.x"), Some("c"));
+    }
+
+    #[test]
+    fn varios_valores_y_booleanos() {
+        let configuracion = configuracion(
+            "[remote \"origin\"]\n\
+             \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+             \tfetch = +refs/tags/*:refs/tags/*\n\
+             [core]\n\tbare\n\tfileMode = off\n\tfileMode = 1\n\traro = quizá\n",
+        );
+        assert_eq!(
+            configuracion.obtener_todos("remote.origin.fetch"),
+            ["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"]
+        );
+        assert_eq!(configuracion.obtener("remote.origin.fetch"), Some("+refs/tags/*:refs/tags/*"));
+        // Sin '=' es verdadera; manda el último valor
+        assert_eq!(configuracion.obtener_bool("core.bare").unwrap(), Some(true));
+        assert_eq!(configuracion.obtener_bool("core.filemode").unwrap(), Some(true));
+        assert_eq!(configuracion.obtener_bool("core.falta").unwrap(), None);
+        assert!(configuracion.obtener_bool("core.raro").is_err());
+    }
+
+    #[test]
+    fn asignar_crea_secciones_y_reemplaza() {
+        let texto = asignar_texto("", "user.name", "Ana", false).unwrap();
+        assert_eq!(texto, "[user]\n\tname = Ana\n");
+        let texto = asignar_texto(&texto, "user.email", "ana@example.com", false).unwrap();
+        assert_eq!(texto, "[user]\n\tname = Ana\n\temail = ana@example.com\n");
+        let texto = asignar_texto(&texto, "USER.NAME", "Ana María", false).unwrap();
+        assert_eq!(texto, "[user]\n\tname = Ana María\n\temail = ana@example.com\n");
+        let texto = asignar_texto(&texto, "remote.origin.url", "https://example.com/r.git", false).unwrap();
+        assert!(texto.ends_with("[remote \"origin\"]\n\turl = https://example.com/r.git\n"), "{}", texto);
+    }
+
+    #[test]
+    fn asignar_conserva_comentarios_y_lo_escrito_se_lee_igual() {
+        let original = "# cabecera\n[prueba] ; comentario\n\tvalor = viejo # se va\n\totro = igual\n";
+        let raros = ["  espacios  ", "con # almohadilla", "con ; punto y coma", "comillas \" y \\ barra", "dos\nlíneas\tcon tab"];
+        for valor in raros {
+            let texto = asignar_texto(original, "prueba.valor", valor, false).unwrap();
+            assert!(texto.starts_with("# cabecera\n[prueba] ; comentario\n"), "{}", texto);
+            assert!(texto.contains("\totro = igual\n"), "{}", texto);
+            let configuracion = configuracion(&texto);
+            assert_eq!(configuracion.obtener("prueba.valor"), Some(valor));
+            assert_eq!(configuracion.obtener("prueba.otro"), Some("igual"));
+        }
+    }
+
+    #[test]
+    fn varios_valores_al_asignar_y_quitar() {
+        let texto = asignar_texto("", "remote.origin.fetch", "uno", true).unwrap();
+        let texto = asignar_texto(&texto, "remote.origin.fetch", "dos", true).unwrap();
+        assert_eq!(configuracion(&texto).obtener_todos("remote.origin.fetch"), ["uno", "dos"]);
+        // Sin --add no se sabe cuál reemplazar, y sin --unset-all cuál quitar
+        assert!(asignar_texto(&texto, "remote.origin.fetch", "tres", false).is_err());
+        assert!(quitar_texto(&texto, "remote.origin.fetch", false).is_err());
+
+        let (sin_valores, quitados) = quitar_texto(&texto, "remote.origin.fetch", true).unwrap();
+        assert_eq!(quitados, 2);
+        assert_eq!(sin_valores, "[remote \"origin\"]\n");
+    }
+
+    #[test]
+    fn quitar_deja_el_resto_del_archivo() {
+        let texto = "[user]\n\tname = Ana # yo\n\temail = ana@example.com\n[core]\n\tbare = false\n";
+        let (texto, quitados) = quitar_texto(texto, "user.name", false).unwrap();
+        assert_eq!(quitados, 1);
+        assert_eq!(texto, "[user]\n\temail = ana@example.com\n[core]\n\tbare = false\n");
+        let (igual, quitados) = quitar_texto(&texto, "user.falta", false).unwrap();
+        assert_eq!((igual.as_str(), quitados), (texto.as_str(), 0));
+    }
+
+    #[test]
+    fn nombres_invalidos() {
+        assert!(normalizar_nombre("sinpunto").is_err());
+        assert!(normalizar_nombre("seccion.1clave").is_err());
+        assert!(normalizar_nombre("sec_cion.clave").is_err());
+        assert_eq!(normalizar_nombre("Core.FileMode").unwrap(), "core.filemode");
+        assert_eq!(normalizar_nombre("Remote.Mi.Origen.URL").unwrap(), "remote.Mi.Origen.url");
+    }
+}
@@ -0,0 +1,632 @@
+use crate::base_objetos::{self, BaseObjetos};
+use crate::comandos::leer_arbol;
+use crate::objetos::{Commit, Etiqueta, Tipo};
+use crate::paquete::{self, ObjetoIndexado};
+use anyhow::{Context, Result};
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use sha1::{Digest, Sha1};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasherDefault, Hasher};
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+// Los deltas se buscan comparando bloques de este tamaño: una coincidencia más corta no ahorra
+// nada frente a insertar los bytes
+const BLOQUE: usize = 16;
+// Posiciones que se recuerdan por bloque de la base; los datos muy repetitivos tendrían miles
+const CANDIDATOS_POR_BLOQUE: usize = 8;
+// Tamaño máximo de una instrucción de copia. El formato admite 0xffffff, pero git se limita a
+// 0x10000 para que los paquetes sirvan a versiones antiguas.
+const MAXIMA_COPIA: usize = 0x10000;
+// Los objetos más pequeños casi nunca se benefician de un delta
+const TAMAÑO_MINIMO_DELTA: u64 = 50;
+// Los objetos más grandes se guardan siempre completos, como `core.bigFileThreshold`
+const TAMAÑO_MAXIMO_DELTA: u64 = 512 * 1024 * 1024;
+
+/// Un objeto que hay que incluir en el paquete. La ruta por la que se llegó a él (vacía para
+/// commits y etiquetas) sirve para agrupar versiones del mismo archivo al buscar deltas.
+#[derive(Debug, Clone)]
+pub(crate) struct ObjetoAEmpaquetar {
+    pub(crate) hash: String,
+    pub(crate) ruta: String,
+}
+
+/// Parámetros de la búsqueda de deltas, como `--window` y `--depth` de `git pack-objects`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Opciones {
+    /// Cuántos objetos anteriores se prueban como base de cada uno (0 desactiva los deltas)
+    pub(crate) ventana: usize,
+    /// Longitud máxima de una cadena de deltas
+    pub(crate) profundidad: usize,
+}
+
+impl Default for Opciones {
+    fn default() -> Self {
+        Opciones {
+            ventana: 10,
+            profundidad: 50,
+        }
+    }
+}
+
+/// Los objetos alcanzables desde `incluir` que no lo son desde `excluir` (por ejemplo, lo que el
+/// servidor ya tiene). Como git, los commits excluidos se descartan enteros y, de los árboles,
+/// solo se recorren los de la frontera (los padres excluidos de commits incluidos): lo que
+/// comparten con los incluidos no se empaqueta.
+pub(crate) fn enumerar_objetos(base: &BaseObjetos, incluir: &[String], excluir: &[String]) -> Result<Vec<ObjetoAEmpaquetar>> {
+    let mut commits_excluidos = HashSet::new();
+    let mut pendientes: Vec<String> = excluir.to_vec();
+    while let Some(hash) = pendientes.pop() {
+        let (tipo, datos) = base.leer_completo(&hash)?;
+        match tipo {
+            Tipo::Tag => pendientes.push(Etiqueta::parsear(&datos)?.objeto),
+            Tipo::Commit if commits_excluidos.insert(hash) => {
+                // Un clon superficial puede no tener todos los padres
+                pendientes.extend(Commit::parsear(&datos)?.padres.into_iter().filter(|p| base.existe(p)));
+            }
+            _ => {}
+        }
+    }
+
+    let mut vistos = HashSet::new();
+    let mut objetos = Vec::new();
+    let mut arboles = Vec::new();
+    let mut frontera = Vec::new();
+    let mut pendientes: Vec<String> = incluir.to_vec();
+    while let Some(hash) = pendientes.pop() {
+        if commits_excluidos.contains(&hash) || vistos.contains(&hash) {
+            continue;
+        }
+        let (tipo, datos) = base.leer_completo(&hash)?;
+        match tipo {
+            Tipo::Commit => {
+                let commit = Commit::parsear(&datos)?;
+                arboles.push(commit.arbol);
+                for padre in commit.padres {
+                    if commits_excluidos.contains(&padre) {
+                        frontera.push(padre);
+                    } else {
+                        pendientes.push(padre);
+                    }
+                }
+            }
+            Tipo::Tag => pendientes.push(Etiqueta::parsear(&datos)?.objeto),
+            // Los árboles se recorren al final, después de marcar los de la frontera
+            Tipo::Arbol => {
+                arboles.push(hash);
+                continue;
+            }
+            Tipo::Blob => {}
+        }
+        vistos.insert(hash.clone());
+        objetos.push(ObjetoAEmpaquetar {
+            hash,
+            ruta: String::new(),
+        });
+    }
+
+    let mut omitidos = Vec::new();
+    for padre in frontera {
+        let (_, datos) = base.leer_completo(&padre)?;
+        recorrer_arbol(base, &Commit::parsear(&datos)?.arbol, "", &mut vistos, &mut omitidos)?;
+    }
+    for arbol in arboles {
+        recorrer_arbol(base, &arbol, "", &mut vistos, &mut objetos)?;
+    }
+    Ok(objetos)
+}
+
+/// Añade a `salida` el árbol y todo lo que contiene que no esté en `vistos`
+pub(crate) fn recorrer_arbol(
+    base: &BaseObjetos,
+    hash: &str,
+    ruta: &str,
+    vistos: &mut HashSet<String>,
+    salida: &mut Vec<ObjetoAEmpaquetar>,
+) -> Result<()> {
+    if !vistos.insert(hash.to_string()) {
+        return Ok(());
+    }
+    salida.push(ObjetoAEmpaquetar {
+        hash: hash.to_string(),
+        ruta: ruta.to_string(),
+    });
+    let (_, datos) = base.leer_completo(hash)?;
+    for entrada in leer_arbol::parsear_entradas(&datos)? {
+        let ruta_entrada = if ruta.is_empty() {
+            entrada.nombre.clone()
+        } else {
+            format!("{}/{}", ruta, entrada.nombre)
+        };
+        if entrada.es_arbol() {
+            recorrer_arbol(base, &entrada.hash, &ruta_entrada, vistos, salida)?;
+        } else if entrada.modo != "160000" && vistos.insert(entrada.hash.clone()) {
+            // Los submódulos (160000) apuntan a commits de otro repositorio
+            salida.push(ObjetoAEmpaquetar {
+                hash: entrada.hash,
+                ruta: ruta_entrada,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Escribe `<prefijo>-<checksum>.pack` y su `.idx` con los objetos indicados y devuelve el
+/// checksum. Ambos se escriben en temporales y se renombran, primero el `.pack`.
+pub(crate) fn guardar(
+    objetos: &[ObjetoAEmpaquetar],
+    base: &BaseObjetos,
+    opciones: Opciones,
+    prefijo: &Path,
+) -> Result<[u8; 20]> {
+    let directorio = prefijo.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(directorio)?;
+    let (ruta_temporal, archivo) = base_objetos::crear_temporal(directorio)?;
+
+    let resultado = (|| -> Result<[u8; 20]> {
+        let mut escritor = BufWriter::new(archivo);
+        let (indexados, checksum) = escribir_paquete(objetos, base, opciones, &mut escritor)?;
+        escritor.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+        let nombre = format!("{}-{}", prefijo.file_name().unwrap_or_default().to_string_lossy(), hex::encode(checksum));
+        let ruta_pack = directorio.join(format!("{}.pack", nombre));
+        fs::set_permissions(&ruta_temporal, fs::Permissions::from_mode(0o444))?;
+        fs::rename(&ruta_temporal, &ruta_pack)?;
+        let idx = paquete::generar_idx(&indexados, &checksum);
+        base_objetos::escribir_renombrando(directorio, &ruta_pack.with_extension("idx"), &idx)?;
+        Ok(checksum)
+    })();
+    if resultado.is_err() {
+        let _ = fs::remove_file(&ruta_temporal);
+    }
+    resultado
+}
+
+// Lo que se sabe de cada objeto mientras se decide cómo guardarlo
+struct Entrada {
+    hash: [u8; 20],
+    tipo: Tipo,
+    tamaño: u64,
+    hash_ruta: u32,
+    // Índice de la entrada que le sirve de base y el delta contra ella
+    base: Option<usize>,
+    delta: Vec<u8>,
+    profundidad: usize,
+}
+
+// Un objeto de la ventana: sus datos y, la primera vez que se usa como base, su índice de bloques
+struct EnVentana {
+    entrada: usize,
+    datos: Vec<u8>,
+    indice: Option<IndiceDelta>,
+}
+
+/// Escribe un packfile v2 con los objetos indicados y devuelve sus entradas para el `.idx`
+/// (ordenadas por hash) y el checksum del paquete.
+///
+/// Como `git pack-objects`, los objetos se ordenan por tipo, por un hash de su ruta que agrupa
+/// los archivos con el mismo nombre y de mayor a menor tamaño. Cada uno se compara con los
+/// `ventana` anteriores del mismo tipo y se guarda como OFS_DELTA contra el que da el delta más
+/// pequeño, sin pasar de `profundidad` deltas encadenados. Después se escriben en el orden
+/// recibido, cada base antes que sus deltas.
+pub(crate) fn escribir_paquete(
+    objetos: &[ObjetoAEmpaquetar],
+    base: &BaseObjetos,
+    opciones: Opciones,
+    destino: &mut impl Write,
+) -> Result<(Vec<ObjetoIndexado>, [u8; 20])> {
+    let mut entradas = Vec::with_capacity(objetos.len());
+    for objeto in objetos {
+        let leido = base.leer(&objeto.hash)?;
+        let mut hash = [0u8; 20];
+        hex::decode_to_slice(&objeto.hash, &mut hash).with_context(|| format!("Hash inválido: {}", objeto.hash))?;
+        entradas.push(Entrada {
+            hash,
+            tipo: leido.tipo,
+            tamaño: leido.tamaño_esperado,
+            hash_ruta: hash_ruta(&objeto.ruta),
+            base: None,
+            delta: Vec::new(),
+            profundidad: 0,
+        });
+    }
+    if opciones.ventana > 0 {
+        buscar_deltas(&mut entradas, objetos, base, opciones)?;
+    }
+
+    let mut escritor = EscritorPaquete {
+        destino,
+        sha: Sha1::new(),
+        posicion: 0,
+    };
+    let mut cabecera = b"PACK".to_vec();
+    cabecera.extend_from_slice(&2u32.to_be_bytes());
+    cabecera.extend_from_slice(&(entradas.len() as u32).to_be_bytes());
+    escritor.escribir(&cabecera)?;
+
+    let mut offsets: Vec<Option<u64>> = vec![None; entradas.len()];
+    let mut indexados = Vec::with_capacity(entradas.len());
+    for i in 0..entradas.len() {
+        // Una base puede aparecer más tarde en la lista: se escribe antes que su delta
+        let mut cadena = vec![i];
+        while let Some(siguiente) = entradas[*cadena.last().expect("no está vacía")].base {
+            cadena.push(siguiente);
+        }
+        for &j in cadena.iter().rev() {
+            if offsets[j].is_some() {
+                continue;
+            }
+            let entrada = &entradas[j];
+            let offset = escritor.posicion;
+            let mut bytes = Vec::new();
+            match entrada.base {
+                Some(b) => {
+                    paquete::escribir_cabecera_objeto(&mut bytes, 6, entrada.delta.len() as u64);
+                    let offset_base = offsets[b].expect("la base se escribe antes que el delta");
+                    escribir_offset_delta(&mut bytes, offset - offset_base);
+                    bytes = comprimir(bytes, &entrada.delta)?;
+                }
+                None => {
+                    let (tipo, datos) = base.leer_completo(&hex::encode(entrada.hash))?;
+                    paquete::escribir_cabecera_objeto(&mut bytes, paquete::numero_empacado(tipo), datos.len() as u64);
+                    bytes = comprimir(bytes, &datos)?;
+                }
+            }
+            let mut crc = Crc::new();
+            crc.update(&bytes);
+            escritor.escribir(&bytes)?;
+            offsets[j] = Some(offset);
+            indexados.push(ObjetoIndexado {
+                hash: entrada.hash,
+                offset,
+                crc32: crc.sum(),
+            });
+        }
+    }
+
+    let checksum: [u8; 20] = escritor.sha.finalize().into();
+    escritor.destino.write_all(&checksum)?;
+    indexados.sort_by_key(|objeto| objeto.hash);
+    Ok((indexados, checksum))
+}
+
+fn buscar_deltas(entradas: &mut [Entrada], objetos: &[ObjetoAEmpaquetar], base: &BaseObjetos, opciones: Opciones) -> Result<()> {
+    let mut orden: Vec<usize> = (0..entradas.len()).collect();
+    orden.sort_by(|&a, &b| {
+        let (a, b) = (&entradas[a], &entradas[b]);
+        paquete::numero_empacado(a.tipo)
+            .cmp(&paquete::numero_empacado(b.tipo))
+            .then(a.hash_ruta.cmp(&b.hash_ruta))
+            .then(b.tamaño.cmp(&a.tamaño))
+    });
+
+    let mut ventana: VecDeque<EnVentana> = VecDeque::new();
+    for i in orden {
+        let (tipo, tamaño) = (entradas[i].tipo, entradas[i].tamaño);
+        if tamaño > TAMAÑO_MAXIMO_DELTA {
+            continue;
+        }
+        let (_, datos) = base.leer_completo(&objetos[i].hash)?;
+
+        if tamaño >= TAMAÑO_MINIMO_DELTA {
+            // Un delta solo compensa si ocupa menos de la mitad del objeto
+            let mut mejor: Option<(usize, Vec<u8>)> = None;
+            for candidato in ventana.iter_mut().rev() {
+                let entrada_base = &entradas[candidato.entrada];
+                if entrada_base.tipo != tipo || entrada_base.profundidad >= opciones.profundidad {
+                    continue;
+                }
+                let limite = match &mejor {
+                    Some((_, delta)) => delta.len(),
+                    None => (tamaño as usize / 2).saturating_sub(20),
+                };
+                // Lo que el objeto tiene de más respecto a la base hay que insertarlo entero
+                if datos.len().saturating_sub(candidato.datos.len()) >= limite {
+                    continue;
+                }
+                let indice = candidato.indice.get_or_insert_with(|| IndiceDelta::new(&candidato.datos));
+                if let Some(delta) = crear_delta(indice, &candidato.datos, &datos, limite) {
+                    mejor = Some((candidato.entrada, delta));
+                }
+            }
+            if let Some((indice_base, delta)) = mejor {
+                entradas[i].profundidad = entradas[indice_base].profundidad + 1;
+                entradas[i].base = Some(indice_base);
+                entradas[i].delta = delta;
+            }
+        }
+
+        ventana.push_back(EnVentana {
+            entrada: i,
+            datos,
+            indice: None,
+        });
+        if ventana.len() > opciones.ventana {
+            ventana.pop_front();
+        }
+    }
+    Ok(())
+}
+
+// El hash de `git pack-objects` para agrupar rutas: pesan más los últimos caracteres, así que
+// `src/main.rs` y `otro/main.rs` quedan juntos, y los archivos con la misma extensión cerca
+fn hash_ruta(ruta: &str) -> u32 {
+    let mut hash: u32 = 0;
+    for byte in ruta.bytes().filter(|b| !b.is_ascii_whitespace()) {
+        hash = (hash >> 2).wrapping_add((byte as u32) << 24);
+    }
+    hash
+}
+
+/// Las posiciones de cada bloque de `BLOQUE` bytes alineado de un objeto base
+pub(crate) struct IndiceDelta {
+    bloques: HashMap<u128, Vec<u32>, BuildHasherDefault<HashBloque>>,
+}
+
+impl IndiceDelta {
+    pub(crate) fn new(base: &[u8]) -> IndiceDelta {
+        let mut bloques: HashMap<u128, Vec<u32>, _> = HashMap::default();
+        for (i, bloque) in base.chunks_exact(BLOQUE).enumerate() {
+            let posiciones = bloques.entry(clave_bloque(bloque)).or_default();
+            if posiciones.len() < CANDIDATOS_POR_BLOQUE {
+                posiciones.push((i * BLOQUE) as u32);
+            }
+        }
+        IndiceDelta { bloques }
+    }
+}
+
+fn clave_bloque(bloque: &[u8]) -> u128 {
+    u128::from_le_bytes(bloque.try_into().expect("el bloque mide BLOQUE bytes"))
+}
+
+// La clave ya son los bytes del bloque: basta con mezclarlos, sin el coste de SipHash, que se
+// notaría porque se busca una vez por cada byte del objeto
+#[derive(Default)]
+struct HashBloque(u64);
+
+impl Hasher for HashBloque {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0.rotate_left(5) ^ byte as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_u128(&mut self, clave: u128) {
+        let mezcla = (clave as u64) ^ ((clave >> 64) as u64).rotate_left(29);
+        self.0 = mezcla.wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (mezcla >> 31);
+    }
+}
+
+/// Un delta que reconstruye `objetivo` a partir de `base` (el formato que lee `aplicar_delta`),
+/// o None si ocupa `limite` bytes o más. Se recorre el objetivo buscando en cada posición un
+/// bloque de la base que coincida; cada coincidencia se alarga hacia delante y hacia atrás
+/// y se convierte en una copia, y lo que queda entre copias se inserta literalmente.
+pub(crate) fn crear_delta(indice: &IndiceDelta, base: &[u8], objetivo: &[u8], limite: usize) -> Option<Vec<u8>> {
+    let mut delta = Vec::new();
+    escribir_tamaño_delta(&mut delta, base.len());
+    escribir_tamaño_delta(&mut delta, objetivo.len());
+
+    let mut literal_desde = 0;
+    let mut i = 0;
+    while i + BLOQUE <= objetivo.len() {
+        let mut mejor = (0, 0);
+        if let Some(posiciones) = indice.bloques.get(&clave_bloque(&objetivo[i..i + BLOQUE])) {
+            for &posicion in posiciones {
+                let posicion = posicion as usize;
+                let longitud = base[posicion..]
+                    .iter()
+                    .zip(&objetivo[i..])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if longitud > mejor.1 {
+                    mejor = (posicion, longitud);
+                }
+            }
+        }
+        let (mut origen, mut longitud) = mejor;
+        if longitud < BLOQUE {
+            // Lo pendiente se va a insertar tal cual: si ya no cabe, el delta no sirve
+            if delta.len() + (i - literal_desde) >= limite {
+                return None;
+            }
+            i += 1;
+            continue;
+        }
+
+        // Lo que coincide justo antes del bloque también se copia en vez de insertarse
+        let mut inicio = i;
+        while inicio > literal_desde && origen > 0 && objetivo[inicio - 1] == base[origen - 1] {
+            inicio -= 1;
+            origen -= 1;
+            longitud += 1;
+        }
+        escribir_literal(&mut delta, &objetivo[literal_desde..inicio]);
+        let mut restante = longitud;
+        while restante > 0 {
+            let trozo = restante.min(MAXIMA_COPIA);
+            escribir_copia(&mut delta, origen + longitud - restante, trozo);
+            restante -= trozo;
+        }
+        if delta.len() >= limite {
+            return None;
+        }
+        i = inicio + longitud;
+        literal_desde = i;
+    }
+    escribir_literal(&mut delta, &objetivo[literal_desde..]);
+    (delta.len() < limite).then_some(delta)
+}
+
+// Tamaños de la base y del resultado al principio del delta: little-endian de 7 bits por byte
+fn escribir_tamaño_delta(delta: &mut Vec<u8>, mut tamaño: usize) {
+    while tamaño >= 0x80 {
+        delta.push((tamaño & 0x7f) as u8 | 0x80);
+        tamaño >>= 7;
+    }
+    delta.push(tamaño as u8);
+}
+
+// Las inserciones llevan como máximo 127 bytes cada una
+fn escribir_literal(delta: &mut Vec<u8>, datos: &[u8]) {
+    for trozo in datos.chunks(0x7f) {
+        delta.push(trozo.len() as u8);
+        delta.extend_from_slice(trozo);
+    }
+}
+
+// Copia: los bits 0-3 dicen qué bytes del offset siguen y los 4-6 los del tamaño; los bytes a
+// cero se omiten y un tamaño de 0x10000 se escribe como 0
+fn escribir_copia(delta: &mut Vec<u8>, offset: usize, tamaño: usize) {
+    let posicion_instruccion = delta.len();
+    let mut instruccion = 0x80u8;
+    delta.push(0);
+    for bit in 0..4 {
+        let byte = (offset >> (8 * bit)) as u8;
+        if byte != 0 {
+            instruccion |= 1 << bit;
+            delta.push(byte);
+        }
+    }
+    let tamaño = if tamaño == 0x10000 { 0 } else { tamaño };
+    for bit in 0..3 {
+        let byte = (tamaño >> (8 * bit)) as u8;
+        if byte != 0 {
+            instruccion |= 0x10 << bit;
+            delta.push(byte);
+        }
+    }
+    delta[posicion_instruccion] = instruccion;
+}
+
+// La distancia hasta la base en big-endian de 7 bits; cada byte de continuación resta 1 (el
+// inverso de `leer_offset_delta`)
+fn escribir_offset_delta(destino: &mut Vec<u8>, mut distancia: u64) {
+    let mut bytes = vec![(distancia & 0x7f) as u8];
+    distancia >>= 7;
+    while distancia != 0 {
+        distancia -= 1;
+        bytes.push(0x80 | (distancia & 0x7f) as u8);
+        distancia >>= 7;
+    }
+    destino.extend(bytes.iter().rev());
+}
+
+fn comprimir(mut cabecera: Vec<u8>, datos: &[u8]) -> Result<Vec<u8>> {
+    let mut comprimido = ZlibEncoder::new(Vec::new(), Compression::default());
+    comprimido.write_all(datos)?;
+    cabecera.extend_from_slice(&comprimido.finish()?);
+    Ok(cabecera)
+}
+
+// Lleva la posición y el SHA-1 de lo escrito (el trailer del paquete)
+struct EscritorPaquete<'a, W> {
+    destino: &'a mut W,
+    sha: Sha1,
+    posicion: u64,
+}
+
+impl<W: Write> EscritorPaquete<'_, W> {
+    fn escribir(&mut self, datos: &[u8]) -> Result<()> {
+        self.sha.update(datos);
+        self.destino.write_all(datos)?;
+        self.posicion += datos.len() as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bytes pseudoaleatorios (xorshift, la semilla no puede ser 0) para que no haya coincidencias
+    // por casualidad
+    fn datos(semilla: u64, tamaño: usize) -> Vec<u8> {
+        let mut estado = semilla;
+        (0..tamaño)
+            .map(|_| {
+                estado ^= estado << 13;
+                estado ^= estado >> 7;
+                estado ^= estado << 17;
+                estado as u8
+            })
+            .collect()
+    }
+
+    // Crea el delta, comprueba que `aplicar_delta` devuelve el objetivo y lo devuelve
+    fn ida_y_vuelta(base: &[u8], objetivo: &[u8]) -> Vec<u8> {
+        let delta = crear_delta(&IndiceDelta::new(base), base, objetivo, usize::MAX).unwrap();
+        let resultado = paquete::aplicar_delta(&delta, base).unwrap();
+        assert!(resultado == objetivo, "el delta no reconstruye el objetivo");
+        delta
+    }
+
+    #[test]
+    fn objetos_pequeños_o_vacios() {
+        ida_y_vuelta(b"", b"");
+        ida_y_vuelta(b"", b"hola");
+        ida_y_vuelta(b"hola", b"");
+        ida_y_vuelta(b"base corta", b"otro texto sin nada en comun con la base");
+    }
+
+    #[test]
+    fn copias_e_inserciones() {
+        let base = datos(1, 4096);
+        // Igual que la base: una sola copia
+        assert!(ida_y_vuelta(&base, &base).len() < 16);
+
+        // Cambios al principio, en medio y al final
+        let mut objetivo = b"cabecera nueva".to_vec();
+        objetivo.extend_from_slice(&base[..1000]);
+        objetivo.extend_from_slice(b"en medio");
+        objetivo.extend_from_slice(&base[1500..]);
+        objetivo.extend_from_slice(b"cola");
+        assert!(ida_y_vuelta(&base, &objetivo).len() < 100);
+
+        // Trozos de la base reordenados y repetidos
+        let mut objetivo = base[3000..].to_vec();
+        objetivo.extend_from_slice(&base[..3000]);
+        objetivo.extend_from_slice(&base[100..200]);
+        ida_y_vuelta(&base, &objetivo);
+
+        // Un literal de más de 127 bytes se parte en varias inserciones
+        let mut objetivo = base[..500].to_vec();
+        objetivo.extend(datos(5, 1000));
+        objetivo.extend_from_slice(&base[500..]);
+        ida_y_vuelta(&base, &objetivo);
+    }
+
+    #[test]
+    fn copias_de_mas_de_0x10000() {
+        // Más de MAXIMA_COPIA seguidos: se parten en copias de 0x10000, que se escriben con
+        // tamaño 0, y los offsets llevan bytes a cero que se omiten
+        let base = datos(3, 5 * 0x10000 + 123);
+        ida_y_vuelta(&base, &base);
+
+        let mut objetivo = base[0x10000..0x40000].to_vec();
+        objetivo.extend_from_slice(b"separador");
+        objetivo.extend_from_slice(&base[0x1_0100..0x2_0100]);
+        objetivo.extend_from_slice(&base[..0x10001]);
+        let delta = ida_y_vuelta(&base, &objetivo);
+        assert!(delta.len() < 100);
+    }
+
+    #[test]
+    fn limite() {
+        let base = datos(7, 4096);
+        let objetivo = datos(9, 4096);
+        let indice = IndiceDelta::new(&base);
+        // Sin nada en común el delta ocupa más que el objetivo
+        assert!(crear_delta(&indice, &base, &objetivo, objetivo.len()).is_none());
+        let delta = ida_y_vuelta(&base, &base);
+        assert!(crear_delta(&indice, &base, &base, delta.len()).is_none());
+        assert_eq!(crear_delta(&indice, &base, &base, delta.len() + 1), Some(delta));
+    }
+}
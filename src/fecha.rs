@@ -0,0 +1,348 @@
+use anyhow::{bail, Context, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MESES: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september", "october",
+    "november", "december",
+];
+const DIAS: [&str; 7] = ["sunday", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday"];
+
+/// El instante actual y la diferencia con UTC de la hora local, en segundos
+pub(crate) fn actual() -> Result<(i64, i64)> {
+    let segundos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+    Ok((segundos, desplazamiento_local(segundos)))
+}
+
+/// Diferencia con UTC, en segundos, de la hora local en un instante, según la zona horaria del
+/// sistema (`TZ` o `/etc/localtime`), así que tiene en cuenta el horario de verano
+#[cfg(unix)]
+pub(crate) fn desplazamiento_local(timestamp: i64) -> i64 {
+    let instante = timestamp as libc::time_t;
+    // SAFETY: `tm` es un struct de enteros (y un puntero que localtime_r rellena), así que todo
+    // ceros es un valor válido, y localtime_r solo escribe en la memoria que se le pasa
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&instante, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+#[cfg(not(unix))]
+pub(crate) fn desplazamiento_local(_timestamp: i64) -> i64 {
+    0
+}
+
+/// Zona horaria como la escribe git en las firmas: 7200 -> `+0200`, -19800 -> `-0530`
+pub(crate) fn zona_horaria(desplazamiento: i64) -> String {
+    let signo = if desplazamiento < 0 { '-' } else { '+' };
+    let minutos = desplazamiento.abs() / 60;
+    format!("{}{:02}{:02}", signo, minutos / 60, minutos % 60)
+}
+
+/// Instante y desplazamiento de la zona horaria de una fecha en alguno de los formatos que
+/// acepta git en GIT_AUTHOR_DATE:
+/// - El suyo interno, `1112911993 +0200` o `@1112911993`
+/// - RFC 2822, `Thu, 07 Apr 2005 22:13:13 +0200`
+/// - ISO 8601, `2005-04-07T22:13:13`, `2005-04-07 22:13:13 -0530` o `2005-04-07T22:13:13Z`
+/// - Fechas `2005.04.07`, `04/07/2005` (mes primero) o `07.04.2005` con una hora
+///
+/// Sin zona horaria, la fecha está en la hora local.
+pub(crate) fn parsear(texto: &str) -> Result<(i64, i64)> {
+    let texto = texto.trim();
+    if let Some(interna) = parsear_interna(texto) {
+        return interna;
+    }
+    let fecha = Campos::parsear(texto).with_context(|| format!("Fecha no válida: '{}'", texto))?;
+    let (Some(año), Some(mes), Some(dia), Some(hora)) = (fecha.año, fecha.mes, fecha.dia, fecha.hora) else {
+        bail!("Fecha no válida: '{}' (necesita día, mes, año y hora)", texto);
+    };
+    if !(1..=12).contains(&mes) || dia < 1 || dia > dias_del_mes(año, mes) {
+        bail!("Fecha no válida: '{}' (no existe ese día)", texto);
+    }
+    let local = dias_desde_civil(año, mes, dia) * 86400 + hora;
+
+    match fecha.zona {
+        Some(desplazamiento) => Ok((local - desplazamiento, desplazamiento)),
+        // La hora local se pasa a UTC con el desplazamiento de ese momento, que se vuelve a
+        // calcular por si la fecha cae justo en un cambio de hora
+        None => {
+            let desplazamiento = desplazamiento_local(local - desplazamiento_local(local));
+            Ok((local - desplazamiento, desplazamiento))
+        }
+    }
+}
+
+// `<segundos> <zona>`, `@<segundos> [zona]` o solo los segundos, si tienen al menos 9 cifras
+// para no confundirlos con un año o un día
+fn parsear_interna(texto: &str) -> Option<Result<(i64, i64)>> {
+    let (arroba, texto) = match texto.strip_prefix('@') {
+        Some(resto) => (true, resto),
+        None => (false, texto),
+    };
+    let (segundos, zona) = match texto.split_once(' ') {
+        Some((segundos, zona)) => (segundos, Some(zona.trim())),
+        None => (texto, None),
+    };
+    if segundos.is_empty() || !segundos.bytes().all(|c| c.is_ascii_digit()) || (!arroba && segundos.len() < 9) {
+        return None;
+    }
+    let segundos: i64 = segundos.parse().ok()?;
+    let desplazamiento = match zona {
+        None => desplazamiento_local(segundos),
+        Some(zona) => match parsear_zona(zona) {
+            Some(desplazamiento) => desplazamiento,
+            None => return Some(Err(anyhow::anyhow!("Zona horaria no válida: '{}'", zona))),
+        },
+    };
+    Some(Ok((segundos, desplazamiento)))
+}
+
+// `+hh`, `+hhmm` o `+hh:mm` (o con '-')
+fn parsear_zona(zona: &str) -> Option<i64> {
+    let signo = match zona.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let digitos = zona[1..].replacen(':', "", 1);
+    if !matches!(digitos.len(), 2 | 4) || !digitos.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let horas: i64 = digitos[..2].parse().ok()?;
+    let minutos: i64 = digitos.get(2..).filter(|m| !m.is_empty()).map_or(Some(0), |m| m.parse().ok())?;
+    if horas > 14 || minutos > 59 {
+        return None;
+    }
+    Some(signo * (horas * 3600 + minutos * 60))
+}
+
+/// Lo que se ha encontrado en una fecha, en cualquier orden
+#[derive(Default)]
+struct Campos {
+    año: Option<i64>,
+    mes: Option<i64>,
+    dia: Option<i64>,
+    /// Segundos desde el principio del día
+    hora: Option<i64>,
+    zona: Option<i64>,
+}
+
+impl Campos {
+    // Se separa en palabras (meses, días de la semana, am/pm, UTC), grupos de números (fechas
+    // con '-', '/' o '.', horas con ':' y números sueltos) y zonas horarias que empiezan con signo
+    fn parsear(texto: &str) -> Result<Campos> {
+        let mut campos = Campos::default();
+        let bytes = texto.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            let c = bytes[i];
+            if c.is_ascii_whitespace() || c == b',' {
+                i += 1;
+            } else if c.is_ascii_alphabetic() {
+                let inicio = i;
+                while i < bytes.len() && bytes[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                campos.palabra(&texto[inicio..i].to_ascii_lowercase())?;
+            } else if c == b'+' || c == b'-' {
+                let inicio = i;
+                i += 1;
+                while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b':') {
+                    i += 1;
+                }
+                let zona = parsear_zona(&texto[inicio..i]).with_context(|| format!("zona horaria '{}'", &texto[inicio..i]))?;
+                campos.zona = Some(zona);
+            } else if c.is_ascii_digit() {
+                let inicio = i;
+                let mut es_hora = false;
+                while i < bytes.len() {
+                    let c = bytes[i];
+                    let separador = matches!(c, b':' | b'.') || (matches!(c, b'-' | b'/') && !es_hora);
+                    if c.is_ascii_digit() {
+                        i += 1;
+                    } else if separador && bytes.get(i + 1).is_some_and(u8::is_ascii_digit) {
+                        es_hora |= c == b':';
+                        i += 1;
+                    } else {
+                        break;
+                    }
+                }
+                campos.numero(&texto[inicio..i])?;
+            } else {
+                bail!("carácter inesperado '{}'", texto[i..].chars().next().unwrap_or('?'));
+            }
+        }
+        Ok(campos)
+    }
+
+    fn palabra(&mut self, palabra: &str) -> Result<()> {
+        let abreviatura = |nombre: &str| palabra.len() >= 3 && nombre.starts_with(palabra);
+        if let Some(mes) = MESES.iter().position(|nombre| abreviatura(nombre)) {
+            self.mes = Some(mes as i64 + 1);
+        } else if DIAS.iter().any(|nombre| abreviatura(nombre)) || palabra == "t" {
+            // El día de la semana no aporta nada y la 'T' separa la fecha de la hora en ISO 8601
+        } else if matches!(palabra, "z" | "utc" | "gmt") {
+            self.zona = Some(0);
+        } else if matches!(palabra, "am" | "pm") {
+            let hora = self.hora.context("am/pm sin hora")?;
+            if !(3600..13 * 3600).contains(&hora) {
+                bail!("hora '{}' fuera del rango de 12 horas", hora / 3600);
+            }
+            let hora = hora % (12 * 3600);
+            self.hora = Some(if palabra == "pm" { hora + 12 * 3600 } else { hora });
+        } else {
+            bail!("palabra desconocida '{}'", palabra);
+        }
+        Ok(())
+    }
+
+    fn numero(&mut self, grupo: &str) -> Result<()> {
+        let partes = |separador: char| -> Result<Vec<i64>> {
+            grupo
+                .split(separador)
+                .map(|parte| parte.parse().with_context(|| format!("número '{}'", parte)))
+                .collect()
+        };
+        if grupo.contains(':') {
+            // hh:mm[:ss[.fracción]]; las fracciones de segundo se descartan
+            let sin_fraccion = grupo.split('.').next().unwrap_or(grupo);
+            let partes = sin_fraccion
+                .split(':')
+                .map(|parte| parte.parse::<i64>().with_context(|| format!("hora '{}'", grupo)))
+                .collect::<Result<Vec<_>>>()?;
+            let [hora, minuto, segundo] = match partes[..] {
+                [hora, minuto] => [hora, minuto, 0],
+                [hora, minuto, segundo] => [hora, minuto, segundo],
+                _ => bail!("hora '{}'", grupo),
+            };
+            if hora > 23 || minuto > 59 || segundo > 60 {
+                bail!("hora '{}'", grupo);
+            }
+            self.hora = Some(hora * 3600 + minuto * 60 + segundo);
+        } else if grupo.contains(['-', '/', '.']) {
+            let separador = grupo.chars().find(|c| matches!(c, '-' | '/' | '.')).unwrap_or('-');
+            let [a, b, c] = partes(separador)?[..] else {
+                bail!("fecha '{}'", grupo);
+            };
+            let largo_primero = grupo.find(separador).unwrap_or(0);
+            let (año, mes, dia) = match separador {
+                _ if largo_primero == 4 => (a, b, c),
+                // Como en git, con '/' va primero el mes salvo que no pueda serlo
+                '/' if a <= 12 => (c, a, b),
+                _ => (c, b, a),
+            };
+            self.año = Some(año);
+            self.mes = Some(mes);
+            self.dia = Some(dia);
+        } else {
+            let valor: i64 = grupo.parse().with_context(|| format!("número '{}'", grupo))?;
+            match grupo.len() {
+                1 | 2 if self.dia.is_none() => self.dia = Some(valor),
+                4 if self.año.is_none() && valor >= 1970 => self.año = Some(valor),
+                _ => bail!("número '{}' fuera de lugar", grupo),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn es_bisiesto(año: i64) -> bool {
+    año % 4 == 0 && (año % 100 != 0 || año % 400 == 0)
+}
+
+fn dias_del_mes(año: i64, mes: i64) -> i64 {
+    match mes {
+        2 if es_bisiesto(año) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Días desde 1970-01-01 de una fecha civil (algoritmo de Howard Hinnant, el inverso del que usa
+// `Firma::fecha_legible()`)
+fn dias_desde_civil(año: i64, mes: i64, dia: i64) -> i64 {
+    let año = if mes <= 2 { año - 1 } else { año };
+    let era = año.div_euclid(400);
+    let yoe = año - era * 400;
+    let mp = (mes + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + dia - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2005-04-07 22:13:13 UTC, la fecha de los ejemplos de la documentación de git
+    const EJEMPLO_UTC: i64 = 1112911993;
+
+    #[test]
+    fn formato_interno() {
+        assert_eq!(parsear("1112911993 +0200").unwrap(), (EJEMPLO_UTC, 7200));
+        assert_eq!(parsear("@1112911993 -0530").unwrap(), (EJEMPLO_UTC, -19800));
+        // Con '@' vale cualquier número de cifras; sin zona, la de la hora local
+        assert_eq!(parsear("@0 +0000").unwrap(), (0, 0));
+        assert_eq!(parsear("@1112911993").unwrap().0, EJEMPLO_UTC);
+        assert!(parsear("1112911993 +2500").is_err());
+    }
+
+    #[test]
+    fn rfc_2822() {
+        assert_eq!(parsear("Thu, 07 Apr 2005 22:13:13 +0200").unwrap(), (EJEMPLO_UTC - 7200, 7200));
+        assert_eq!(parsear("7 April 2005 22:13:13 -0000").unwrap(), (EJEMPLO_UTC, 0));
+        assert_eq!(parsear("Thu Apr 7 22:13:13 2005 +0000").unwrap(), (EJEMPLO_UTC, 0));
+    }
+
+    #[test]
+    fn iso_8601() {
+        assert_eq!(parsear("2005-04-07T22:13:13Z").unwrap(), (EJEMPLO_UTC, 0));
+        assert_eq!(parsear("2005-04-07T22:13:13+02:00").unwrap(), (EJEMPLO_UTC - 7200, 7200));
+        assert_eq!(parsear("2005-04-07 22:13:13 -05:30").unwrap(), (EJEMPLO_UTC + 19800, -19800));
+        assert_eq!(parsear("2005-04-07T22:13:13.250+0000").unwrap(), (EJEMPLO_UTC, 0));
+        assert_eq!(parsear("2005.04.07 22:13:13 UTC").unwrap(), (EJEMPLO_UTC, 0));
+    }
+
+    #[test]
+    fn orden_de_dia_y_mes() {
+        // Con '/' va primero el mes; con '.' o '-' y el año al final, primero el día
+        assert_eq!(parsear("04/07/2005 22:13:13 +0000").unwrap(), (EJEMPLO_UTC, 0));
+        assert_eq!(parsear("07.04.2005 22:13:13 +0000").unwrap(), (EJEMPLO_UTC, 0));
+        assert_eq!(parsear("07-04-2005 22:13:13 +0000").unwrap(), (EJEMPLO_UTC, 0));
+        // Si el primero no puede ser un mes, es el día
+        assert_eq!(parsear("13/04/2005 00:00:00 +0000").unwrap().0, parsear("2005-04-13 00:00:00 +0000").unwrap().0);
+    }
+
+    #[test]
+    fn am_pm_y_calendario() {
+        assert_eq!(parsear("2005-04-07 10:13:13 pm +0000").unwrap(), (EJEMPLO_UTC, 0));
+        assert_eq!(parsear("2005-04-08 12:00:00 am +0000").unwrap().0, EJEMPLO_UTC + 6407);
+        assert_eq!(parsear("2024-02-29 12:00:00 +0000").unwrap().0, 1709208000);
+        assert_eq!(parsear("1999-12-31 23:59:59 +0000").unwrap().0, 946684799);
+    }
+
+    #[test]
+    fn fechas_invalidas() {
+        for texto in [
+            "2023-02-29 12:00:00 +0000",
+            "2005-13-01 12:00:00 +0000",
+            "2005-04-07 +0000",
+            "2005-04-07 24:00:00 +0000",
+            "ayer",
+            "2005-04-07 22:13:13 +0200 extra",
+        ] {
+            assert!(parsear(texto).is_err(), "'{}' debería ser inválida", texto);
+        }
+    }
+
+    #[test]
+    fn zonas_horarias() {
+        assert_eq!(zona_horaria(7200), "+0200");
+        assert_eq!(zona_horaria(-19800), "-0530");
+        assert_eq!(zona_horaria(0), "+0000");
+        assert_eq!(parsear_zona("+05"), Some(18000));
+        assert_eq!(parsear_zona("-0930"), Some(-34200));
+        assert_eq!(parsear_zona("+1:30"), None);
+    }
+}
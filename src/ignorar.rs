@@ -0,0 +1,427 @@
+use crate::configuracion::{self, Configuracion};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Decide qué rutas del árbol de trabajo ignora git, con las mismas fuentes y la misma prioridad:
+/// `core.excludesFile`, `.git/info/exclude` y los `.gitignore` de cada directorio, donde manda el
+/// más profundo y, dentro de cada archivo, el último patrón que coincide.
+pub(crate) struct Ignorados {
+    /// Patrones de core.excludesFile y .git/info/exclude, de menor a mayor prioridad
+    globales: Vec<Patron>,
+    /// Patrones del `.gitignore` de cada directorio ya visitado ("" es la raíz)
+    por_directorio: HashMap<String, Vec<Patron>>,
+    /// Resultado ya calculado para cada directorio
+    directorios: HashMap<String, bool>,
+}
+
+struct Patron {
+    patron: Vec<u8>,
+    negado: bool,
+    solo_directorios: bool,
+    /// Con una '/' que no sea la final, el patrón se compara con la ruta desde el directorio del
+    /// `.gitignore`; si no, con el último componente de la ruta a cualquier profundidad
+    anclado: bool,
+    /// Directorio del archivo de donde sale, terminado en '/' ("" para la raíz)
+    base: String,
+}
+
+impl Ignorados {
+    pub(crate) fn cargar() -> Result<Ignorados> {
+        let mut globales = Vec::new();
+        if let Some(ruta) = archivo_exclusiones_global()? {
+            globales.extend(leer_patrones(&ruta, ""));
+        }
+        globales.extend(leer_patrones(Path::new(".git/info/exclude"), ""));
+        Ok(Ignorados {
+            globales,
+            por_directorio: HashMap::new(),
+            directorios: HashMap::new(),
+        })
+    }
+
+    /// Si se ignora una ruta relativa a la raíz del repositorio (separada por '/'). Como en git,
+    /// todo lo que hay dentro de un directorio ignorado está ignorado, aunque un patrón con '!'
+    /// intente recuperarlo. No sabe nada del índice: un archivo rastreado nunca se ignora, y eso
+    /// lo tiene que comprobar quien llama.
+    pub(crate) fn ignorado(&mut self, ruta: &str, es_directorio: bool) -> bool {
+        let mut fin = 0;
+        while let Some(posicion) = ruta[fin..].find('/') {
+            let directorio = &ruta[..fin + posicion];
+            if self.directorio_ignorado(directorio) {
+                return true;
+            }
+            fin += posicion + 1;
+        }
+        if es_directorio {
+            self.directorio_ignorado(ruta)
+        } else {
+            self.coincide(ruta, false)
+        }
+    }
+
+    fn directorio_ignorado(&mut self, directorio: &str) -> bool {
+        if let Some(&ignorado) = self.directorios.get(directorio) {
+            return ignorado;
+        }
+        let ignorado = self.coincide(directorio, true);
+        self.directorios.insert(directorio.to_string(), ignorado);
+        ignorado
+    }
+
+    // Solo la ruta en sí, sin mirar sus directorios. Se recorren las fuentes de mayor a menor
+    // prioridad y gana el primer patrón que coincide.
+    fn coincide(&mut self, ruta: &str, es_directorio: bool) -> bool {
+        let mut directorios = vec![String::new()];
+        directorios.extend(ruta.match_indices('/').map(|(posicion, _)| ruta[..=posicion].to_string()));
+        for directorio in &directorios {
+            if !self.por_directorio.contains_key(directorio) {
+                let archivo = Path::new(if directorio.is_empty() { "." } else { directorio }).join(".gitignore");
+                let patrones = leer_patrones(&archivo, directorio);
+                self.por_directorio.insert(directorio.clone(), patrones);
+            }
+        }
+
+        let locales = directorios.iter().rev().map(|directorio| &self.por_directorio[directorio]);
+        for patrones in locales.chain(std::iter::once(&self.globales)) {
+            if let Some(patron) = patrones.iter().rev().find(|p| p.coincide(ruta, es_directorio)) {
+                return !patron.negado;
+            }
+        }
+        false
+    }
+}
+
+impl Patron {
+    // Una línea de un archivo de exclusiones, o None si no es un patrón (vacía o comentario)
+    fn parsear(linea: &str, base: &str) -> Option<Patron> {
+        let mut linea = linea.trim_end_matches('\r');
+        if linea.is_empty() || linea.starts_with('#') {
+            return None;
+        }
+        // Los espacios del final no cuentan salvo que se escapen con '\'
+        while linea.ends_with(' ') && !linea.ends_with("\\ ") {
+            linea = &linea[..linea.len() - 1];
+        }
+        let (negado, linea) = match linea.strip_prefix('!') {
+            Some(resto) => (true, resto),
+            None => (false, linea),
+        };
+        let (solo_directorios, linea) = match linea.strip_suffix('/') {
+            Some(resto) => (true, resto),
+            None => (false, linea),
+        };
+        let anclado = linea.contains('/');
+        let linea = linea.strip_prefix('/').unwrap_or(linea);
+        if linea.is_empty() {
+            return None;
+        }
+        Some(Patron {
+            patron: linea.as_bytes().to_vec(),
+            negado,
+            solo_directorios,
+            anclado,
+            base: base.to_string(),
+        })
+    }
+
+    fn coincide(&self, ruta: &str, es_directorio: bool) -> bool {
+        if self.solo_directorios && !es_directorio {
+            return false;
+        }
+        let Some(relativa) = ruta.strip_prefix(&self.base) else {
+            return false;
+        };
+        if self.anclado {
+            coincidir(&self.patron, 0, relativa.as_bytes(), 0)
+        } else {
+            let nombre = relativa.rsplit('/').next().unwrap_or(relativa);
+            coincidir(&self.patron, 0, nombre.as_bytes(), 0)
+        }
+    }
+}
+
+// Glob de git (wildmatch con WM_PATHNAME): '*' y '?' no cruzan '/', `[...]` es una clase de
+// caracteres y '\' escapa el siguiente. `**` solo es especial si ocupa un componente entero:
+// `**/` al principio o `/**/` en medio equivalen a cero o más directorios y `/**` al final, a
+// todo lo que hay dentro.
+fn coincidir(patron: &[u8], mut i: usize, texto: &[u8], mut j: usize) -> bool {
+    while i < patron.len() {
+        if let Some(clase) = Clase::parsear(patron, i) {
+            let Some(&c) = texto.get(j) else {
+                return false;
+            };
+            if c == b'/' || !clase.contiene(c) {
+                return false;
+            }
+            i = clase.fin;
+            j += 1;
+            continue;
+        }
+        match patron[i] {
+            b'*' => {
+                let doble = patron.get(i + 1) == Some(&b'*')
+                    && (i == 0 || patron[i - 1] == b'/')
+                    && (i + 2 == patron.len() || patron[i + 2] == b'/');
+                if doble {
+                    if i + 2 == patron.len() {
+                        return true;
+                    }
+                    return (j..=texto.len())
+                        .filter(|&k| k == j || texto[k - 1] == b'/')
+                        .any(|k| coincidir(patron, i + 3, texto, k));
+                }
+                let mut resto = i + 1;
+                while patron.get(resto) == Some(&b'*') {
+                    resto += 1;
+                }
+                for k in j..=texto.len() {
+                    if coincidir(patron, resto, texto, k) {
+                        return true;
+                    }
+                    if k < texto.len() && texto[k] == b'/' {
+                        return false;
+                    }
+                }
+                return false;
+            }
+            b'?' => {
+                if j >= texto.len() || texto[j] == b'/' {
+                    return false;
+                }
+                i += 1;
+                j += 1;
+            }
+            b'\\' if i + 1 < patron.len() => {
+                if texto.get(j) != Some(&patron[i + 1]) {
+                    return false;
+                }
+                i += 2;
+                j += 1;
+            }
+            c => {
+                if texto.get(j) != Some(&c) {
+                    return false;
+                }
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    j == texto.len()
+}
+
+/// Una clase de caracteres `[...]` de un patrón
+struct Clase {
+    /// Posición del patrón siguiente a ']'
+    fin: usize,
+    /// Con '!' o '^' al principio
+    negada: bool,
+    rangos: Vec<(u8, u8)>,
+}
+
+impl Clase {
+    // La clase que empieza en `inicio`, si la hay. Un ']' justo al principio es literal. None si
+    // no se cierra, y entonces el '[' es un carácter normal.
+    fn parsear(patron: &[u8], inicio: usize) -> Option<Clase> {
+        if patron[inicio] != b'[' {
+            return None;
+        }
+        let mut k = inicio + 1;
+        let negada = matches!(patron.get(k), Some(b'!' | b'^'));
+        if negada {
+            k += 1;
+        }
+        let primero = k;
+        let mut rangos = Vec::new();
+        loop {
+            let mut c = *patron.get(k)?;
+            if c == b']' && k > primero {
+                return Some(Clase {
+                    fin: k + 1,
+                    negada,
+                    rangos,
+                });
+            }
+            if c == b'\\' {
+                k += 1;
+                c = *patron.get(k)?;
+            }
+            k += 1;
+            if patron.get(k) == Some(&b'-') && patron.get(k + 1).is_some_and(|&h| h != b']') {
+                let mut hasta = patron[k + 1];
+                k += 2;
+                if hasta == b'\\' {
+                    hasta = *patron.get(k)?;
+                    k += 1;
+                }
+                rangos.push((c, hasta));
+            } else {
+                rangos.push((c, c));
+            }
+        }
+    }
+
+    fn contiene(&self, c: u8) -> bool {
+        self.rangos.iter().any(|&(desde, hasta)| desde <= c && c <= hasta) != self.negada
+    }
+}
+
+// Un archivo que no existe o no se puede leer simplemente no aporta patrones
+fn leer_patrones(ruta: &Path, base: &str) -> Vec<Patron> {
+    let Ok(contenido) = fs::read(ruta) else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(&contenido)
+        .lines()
+        .filter_map(|linea| Patron::parsear(linea, base))
+        .collect()
+}
+
+// core.excludesFile o, si no está configurado, $XDG_CONFIG_HOME/git/ignore
+fn archivo_exclusiones_global() -> Result<Option<PathBuf>> {
+    match Configuracion::cargar()?.obtener_ruta("core.excludesFile")? {
+        Some(ruta) => Ok(Some(ruta)),
+        None => Ok(configuracion::directorio_xdg().map(|directorio| directorio.join("git/ignore"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Unos Ignorados sin globales y con el contenido de cada `.gitignore` ya cargado, indexado
+    // por su directorio ("" la raíz, "a/" un subdirectorio); los que falten quedan vacíos
+    fn con_gitignore(archivos: &[(&str, &str)], directorios: &[&str]) -> Ignorados {
+        let mut por_directorio = HashMap::new();
+        for directorio in directorios {
+            por_directorio.insert(directorio.to_string(), Vec::new());
+        }
+        for (directorio, contenido) in archivos {
+            let patrones = contenido
+                .lines()
+                .filter_map(|linea| Patron::parsear(linea, directorio))
+                .collect();
+            por_directorio.insert(directorio.to_string(), patrones);
+        }
+        Ignorados {
+            globales: Vec::new(),
+            por_directorio,
+            directorios: HashMap::new(),
+        }
+    }
+
+    fn coincide(patron: &str, ruta: &str) -> bool {
+        Patron::parsear(patron, "").unwrap().coincide(ruta, false)
+    }
+
+    #[test]
+    fn lineas_que_no_son_patrones() {
+        assert!(Patron::parsear("", "").is_none());
+        assert!(Patron::parsear("# comentario", "").is_none());
+        assert!(Patron::parsear("/", "").is_none());
+        assert!(Patron::parsear("\r", "").is_none());
+        // Los espacios del final se quitan salvo si están escapados
+        assert!(coincide("a.txt   ", "a.txt"));
+        assert!(coincide("a\\ ", "a "));
+        assert!(coincide("\\#nota", "#nota"));
+    }
+
+    #[test]
+    fn comodines() {
+        assert!(coincide("*.o", "main.o"));
+        assert!(coincide("*.o", "src/main.o"));
+        assert!(!coincide("*.o", "main.c"));
+        assert!(coincide("?.txt", "a.txt"));
+        assert!(!coincide("?.txt", "ab.txt"));
+        assert!(coincide("[abc].txt", "b.txt"));
+        assert!(!coincide("[!abc].txt", "b.txt"));
+        assert!(coincide("[a-z]*.rs", "main.rs"));
+        assert!(coincide("\\*.txt", "*.txt"));
+        assert!(!coincide("\\*.txt", "a.txt"));
+    }
+
+    #[test]
+    fn anclaje() {
+        // Sin '/' vale a cualquier profundidad
+        assert!(coincide("build", "build"));
+        assert!(coincide("build", "src/build"));
+        // Con '/' al principio o en medio, solo desde el directorio del .gitignore
+        assert!(coincide("/build", "build"));
+        assert!(!coincide("/build", "src/build"));
+        assert!(coincide("doc/*.txt", "doc/a.txt"));
+        assert!(!coincide("doc/*.txt", "src/doc/a.txt"));
+        // '*' no cruza '/'
+        assert!(!coincide("doc/*.txt", "doc/sub/a.txt"));
+        // Un patrón de un subdirectorio se ancla a ese subdirectorio
+        let patron = Patron::parsear("/tmp", "src/").unwrap();
+        assert!(patron.coincide("src/tmp", false));
+        assert!(!patron.coincide("tmp", false));
+        assert!(!patron.coincide("src/a/tmp", false));
+    }
+
+    #[test]
+    fn doble_asterisco() {
+        assert!(coincide("**/log", "log"));
+        assert!(coincide("**/log", "a/b/log"));
+        assert!(coincide("a/**/b", "a/b"));
+        assert!(coincide("a/**/b", "a/x/y/b"));
+        assert!(!coincide("a/**/b", "x/a/b"));
+        assert!(coincide("a/**", "a/x"));
+        assert!(coincide("a/**", "a/x/y"));
+        assert!(!coincide("a/**", "a"));
+        // Si no ocupa un componente entero es un '*' normal, que no cruza '/'
+        assert!(coincide("a**b", "axxb"));
+        assert!(!coincide("a**b", "a/b"));
+    }
+
+    #[test]
+    fn solo_directorios() {
+        let patron = Patron::parsear("cache/", "").unwrap();
+        assert!(patron.coincide("cache", true));
+        assert!(patron.coincide("a/cache", true));
+        assert!(!patron.coincide("cache", false));
+
+        // Lo que hay dentro del directorio ignorado también queda ignorado
+        let mut ignorados = con_gitignore(&[("", "cache/\n")], &["a/", "cache/", "a/cache/"]);
+        assert!(ignorados.ignorado("cache/x", false));
+        assert!(ignorados.ignorado("a/cache/x", false));
+        assert!(!ignorados.ignorado("cache", false));
+    }
+
+    #[test]
+    fn negacion() {
+        let mut ignorados = con_gitignore(&[("", "*.log\n!importante.log\n")], &[]);
+        assert!(ignorados.ignorado("a.log", false));
+        assert!(!ignorados.ignorado("importante.log", false));
+
+        // Gana el último patrón que coincide
+        let mut ignorados = con_gitignore(&[("", "!importante.log\n*.log\n")], &[]);
+        assert!(ignorados.ignorado("importante.log", false));
+
+        // No se puede recuperar un archivo de un directorio ignorado
+        let mut ignorados = con_gitignore(&[("", "build/\n!build/a.txt\n")], &["build/"]);
+        assert!(ignorados.ignorado("build/a.txt", false));
+    }
+
+    #[test]
+    fn gitignore_anidados() {
+        let archivos = [("", "*.tmp\nsecreto\n"), ("a/", "!*.tmp\n"), ("a/b/", "*.tmp\n")];
+        let mut ignorados = con_gitignore(&archivos, &["c/"]);
+        // El .gitignore más profundo manda sobre los de arriba
+        assert!(ignorados.ignorado("x.tmp", false));
+        assert!(!ignorados.ignorado("a/x.tmp", false));
+        assert!(ignorados.ignorado("a/b/x.tmp", false));
+        assert!(ignorados.ignorado("c/x.tmp", false));
+        // Lo que no dice nada el más profundo lo siguen decidiendo los de arriba
+        assert!(ignorados.ignorado("a/secreto", false));
+        assert!(!ignorados.ignorado("a/b/otro", false));
+
+        // Los globales tienen la menor prioridad
+        ignorados.globales = Patron::parsear("*.bak", "").into_iter().collect();
+        ignorados.globales.extend(Patron::parsear("!*.tmp", ""));
+        assert!(ignorados.ignorado("x.bak", false));
+        assert!(ignorados.ignorado("x.tmp", false));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::bloqueo::{self, Bloqueo};
+use anyhow::{Context, Result};
+use sha1::{Digest, Sha1};
+use std::fs::{self, Metadata};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+// Tamaño fijo de una entrada antes de la ruta: 10 campos de 32 bits, 20 bytes de SHA-1
+// y 16 bits de flags
+const TAMAÑO_ENTRADA_FIJO: usize = 62;
+const FLAG_EXTENDIDO: u16 = 0x4000;
+const MASCARA_LONGITUD_NOMBRE: u16 = 0x0FFF;
+
+/// Una entrada del área de preparación (.git/index)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EntradaIndice {
+    pub(crate) ctime_segundos: u32,
+    pub(crate) ctime_nanosegundos: u32,
+    pub(crate) mtime_segundos: u32,
+    pub(crate) mtime_nanosegundos: u32,
+    pub(crate) dispositivo: u32,
+    pub(crate) inodo: u32,
+    pub(crate) modo: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) tamaño: u32,
+    pub(crate) hash: [u8; 20],
+    pub(crate) flags: u16,
+    pub(crate) flags_extendidos: u16,
+    pub(crate) ruta: String,
+}
+
+impl EntradaIndice {
+    /// Construye una entrada a partir de los metadatos del archivo en el directorio de trabajo
+    pub(crate) fn desde_metadata(ruta: &str, metadata: &Metadata, hash: [u8; 20]) -> Self {
+        let modo = if metadata.file_type().is_symlink() {
+            0o120000
+        } else if metadata.mode() & 0o111 != 0 {
+            0o100755
+        } else {
+            0o100644
+        };
+
+        // Los campos del índice son de 32 bits, git trunca los valores más grandes
+        EntradaIndice {
+            ctime_segundos: metadata.ctime() as u32,
+            ctime_nanosegundos: metadata.ctime_nsec() as u32,
+            mtime_segundos: metadata.mtime() as u32,
+            mtime_nanosegundos: metadata.mtime_nsec() as u32,
+            dispositivo: metadata.dev() as u32,
+            inodo: metadata.ino() as u32,
+            modo,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            tamaño: metadata.size() as u32,
+            hash,
+            flags: ruta.len().min(MASCARA_LONGITUD_NOMBRE as usize) as u16,
+            flags_extendidos: 0,
+            ruta: ruta.to_string(),
+        }
+    }
+
+    /// Compara los metadatos guardados con los del archivo, igual que git, para decidir si hace
+    /// falta volver a calcular el hash del contenido
+    pub(crate) fn necesita_rehash(&self, metadata: &Metadata, mtime_indice: Option<(i64, i64)>) -> bool {
+        let cambiado = self.mtime_segundos != metadata.mtime() as u32
+            || self.mtime_nanosegundos != metadata.mtime_nsec() as u32
+            || self.ctime_segundos != metadata.ctime() as u32
+            || self.ctime_nanosegundos != metadata.ctime_nsec() as u32
+            || self.tamaño != metadata.size() as u32
+            || self.inodo != metadata.ino() as u32;
+        if cambiado {
+            return true;
+        }
+
+        // Entrada "racy": el archivo se modificó en el mismo instante en que se escribió el índice,
+        // así que los metadatos no bastan para saber si cambió
+        match mtime_indice {
+            Some((segundos, nanosegundos)) => {
+                (self.mtime_segundos as i64, self.mtime_nanosegundos as i64) >= (segundos, nanosegundos)
+            }
+            None => true,
+        }
+    }
+
+    /// Etapa de fusión (0 si no hay conflicto)
+    pub(crate) fn etapa(&self) -> u16 {
+        (self.flags >> 12) & 0x3
+    }
+
+    /// Con `core.fileMode` a false el bit de ejecución del disco no es fiable (sistemas de
+    /// archivos que no lo tienen): un archivo normal conserva el modo que tenía en el índice
+    pub(crate) fn conservar_modo_ejecutable(&mut self, anterior: u32) {
+        let normal = |modo: u32| modo == 0o100644 || modo == 0o100755;
+        if normal(self.modo) && normal(anterior) {
+            self.modo = anterior;
+        }
+    }
+}
+
+/// El área de preparación en formato DIRC (versiones 2 y 3)
+#[derive(Debug, Default)]
+pub(crate) struct Indice {
+    pub(crate) entradas: Vec<EntradaIndice>,
+}
+
+impl Indice {
+    /// Lee el índice desde disco. Si el archivo no existe devuelve un índice vacío.
+    pub(crate) fn leer(ruta: &Path) -> Result<Indice> {
+        if !ruta.exists() {
+            return Ok(Indice::default());
+        }
+        let datos = fs::read(ruta).context("leer .git/index")?;
+        Self::parsear(&datos)
+    }
+
+    /// Toma `index.lock` y después lee el índice. Mientras se tenga el bloqueo ningún otro
+    /// proceso puede cambiarlo, así que lo leído sigue valiendo hasta `escribir_bloqueado`.
+    pub(crate) fn leer_bloqueado(ruta: &Path) -> Result<(Indice, Bloqueo)> {
+        let bloqueo = Bloqueo::adquirir(ruta)?;
+        Ok((Self::leer(ruta)?, bloqueo))
+    }
+
+    /// Fecha de modificación del archivo de índice, necesaria para detectar entradas "racy"
+    pub(crate) fn mtime(ruta: &Path) -> Option<(i64, i64)> {
+        fs::metadata(ruta).ok().map(|m| (m.mtime(), m.mtime_nsec()))
+    }
+
+    fn parsear(datos: &[u8]) -> Result<Indice> {
+        if datos.len() < 12 + 20 {
+            anyhow::bail!("El índice es demasiado corto");
+        }
+
+        // Verificar el checksum SHA-1 al final del archivo
+        let (contenido, checksum) = datos.split_at(datos.len() - 20);
+        let calculado = Sha1::digest(contenido);
+        if calculado.as_slice() != checksum {
+            anyhow::bail!("El checksum del índice no coincide, el archivo está corrupto");
+        }
+
+        if &contenido[..4] != b"DIRC" {
+            anyhow::bail!("El índice no comienza con la firma DIRC");
+        }
+        let version = leer_u32(contenido, 4);
+        if version != 2 && version != 3 {
+            anyhow::bail!("Versión de índice no soportada: {}", version);
+        }
+        let cantidad = leer_u32(contenido, 8) as usize;
+
+        // La cantidad viene del archivo: no se reserva más de lo que cabe en él
+        let mut entradas = Vec::with_capacity(cantidad.min(contenido.len() / TAMAÑO_ENTRADA_FIJO));
+        let mut pos = 12;
+        for _ in 0..cantidad {
+            if pos + TAMAÑO_ENTRADA_FIJO > contenido.len() {
+                anyhow::bail!("Entrada del índice truncada");
+            }
+            let campo = |i: usize| leer_u32(contenido, pos + i * 4);
+            let mut hash = [0u8; 20];
+            hash.copy_from_slice(&contenido[pos + 40..pos + 60]);
+            let flags = u16::from_be_bytes([contenido[pos + 60], contenido[pos + 61]]);
+
+            let mut inicio_nombre = pos + TAMAÑO_ENTRADA_FIJO;
+            let mut flags_extendidos = 0;
+            if flags & FLAG_EXTENDIDO != 0 {
+                if version < 3 {
+                    anyhow::bail!("Entrada con flags extendidos en un índice versión 2");
+                }
+                let bytes = contenido
+                    .get(inicio_nombre..inicio_nombre + 2)
+                    .context("Entrada del índice truncada")?;
+                flags_extendidos = u16::from_be_bytes([bytes[0], bytes[1]]);
+                inicio_nombre += 2;
+            }
+
+            // El nombre termina en NUL; la longitud en flags se satura en 0xFFF
+            let fin_nombre = contenido[inicio_nombre..]
+                .iter()
+                .position(|&b| b == 0)
+                .map(|p| inicio_nombre + p)
+                .context("Nombre de entrada del índice sin terminar")?;
+            let ruta = String::from_utf8(contenido[inicio_nombre..fin_nombre].to_vec())
+                .context("la ruta de una entrada del índice no es UTF-8 válido")?;
+
+            entradas.push(EntradaIndice {
+                ctime_segundos: campo(0),
+                ctime_nanosegundos: campo(1),
+                mtime_segundos: campo(2),
+                mtime_nanosegundos: campo(3),
+                dispositivo: campo(4),
+                inodo: campo(5),
+                modo: campo(6),
+                uid: campo(7),
+                gid: campo(8),
+                tamaño: campo(9),
+                hash,
+                flags,
+                flags_extendidos,
+                ruta,
+            });
+
+            // Las entradas se rellenan con NULs hasta un múltiplo de 8 bytes
+            let longitud = fin_nombre - pos;
+            pos += (longitud + 8) & !7;
+        }
+
+        // Extensiones: las opcionales (firma en mayúscula) se pueden ignorar,
+        // se regeneran cuando git vuelve a escribir el índice
+        while pos + 8 <= contenido.len() {
+            let firma = &contenido[pos..pos + 4];
+            let tamaño = leer_u32(contenido, pos + 4) as usize;
+            if !firma[0].is_ascii_uppercase() {
+                anyhow::bail!(
+                    "Extensión obligatoria del índice no soportada: {}",
+                    String::from_utf8_lossy(firma)
+                );
+            }
+            pos += 8 + tamaño;
+        }
+
+        Ok(Indice { entradas })
+    }
+
+    /// Escribe el índice en disco
+    pub(crate) fn escribir(&self, ruta: &Path) -> Result<()> {
+        bloqueo::escribir_atomico(ruta, &self.serializar()).context("escribir .git/index")
+    }
+
+    /// Escribe el índice a través del bloqueo tomado en `leer_bloqueado`
+    pub(crate) fn escribir_bloqueado(&self, mut bloqueo: Bloqueo) -> Result<()> {
+        bloqueo.escribir(&self.serializar())?;
+        bloqueo.confirmar().context("escribir .git/index")
+    }
+
+    // Formato DIRC, usando la versión 3 solo si alguna entrada la necesita
+    fn serializar(&self) -> Vec<u8> {
+        let version: u32 = if self.entradas.iter().any(|e| e.flags_extendidos != 0) {
+            3
+        } else {
+            2
+        };
+
+        let mut datos = Vec::new();
+        datos.extend_from_slice(b"DIRC");
+        datos.extend_from_slice(&version.to_be_bytes());
+        datos.extend_from_slice(&(self.entradas.len() as u32).to_be_bytes());
+
+        for entrada in &self.entradas {
+            let inicio = datos.len();
+            for campo in [
+                entrada.ctime_segundos,
+                entrada.ctime_nanosegundos,
+                entrada.mtime_segundos,
+                entrada.mtime_nanosegundos,
+                entrada.dispositivo,
+                entrada.inodo,
+                entrada.modo,
+                entrada.uid,
+                entrada.gid,
+                entrada.tamaño,
+            ] {
+                datos.extend_from_slice(&campo.to_be_bytes());
+            }
+            datos.extend_from_slice(&entrada.hash);
+
+            let mut flags = entrada.flags & !(MASCARA_LONGITUD_NOMBRE | FLAG_EXTENDIDO);
+            flags |= entrada.ruta.len().min(MASCARA_LONGITUD_NOMBRE as usize) as u16;
+            if entrada.flags_extendidos != 0 {
+                flags |= FLAG_EXTENDIDO;
+            }
+            datos.extend_from_slice(&flags.to_be_bytes());
+            if entrada.flags_extendidos != 0 {
+                datos.extend_from_slice(&entrada.flags_extendidos.to_be_bytes());
+            }
+            datos.extend_from_slice(entrada.ruta.as_bytes());
+
+            // Al menos un NUL y relleno hasta múltiplo de 8
+            let longitud = datos.len() - inicio;
+            let relleno = 8 - (longitud % 8);
+            datos.extend(std::iter::repeat(0u8).take(relleno));
+        }
+
+        let checksum = Sha1::digest(&datos);
+        datos.extend_from_slice(&checksum);
+        datos
+    }
+
+    /// Agrega o reemplaza una entrada manteniendo el orden que exige git
+    pub(crate) fn agregar(&mut self, entrada: EntradaIndice) {
+        // Una entrada sin conflicto reemplaza todas las etapas de fusión de esa ruta
+        self.entradas.retain(|e| e.ruta != entrada.ruta || e.etapa() == entrada.etapa());
+        match self
+            .entradas
+            .binary_search_by(|e| comparar_entradas(e, &entrada.ruta, entrada.etapa()))
+        {
+            Ok(i) => self.entradas[i] = entrada,
+            Err(i) => self.entradas.insert(i, entrada),
+        }
+    }
+
+    /// La entrada sin conflicto de una ruta
+    pub(crate) fn buscar(&self, ruta: &str) -> Option<&EntradaIndice> {
+        let posicion = self.entradas.binary_search_by(|e| comparar_entradas(e, ruta, 0)).ok()?;
+        Some(&self.entradas[posicion])
+    }
+
+    pub(crate) fn eliminar(&mut self, ruta: &str) {
+        self.entradas.retain(|e| e.ruta != ruta);
+    }
+}
+
+// Las entradas se ordenan por ruta (bytes) y luego por etapa
+fn comparar_entradas(entrada: &EntradaIndice, ruta: &str, etapa: u16) -> std::cmp::Ordering {
+    entrada
+        .ruta
+        .as_bytes()
+        .cmp(ruta.as_bytes())
+        .then(entrada.etapa().cmp(&etapa))
+}
+
+fn leer_u32(datos: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([datos[pos], datos[pos + 1], datos[pos + 2], datos[pos + 3]])
+}
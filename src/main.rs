@@ -2,8 +2,21 @@ use clap::{Parser, Subcommand};
 use std::fs;
 use std::path::PathBuf;
 
+pub(crate) mod base_objetos;
+pub(crate) mod bloqueo;
 pub(crate) mod comandos;
+pub(crate) mod commit;
+pub(crate) mod configuracion;
+pub(crate) mod empaquetar;
+pub(crate) mod fecha;
+pub(crate) mod ignorar;
+pub(crate) mod indice;
 pub(crate) mod objetos;
+pub(crate) mod paquete;
+pub(crate) mod pktline;
+pub(crate) mod protocolo;
+pub(crate) mod referencias;
+pub(crate) mod revision;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -16,10 +29,30 @@ struct Argumentos {
 enum Comando {
     /// Inicializa un repositorio git
     Iniciar,
+    /// Muestra el contenido, tipo o tamaño de un objeto
+    #[clap(group(
+        clap::ArgGroup::new("modo")
+            .required(true)
+            .args(["mostrar_bonito", "tipo", "tamano", "existe", "lote", "lote_cabecera"])
+    ))]
     MostrarArchivo {
         #[clap(short = 'p')]
         mostrar_bonito: bool,
-        hash_objeto: String,
+        #[clap(short = 't')]
+        tipo: bool,
+        #[clap(short = 's')]
+        tamano: bool,
+        /// Termina con código 0 si el objeto existe, 1 si no
+        #[clap(short = 'e')]
+        existe: bool,
+        /// Lee nombres de objetos de stdin y muestra cabecera y contenido
+        #[clap(long = "batch")]
+        lote: bool,
+        /// Lee nombres de objetos de stdin y muestra solo la cabecera
+        #[clap(long = "batch-check")]
+        lote_cabecera: bool,
+        #[clap(required_unless_present_any = ["lote", "lote_cabecera"])]
+        hash_objeto: Option<String>,
     },
     HashObjeto {
         #[clap(short = 'w')]
@@ -34,18 +67,191 @@ enum Comando {
     LeerArbol {
         hash_arbol: String,
     },
+    /// Crea un objeto árbol a partir del índice
     EscribirArbol,
+    /// Agrega el contenido de archivos al índice
+    Agregar {
+        #[clap(required = true)]
+        rutas: Vec<PathBuf>,
+        /// Agrega también los archivos ignorados por .gitignore
+        #[clap(short = 'f', long = "force")]
+        forzar: bool,
+    },
     CommitArbol {
         hash_arbol: String,
+        /// Commit padre; se repite para crear una fusión
         #[clap(short = 'p')]
-        padre: Option<String>,
+        padres: Vec<String>,
+        /// Párrafo del mensaje; se puede repetir
         #[clap(short = 'm')]
-        mensaje: String,
+        mensajes: Vec<String>,
+        /// Lee el mensaje de un archivo ('-' para stdin). Sin -m ni -F se lee de stdin
+        #[clap(short = 'F')]
+        archivos: Vec<String>,
+        /// Autor del commit, como "Nombre <email>"
+        #[clap(long = "author")]
+        autor: Option<String>,
+        /// Fecha del autor, en cualquiera de los formatos de GIT_AUTHOR_DATE
+        #[clap(long = "date")]
+        fecha: Option<String>,
+        /// Solo escribe el commit, sin mover HEAD ni la rama actual
+        #[clap(long = "no-update-head")]
+        sin_actualizar_head: bool,
+    },
+    /// Crea un commit con lo preparado en el índice, encima de HEAD
+    Confirmar {
+        /// Mensaje del commit (cada -m es un párrafo); sin él se abre el editor
+        #[clap(short = 'm', long = "message")]
+        mensajes: Vec<String>,
+        /// Permite un commit que no cambia nada respecto a su padre
+        #[clap(long = "allow-empty")]
+        permitir_vacio: bool,
+        /// Reemplaza el último commit en vez de crear uno encima
+        #[clap(long = "amend")]
+        enmendar: bool,
+        /// Autor del commit, como "Nombre <email>"
+        #[clap(long = "author")]
+        autor: Option<String>,
+        /// Fecha del autor, en cualquiera de los formatos de GIT_AUTHOR_DATE
+        #[clap(long = "date")]
+        fecha: Option<String>,
+    },
+    /// Muestra el estado del árbol de trabajo
+    Estado,
+    /// Muestra el historial de commits
+    Registro {
+        /// Commit desde el que empezar (HEAD por defecto)
+        revision: Option<String>,
+        #[clap(long)]
+        oneline: bool,
+        #[clap(short = 'n')]
+        cantidad: Option<usize>,
+        #[clap(long)]
+        graph: bool,
+    },
+    /// Lista, crea, borra o renombra ramas
+    Rama {
+        /// Borra la rama (solo si está fusionada en HEAD, salvo con -f)
+        #[clap(short = 'd', long = "delete")]
+        borrar: bool,
+        /// Igual que --delete --force
+        #[clap(short = 'D')]
+        borrar_forzado: bool,
+        /// Renombra una rama
+        #[clap(short = 'm', long = "move")]
+        renombrar: bool,
+        #[clap(short = 'f', long = "force")]
+        forzar: bool,
+        nombres: Vec<String>,
+    },
+    /// Cambia de rama o de commit actualizando el árbol de trabajo y el índice
+    Cambiar {
+        /// Rama o revisión de destino (con -c, punto de inicio de la rama nueva)
+        objetivo: Option<String>,
+        /// Crea una rama nueva y cambia a ella
+        #[clap(short = 'c', long = "create")]
+        crear: Option<String>,
+        /// Deja HEAD desacoplado aunque el destino sea una rama
+        #[clap(long = "detach")]
+        separar: bool,
+        /// Descarta los cambios locales
+        #[clap(short = 'f', long = "force")]
+        forzar: bool,
+    },
+    /// Lista, crea o borra etiquetas
+    Etiqueta {
+        /// Lista las etiquetas (opcionalmente las que coinciden con un patrón)
+        #[clap(short = 'l', long = "list")]
+        listar: bool,
+        /// Muestra también la primera línea del mensaje de cada etiqueta
+        #[clap(short = 'n')]
+        con_mensaje: bool,
+        #[clap(short = 'd', long = "delete")]
+        borrar: bool,
+        /// Crea una etiqueta anotada (un objeto tag)
+        #[clap(short = 'a', long = "annotate")]
+        anotada: bool,
+        #[clap(short = 'm', long = "message")]
+        mensaje: Option<String>,
+        /// Reemplaza una etiqueta existente
+        #[clap(short = 'f', long = "force")]
+        forzar: bool,
+        nombres: Vec<String>,
     },
     Clonar {
         url: String,
         directorio_destino: PathBuf,
     },
+    /// Descarga los commits nuevos de un remoto y actualiza sus ramas remotas
+    Traer {
+        /// Nombre del remoto (por defecto el de la rama actual, u origin)
+        remoto: Option<String>,
+    },
+    /// Escribe un packfile (y su .idx) con los objetos leídos de stdin
+    EmpaquetarObjetos {
+        /// Los archivos se llaman <prefijo>-<checksum>.pack y .idx
+        prefijo: PathBuf,
+        /// Cuántos objetos anteriores se prueban como base de cada delta
+        #[clap(long = "window", default_value_t = 10)]
+        ventana: usize,
+        /// Longitud máxima de las cadenas de deltas
+        #[clap(long = "depth", default_value_t = 50)]
+        profundidad: usize,
+    },
+    /// Empaqueta los objetos alcanzables, borra los inalcanzables antiguos y empaqueta las referencias
+    Recolectar {
+        /// Plazo para borrar objetos inalcanzables: now, never o por ejemplo 2.weeks.ago
+        #[clap(long = "prune")]
+        podar: Option<String>,
+    },
+    /// Consulta o modifica la configuración (.git/config, ~/.gitconfig o /etc/gitconfig)
+    #[clap(group(clap::ArgGroup::new("ambito").args(["global", "sistema", "local"])))]
+    #[clap(group(
+        clap::ArgGroup::new("accion")
+            .args(["obtener", "obtener_todos", "listar", "agregar", "quitar", "quitar_todos"])
+    ))]
+    Configuracion {
+        /// Usa la configuración global (~/.gitconfig)
+        #[clap(long)]
+        global: bool,
+        /// Usa la configuración del sistema (/etc/gitconfig)
+        #[clap(long = "system")]
+        sistema: bool,
+        /// Usa la configuración del repositorio (.git/config)
+        #[clap(long)]
+        local: bool,
+        #[clap(long = "get")]
+        obtener: bool,
+        /// Muestra todos los valores de una variable
+        #[clap(long = "get-all")]
+        obtener_todos: bool,
+        /// Muestra todas las variables
+        #[clap(short = 'l', long = "list")]
+        listar: bool,
+        /// Añade un valor sin reemplazar los que ya tiene la variable
+        #[clap(long = "add", requires = "valor")]
+        agregar: bool,
+        #[clap(long = "unset")]
+        quitar: bool,
+        #[clap(long = "unset-all")]
+        quitar_todos: bool,
+        /// seccion.clave o seccion.subseccion.clave
+        #[clap(required_unless_present = "listar")]
+        nombre: Option<String>,
+        valor: Option<String>,
+    },
+    /// Comprueba la integridad de la base de objetos y la conectividad desde las referencias
+    Verificar,
+    /// Envía commits locales a un remoto y actualiza sus ramas
+    Empujar {
+        /// Nombre del remoto (por defecto el de la rama actual, u origin)
+        remoto: Option<String>,
+        /// Qué empujar: `[+]<origen>[:<destino>]` (por defecto la rama actual)
+        especificaciones: Vec<String>,
+        /// Actualiza las referencias aunque no sea fast-forward
+        #[clap(short = 'f', long = "force")]
+        forzar: bool,
+    },
 }
 
 fn main() -> anyhow::Result<()> {
@@ -62,8 +268,30 @@ fn main() -> anyhow::Result<()> {
         }
         Comando::MostrarArchivo {
             mostrar_bonito,
+            tipo,
+            tamano,
+            existe,
+            lote,
+            lote_cabecera,
             hash_objeto,
-        } => comandos::mostrar_archivo::ejecutar(mostrar_bonito, &hash_objeto)?,
+        } => {
+            use comandos::mostrar_archivo::Modo;
+            let modo = if tipo {
+                Modo::Tipo
+            } else if tamano {
+                Modo::Tamaño
+            } else if existe {
+                Modo::Existe
+            } else if lote {
+                Modo::Lote
+            } else if lote_cabecera {
+                Modo::LoteCabecera
+            } else {
+                debug_assert!(mostrar_bonito);
+                Modo::Bonito
+            };
+            comandos::mostrar_archivo::ejecutar(modo, hash_objeto.as_deref())?
+        }
         Comando::HashObjeto { escribir, archivo } => comandos::hash_objeto::ejecutar(escribir, &archivo)?,
         Comando::ListarArbol {
             solo_nombres,
@@ -71,10 +299,78 @@ fn main() -> anyhow::Result<()> {
         } => comandos::listar_arbol::ejecutar(solo_nombres, &hash_arbol)?,
         Comando::LeerArbol { hash_arbol } => comandos::leer_arbol::ejecutar(&hash_arbol)?,
         Comando::EscribirArbol => comandos::escribir_arbol::ejecutar()?,
-        Comando::CommitArbol { hash_arbol, padre, mensaje } => 
-            comandos::commit_arbol::ejecutar(&hash_arbol, padre.as_deref(), &mensaje)?,
+        Comando::Agregar { rutas, forzar } => comandos::agregar::ejecutar(&rutas, forzar)?,
+        Comando::CommitArbol { hash_arbol, padres, mensajes, archivos, autor, fecha, sin_actualizar_head } => 
+            comandos::commit_arbol::ejecutar(
+                &hash_arbol,
+                &padres,
+                &mensajes,
+                &archivos,
+                autor.as_deref(),
+                fecha.as_deref(),
+                !sin_actualizar_head,
+            )?,
+        Comando::Confirmar { mensajes, permitir_vacio, enmendar, autor, fecha } =>
+            comandos::confirmar::ejecutar(&mensajes, permitir_vacio, enmendar, autor.as_deref(), fecha.as_deref())?,
+        Comando::Estado => comandos::estado::ejecutar()?,
+        Comando::Registro { revision, oneline, cantidad, graph } =>
+            comandos::registro::ejecutar(revision.as_deref(), oneline, cantidad, graph)?,
+        Comando::Rama { borrar, borrar_forzado, renombrar, forzar, nombres } =>
+            comandos::rama::ejecutar(&nombres, borrar || borrar_forzado, renombrar, forzar || borrar_forzado)?,
+        Comando::Cambiar { objetivo, crear, separar, forzar } =>
+            comandos::cambiar::ejecutar(objetivo.as_deref(), crear.as_deref(), separar, forzar)?,
+        Comando::Etiqueta { listar, con_mensaje, borrar, anotada, mensaje, forzar, nombres } =>
+            comandos::etiqueta::ejecutar(&nombres, listar, con_mensaje, borrar, anotada, mensaje.as_deref(), forzar)?,
         Comando::Clonar { url, directorio_destino } => 
             comandos::clonar::ejecutar(&url, &directorio_destino)?,
+        Comando::Traer { remoto } => comandos::traer::ejecutar(remoto.as_deref())?,
+        Comando::EmpaquetarObjetos { prefijo, ventana, profundidad } =>
+            comandos::empaquetar_objetos::ejecutar(&prefijo, empaquetar::Opciones { ventana, profundidad })?,
+        Comando::Recolectar { podar } => comandos::recolectar::ejecutar(podar.as_deref())?,
+        Comando::Configuracion {
+            global,
+            sistema,
+            local,
+            obtener,
+            obtener_todos,
+            listar,
+            agregar,
+            quitar,
+            quitar_todos,
+            nombre,
+            valor,
+        } => {
+            use comandos::configuracion::Accion;
+            use configuracion::Ambito;
+            let ambito = if global {
+                Some(Ambito::Global)
+            } else if sistema {
+                Some(Ambito::Sistema)
+            } else if local {
+                Some(Ambito::Local)
+            } else {
+                None
+            };
+            let accion = if listar {
+                Accion::Listar
+            } else if obtener_todos {
+                Accion::ObtenerTodos
+            } else if agregar {
+                Accion::Agregar
+            } else if quitar {
+                Accion::Quitar
+            } else if quitar_todos {
+                Accion::QuitarTodos
+            } else if valor.is_some() && !obtener {
+                Accion::Asignar
+            } else {
+                Accion::Obtener
+            };
+            comandos::configuracion::ejecutar(accion, ambito, nombre.as_deref(), valor.as_deref())?
+        }
+        Comando::Verificar => comandos::verificar::ejecutar()?,
+        Comando::Empujar { remoto, especificaciones, forzar } =>
+            comandos::empujar::ejecutar(remoto.as_deref(), &especificaciones, forzar)?,
     }
     Ok(())
 }
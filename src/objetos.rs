@@ -1,15 +1,15 @@
+use crate::base_objetos::{validar_hash, BaseObjetos};
 use anyhow::Context;
-use flate2::read::ZlibDecoder;
-use std::ffi::CStr;
+use std::cell::RefCell;
 use std::fmt;
 use std::io::prelude::*;
-use std::io::BufReader;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub(crate) enum Tipo {
     Blob,
     Arbol,
     Commit,
+    Tag,
 }
 
 impl fmt::Display for Tipo {
@@ -18,6 +18,7 @@ impl fmt::Display for Tipo {
             Tipo::Blob => write!(f, "blob"),
             Tipo::Arbol => write!(f, "tree"),
             Tipo::Commit => write!(f, "commit"),
+            Tipo::Tag => write!(f, "tag"),
         }
     }
 }
@@ -28,39 +29,226 @@ pub(crate) struct Objeto<R> {
     pub(crate) lector: R,
 }
 
-impl Objeto<()> {
-    pub(crate) fn leer(hash: &str) -> anyhow::Result<Objeto<impl BufRead>> {
-        let f = std::fs::File::open(format!(".git/objects/{}/{}", &hash[..2], &hash[2..]))
-            .context("abrir en .git/objects")?;
-        let z = ZlibDecoder::new(f);
-        let mut z = BufReader::new(z);
-        let mut buf = Vec::new();
-        z.read_until(0, &mut buf)
-            .context("leer cabecera desde .git/objects")?;
-        let cabecera = CStr::from_bytes_with_nul(&buf)
-            .expect("sabemos que hay exactamente un nul, y está al final");
-        let cabecera = cabecera
-            .to_str()
-            .context("la cabecera del archivo .git/objects no es UTF-8 válido")?;
-        let Some((tipo, tamaño)) = cabecera.split_once(' ') else {
-            anyhow::bail!("La cabecera del archivo .git/objects no comenzó con un tipo conocido: '{cabecera}'");
-        };
-        let tipo = match tipo {
+impl Tipo {
+    /// Tipo a partir del nombre que usa git en las cabeceras (`blob`, `tree`, `commit`, `tag`)
+    pub(crate) fn desde_nombre(nombre: &str) -> anyhow::Result<Tipo> {
+        Ok(match nombre {
             "blob" => Tipo::Blob,
             "tree" => Tipo::Arbol,
             "commit" => Tipo::Commit,
-            _ => anyhow::bail!("¿Qué es un '{tipo}'?"),
-        };
-        let tamaño = tamaño
-            .parse::<u64>()
-            .context("La cabecera del archivo .git/objects tiene un tamaño inválido: {tamaño}")?;
-        // NOTA: esto no dará error si el archivo descomprimido es demasiado largo, pero al menos
-        // no spameará stdout y será vulnerable a un zipbomb.
-        let z = z.take(tamaño);
-        Ok(Objeto {
-            tipo,
-            tamaño_esperado: tamaño,
-            lector: z,
+            "tag" => Tipo::Tag,
+            _ => anyhow::bail!("¿Qué es un '{nombre}'?"),
         })
     }
-} 
\ No newline at end of file
+}
+
+thread_local! {
+    // Una sola base por proceso, para no volver a cargar los índices de los paquetes en cada
+    // lectura. La lista de paquetes se carga en la primera lectura: quien añade o borra paquetes
+    // después tiene que llamar a `Objeto::recargar_base()`.
+    static BASE_REPOSITORIO: RefCell<BaseObjetos> = RefCell::new(BaseObjetos::abrir(".git"));
+}
+
+impl Objeto<()> {
+    /// Lee un objeto de la base de objetos del repositorio actual (`.git`)
+    pub(crate) fn leer(hash: &str) -> anyhow::Result<Objeto<Box<dyn BufRead>>> {
+        BASE_REPOSITORIO.with(|base| base.borrow().leer(hash))
+    }
+
+    /// Vuelve a abrir la base del repositorio actual para que las lecturas vean los paquetes que
+    /// se han guardado o borrado desde la primera
+    pub(crate) fn recargar_base() {
+        BASE_REPOSITORIO.with(|base| *base.borrow_mut() = BaseObjetos::abrir(".git"));
+    }
+}
+
+/// Autor o committer de un commit: `Nombre <email> 1700000000 +0100`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Firma {
+    pub(crate) nombre: String,
+    pub(crate) email: String,
+    pub(crate) timestamp: i64,
+    pub(crate) zona_horaria: String,
+}
+
+impl Firma {
+    pub(crate) fn parsear(linea: &str) -> anyhow::Result<Firma> {
+        let (nombre, resto) = linea
+            .split_once(" <")
+            .with_context(|| format!("Firma sin email: '{linea}'"))?;
+        let (email, resto) = resto
+            .split_once("> ")
+            .with_context(|| format!("Firma sin fecha: '{linea}'"))?;
+        let (timestamp, zona_horaria) = resto.split_once(' ').unwrap_or((resto, "+0000"));
+        Ok(Firma {
+            nombre: nombre.to_string(),
+            email: email.to_string(),
+            timestamp: timestamp
+                .parse()
+                .with_context(|| format!("Fecha inválida en la firma: '{linea}'"))?,
+            zona_horaria: zona_horaria.to_string(),
+        })
+    }
+
+    /// Desplazamiento de la zona horaria en segundos (+0130 -> 5400)
+    pub(crate) fn desplazamiento(&self) -> i64 {
+        let signo = if self.zona_horaria.starts_with('-') { -1 } else { 1 };
+        let digitos = self.zona_horaria.trim_start_matches(['+', '-']);
+        let horas = digitos.get(..2).and_then(|h| h.parse::<i64>().ok()).unwrap_or(0);
+        let minutos = digitos.get(2..4).and_then(|m| m.parse::<i64>().ok()).unwrap_or(0);
+        signo * (horas * 3600 + minutos * 60)
+    }
+
+    /// Fecha en la zona horaria del autor, por ejemplo `jue 18 oct 2026 12:00:00 +0200`
+    pub(crate) fn fecha_legible(&self) -> String {
+        const DIAS: [&str; 7] = ["jue", "vie", "sáb", "dom", "lun", "mar", "mié"];
+        const MESES: [&str; 12] = [
+            "ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov", "dic",
+        ];
+
+        let local = self.timestamp + self.desplazamiento();
+        let dias = local.div_euclid(86400);
+        let segundos_del_dia = local.rem_euclid(86400);
+
+        // Conversión de días desde 1970-01-01 a fecha civil (algoritmo de Howard Hinnant)
+        let z = dias + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let dia = doy - (153 * mp + 2) / 5 + 1;
+        let mes = if mp < 10 { mp + 3 } else { mp - 9 };
+        let año = yoe + era * 400 + if mes <= 2 { 1 } else { 0 };
+
+        format!(
+            "{} {} {} {} {:02}:{:02}:{:02} {}",
+            DIAS[dias.rem_euclid(7) as usize],
+            dia,
+            MESES[(mes - 1) as usize],
+            año,
+            segundos_del_dia / 3600,
+            segundos_del_dia % 3600 / 60,
+            segundos_del_dia % 60,
+            self.zona_horaria
+        )
+    }
+}
+
+impl fmt::Display for Firma {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} <{}> {} {}", self.nombre, self.email, self.timestamp, self.zona_horaria)
+    }
+}
+
+/// Un objeto commit ya parseado
+#[derive(Debug, Clone)]
+pub(crate) struct Commit {
+    pub(crate) arbol: String,
+    pub(crate) padres: Vec<String>,
+    pub(crate) autor: Firma,
+    pub(crate) committer: Firma,
+    pub(crate) mensaje: String,
+}
+
+impl Commit {
+    pub(crate) fn leer(hash: &str) -> anyhow::Result<Commit> {
+        let mut objeto = Objeto::leer(hash)?;
+        if objeto.tipo != Tipo::Commit {
+            anyhow::bail!("El objeto {hash} es un {}, no un commit", objeto.tipo);
+        }
+        let mut contenido = Vec::new();
+        objeto.lector.read_to_end(&mut contenido)?;
+        Self::parsear(&contenido).with_context(|| format!("parsear el commit {hash}"))
+    }
+
+    pub(crate) fn parsear(contenido: &[u8]) -> anyhow::Result<Commit> {
+        let contenido = String::from_utf8_lossy(contenido);
+        let (cabecera, mensaje) = contenido.split_once("\n\n").unwrap_or((&contenido, ""));
+
+        let mut arbol = None;
+        let mut padres = Vec::new();
+        let mut autor = None;
+        let mut committer = None;
+        for linea in cabecera.lines() {
+            // Las líneas de continuación (gpgsig, mergetag...) empiezan con un espacio
+            if linea.starts_with(' ') {
+                continue;
+            }
+            let (clave, valor) = linea.split_once(' ').unwrap_or((linea, ""));
+            match clave {
+                // Quien usa el commit da por hecho que son hashes completos (registro los acorta)
+                "tree" => {
+                    validar_hash(valor).context("Línea 'tree' inválida")?;
+                    arbol = Some(valor.to_string());
+                }
+                "parent" => {
+                    validar_hash(valor).context("Línea 'parent' inválida")?;
+                    padres.push(valor.to_string());
+                }
+                "author" => autor = Some(Firma::parsear(valor)?),
+                "committer" => committer = Some(Firma::parsear(valor)?),
+                _ => {}
+            }
+        }
+
+        Ok(Commit {
+            arbol: arbol.context("El commit no tiene línea 'tree'")?,
+            padres,
+            autor: autor.context("El commit no tiene línea 'author'")?,
+            committer: committer.context("El commit no tiene línea 'committer'")?,
+            mensaje: mensaje.to_string(),
+        })
+    }
+}
+
+/// Un objeto tag (etiqueta anotada) ya parseado
+#[derive(Debug, Clone)]
+pub(crate) struct Etiqueta {
+    pub(crate) objeto: String,
+    pub(crate) tipo: Tipo,
+    pub(crate) mensaje: String,
+}
+
+impl Etiqueta {
+    pub(crate) fn leer(hash: &str) -> anyhow::Result<Etiqueta> {
+        let mut objeto = Objeto::leer(hash)?;
+        if objeto.tipo != Tipo::Tag {
+            anyhow::bail!("El objeto {hash} es un {}, no un tag", objeto.tipo);
+        }
+        let mut contenido = Vec::new();
+        objeto.lector.read_to_end(&mut contenido)?;
+        Self::parsear(&contenido).with_context(|| format!("parsear el tag {hash}"))
+    }
+
+    pub(crate) fn parsear(contenido: &[u8]) -> anyhow::Result<Etiqueta> {
+        let contenido = String::from_utf8_lossy(contenido);
+        let (cabecera, mensaje) = contenido.split_once("\n\n").unwrap_or((&contenido, ""));
+
+        let mut objeto = None;
+        let mut tipo = None;
+        let mut tiene_nombre = false;
+        for linea in cabecera.lines() {
+            let (clave, valor) = linea.split_once(' ').unwrap_or((linea, ""));
+            match clave {
+                "object" => objeto = Some(valor.to_string()),
+                "type" => tipo = Some(Tipo::desde_nombre(valor)?),
+                "tag" => tiene_nombre = !valor.is_empty(),
+                // Los tags muy antiguos no tienen etiquetador, pero si lo hay debe ser válido
+                "tagger" => {
+                    Firma::parsear(valor)?;
+                }
+                _ => {}
+            }
+        }
+
+        if !tiene_nombre {
+            anyhow::bail!("El tag no tiene línea 'tag'");
+        }
+        Ok(Etiqueta {
+            objeto: objeto.context("El tag no tiene línea 'object'")?,
+            tipo: tipo.context("El tag no tiene línea 'type'")?,
+            mensaje: mensaje.to_string(),
+        })
+    }
+}
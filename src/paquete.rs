@@ -0,0 +1,876 @@
+use crate::base_objetos::{calcular_hash_bytes, BaseObjetos};
+use crate::objetos::Tipo;
+use anyhow::{Context, Result};
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use sha1::{Digest, Sha1};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+const FIRMA_IDX: [u8; 4] = [0xff, b't', b'O', b'c'];
+const INICIO_FANOUT: usize = 8;
+const INICIO_HASHES: usize = INICIO_FANOUT + 256 * 4;
+
+// Cuánto ocupan como máximo las bases de delta guardadas en memoria por paquete
+const LIMITE_CACHE_BASES: usize = 32 * 1024 * 1024;
+
+/// Un packfile (`pack-*.pack`) junto con su índice `.idx` versión 2
+pub(crate) struct Paquete {
+    ruta_pack: PathBuf,
+    /// El `.idx` completo; las búsquedas se hacen directamente sobre sus tablas
+    idx: Vec<u8>,
+    cantidad: usize,
+    // El .pack se abre la primera vez que se lee un objeto
+    lector: RefCell<Option<BufReader<File>>>,
+    cache_bases: RefCell<CacheBases>,
+}
+
+// Objetos ya reconstruidos que sirven de base a otros deltas, por offset en el paquete.
+// Al pasar el límite se descartan los más antiguos.
+#[derive(Default)]
+struct CacheBases {
+    objetos: HashMap<u64, (Tipo, Rc<Vec<u8>>)>,
+    orden: VecDeque<u64>,
+    tamaño: usize,
+}
+
+impl CacheBases {
+    fn obtener(&self, offset: u64) -> Option<(Tipo, Rc<Vec<u8>>)> {
+        self.objetos.get(&offset).cloned()
+    }
+
+    fn guardar(&mut self, offset: u64, tipo: Tipo, datos: Rc<Vec<u8>>) {
+        if datos.len() > LIMITE_CACHE_BASES || self.objetos.contains_key(&offset) {
+            return;
+        }
+        self.tamaño += datos.len();
+        self.objetos.insert(offset, (tipo, datos));
+        self.orden.push_back(offset);
+        while self.tamaño > LIMITE_CACHE_BASES {
+            let Some(viejo) = self.orden.pop_front() else { break };
+            if let Some((_, datos)) = self.objetos.remove(&viejo) {
+                self.tamaño -= datos.len();
+            }
+        }
+    }
+}
+
+impl Paquete {
+    /// Abre un paquete a partir de la ruta de su `.idx`
+    pub(crate) fn abrir(ruta_idx: &Path) -> Result<Paquete> {
+        let idx = fs::read(ruta_idx).with_context(|| format!("No se pudo leer {}", ruta_idx.display()))?;
+        if idx.len() < INICIO_HASHES || idx[..4] != FIRMA_IDX || leer_u32(&idx, 4) != 2 {
+            anyhow::bail!("{} no es un índice de paquete versión 2", ruta_idx.display());
+        }
+
+        // La última entrada de la tabla fanout es el número total de objetos
+        let cantidad = leer_u32(&idx, INICIO_FANOUT + 255 * 4) as usize;
+        if idx.len() < INICIO_HASHES + cantidad * (20 + 4 + 4) + 40 {
+            anyhow::bail!("El índice {} está truncado", ruta_idx.display());
+        }
+
+        Ok(Paquete {
+            idx,
+            cantidad,
+            ..Paquete::sin_indice(&ruta_idx.with_extension("pack"))
+        })
+    }
+
+    // Un .pack que todavía no tiene .idx: solo sirve para leer por offset mientras se indexa
+    fn sin_indice(ruta_pack: &Path) -> Paquete {
+        Paquete {
+            ruta_pack: ruta_pack.to_path_buf(),
+            idx: Vec::new(),
+            cantidad: 0,
+            lector: RefCell::new(None),
+            cache_bases: RefCell::new(CacheBases::default()),
+        }
+    }
+
+    /// Offset del objeto dentro del `.pack`, si este paquete lo contiene
+    pub(crate) fn buscar(&self, hash: &[u8; 20]) -> Option<u64> {
+        // La tabla fanout acota los candidatos a los que empiezan con el mismo byte; dentro de
+        // ese rango los hashes están ordenados y se busca de forma binaria
+        let (mut inicio, mut fin) = self.rango_fanout(hash[0]);
+
+        while inicio < fin {
+            let medio = inicio + (fin - inicio) / 2;
+            match self.hash_en(medio).cmp(hash) {
+                Ordering::Less => inicio = medio + 1,
+                Ordering::Greater => fin = medio,
+                Ordering::Equal => return Some(self.offset_en(medio)),
+            }
+        }
+        None
+    }
+
+    /// Vuelve a indexar el `.pack` desde cero (descomprime y hashea cada objeto, calcula los
+    /// CRC32 y comprueba el checksum final) y compara el resultado byte a byte con el `.idx`.
+    /// Devuelve cuántos objetos tiene.
+    pub(crate) fn verificar(&self, base: &BaseObjetos) -> Result<usize> {
+        let (objetos, checksum) = indexar_con(&self.ruta_pack, base, false)?;
+        if generar_idx(&objetos, &checksum) != self.idx {
+            anyhow::bail!("El índice de {} no corresponde a su contenido", self.ruta_pack.display());
+        }
+        Ok(objetos.len())
+    }
+
+    /// Los hashes del paquete que empiezan por `prefijo`, en hexadecimal; solo se recorren los
+    /// que la tabla fanout asigna a su primer byte
+    pub(crate) fn buscar_prefijo(&self, primer_byte: u8, prefijo: &str) -> Vec<String> {
+        let (inicio, fin) = self.rango_fanout(primer_byte);
+        (inicio..fin)
+            .map(|posicion| hex::encode(self.hash_en(posicion)))
+            .filter(|hash| hash.starts_with(prefijo))
+            .collect()
+    }
+
+    // Posiciones de los hashes que empiezan por un byte. Con un .idx dañado la tabla fanout
+    // podría apuntar más allá de los hashes, así que se recorta a la cantidad de objetos.
+    fn rango_fanout(&self, primer_byte: u8) -> (usize, usize) {
+        let primer_byte = primer_byte as usize;
+        let inicio = if primer_byte == 0 {
+            0
+        } else {
+            leer_u32(&self.idx, INICIO_FANOUT + (primer_byte - 1) * 4) as usize
+        };
+        let fin = (leer_u32(&self.idx, INICIO_FANOUT + primer_byte * 4) as usize).min(self.cantidad);
+        (inicio.min(fin), fin)
+    }
+
+    pub(crate) fn ruta(&self) -> &Path {
+        &self.ruta_pack
+    }
+
+    pub(crate) fn hashes(&self) -> impl Iterator<Item = &[u8; 20]> {
+        (0..self.cantidad).map(|i| self.hash_en(i))
+    }
+
+    fn hash_en(&self, posicion: usize) -> &[u8; 20] {
+        let inicio = INICIO_HASHES + posicion * 20;
+        self.idx[inicio..inicio + 20].try_into().expect("son 20 bytes")
+    }
+
+    fn offset_en(&self, posicion: usize) -> u64 {
+        let inicio_offsets = INICIO_HASHES + self.cantidad * (20 + 4);
+        let offset = leer_u32(&self.idx, inicio_offsets + posicion * 4);
+        if offset & 0x8000_0000 == 0 {
+            return offset as u64;
+        }
+        // Con el bit alto activado el offset real está en la tabla de offsets de 64 bits
+        let posicion_larga = inicio_offsets + self.cantidad * 4 + (offset & 0x7fff_ffff) as usize * 8;
+        self.idx
+            .get(posicion_larga..posicion_larga + 8)
+            .map_or(u64::MAX, |bytes| u64::from_be_bytes(bytes.try_into().expect("son 8 bytes")))
+    }
+
+    /// Lee el objeto que empieza en `offset`, resolviendo la cadena de deltas. Las bases de un
+    /// REF_DELTA pueden estar en otro paquete o sueltas, por eso se recibe la base de objetos.
+    pub(crate) fn leer(&self, offset: u64, base: &BaseObjetos) -> Result<(Tipo, Vec<u8>)> {
+        let objeto = self.leer_con(offset, &mut |hash_base| base.leer_completo(&hex::encode(hash_base)).map(Some))?;
+        Ok(objeto.expect("la base de objetos siempre devuelve la base o un error"))
+    }
+
+    // Como `leer`, pero quien llama decide de dónde salen las bases REF_DELTA. Si no la
+    // encuentra (None), el objeto todavía no se puede reconstruir.
+    fn leer_con(&self, offset: u64, base_externa: &mut BuscarBase) -> Result<Option<(Tipo, Vec<u8>)>> {
+        // Bajar por la cadena de deltas hasta un objeto completo (o una base ya en caché)
+        let mut deltas = Vec::new();
+        let mut actual = offset;
+        // offset_base queda en None cuando la base viene de fuera del paquete (REF_DELTA)
+        let (tipo, mut datos, offset_base) = loop {
+            if let Some((tipo, datos)) = self.cache_bases.borrow().obtener(actual) {
+                break (tipo, datos, Some(actual));
+            }
+
+            let (numero_tipo, base_delta, datos_entrada) = self.leer_entrada(actual)?;
+            match base_delta {
+                BaseDelta::Ninguna => break (tipo_empacado(numero_tipo)?, Rc::new(datos_entrada), Some(actual)),
+                // OFS_DELTA: la base está en este mismo paquete, N bytes antes
+                BaseDelta::Offset(offset_base) => {
+                    deltas.push((actual, datos_entrada));
+                    actual = offset_base;
+                }
+                // REF_DELTA: la base se identifica por su hash
+                BaseDelta::Hash(hash_base) => {
+                    deltas.push((actual, datos_entrada));
+                    let Some((tipo, datos)) = base_externa(&hash_base)? else {
+                        return Ok(None);
+                    };
+                    break (tipo, Rc::new(datos), None);
+                }
+            }
+            if deltas.len() > 10_000 {
+                anyhow::bail!("Cadena de deltas demasiado larga en {}", self.ruta_pack.display());
+            }
+        };
+
+        // Aplicar los deltas desde la base hacia arriba; los resultados intermedios sirven de
+        // base a otros objetos, así que se guardan en la caché
+        if let (Some(offset_base), false) = (offset_base, deltas.is_empty()) {
+            self.cache_bases.borrow_mut().guardar(offset_base, tipo, datos.clone());
+        }
+        while let Some((offset_delta, delta)) = deltas.pop() {
+            datos = Rc::new(aplicar_delta(&delta, &datos)?);
+            if !deltas.is_empty() {
+                self.cache_bases.borrow_mut().guardar(offset_delta, tipo, datos.clone());
+            }
+        }
+
+        Ok(Some((tipo, Rc::try_unwrap(datos).unwrap_or_else(|compartido| (*compartido).clone()))))
+    }
+
+    // Una entrada sin resolver: cabecera, base del delta y datos descomprimidos
+    fn leer_entrada(&self, offset: u64) -> Result<(u8, BaseDelta, Vec<u8>)> {
+        let mut lector = self.lector.borrow_mut();
+        if lector.is_none() {
+            let archivo = File::open(&self.ruta_pack)
+                .with_context(|| format!("No se pudo abrir {}", self.ruta_pack.display()))?;
+            *lector = Some(BufReader::new(archivo));
+        }
+        let lector = lector.as_mut().expect("se acaba de abrir");
+        lector.seek(SeekFrom::Start(offset))?;
+
+        let (numero_tipo, tamaño) = leer_cabecera_objeto(lector)?;
+        let base = match numero_tipo {
+            1..=4 => BaseDelta::Ninguna,
+            6 => BaseDelta::Offset(
+                offset
+                    .checked_sub(leer_offset_delta(lector)?)
+                    .context("OFS_DELTA apunta antes del inicio del paquete")?,
+            ),
+            7 => {
+                let mut hash_base = [0u8; 20];
+                lector.read_exact(&mut hash_base)?;
+                BaseDelta::Hash(hash_base)
+            }
+            _ => anyhow::bail!("Tipo de objeto desconocido en el paquete: {}", numero_tipo),
+        };
+
+        Ok((numero_tipo, base, descomprimir(lector, tamaño)?))
+    }
+}
+
+/// Un objeto del paquete tal como queda registrado en el `.idx`
+pub(crate) struct ObjetoIndexado {
+    pub(crate) hash: [u8; 20],
+    pub(crate) offset: u64,
+    pub(crate) crc32: u32,
+}
+
+// Dónde está la base de un objeto del paquete
+enum BaseDelta {
+    Ninguna,
+    Offset(u64),
+    Hash([u8; 20]),
+}
+
+type BuscarBase<'a> = dyn FnMut(&[u8; 20]) -> Result<Option<(Tipo, Vec<u8>)>> + 'a;
+
+// Lo que se recuerda de cada entrada tras la primera pasada; los datos se vuelven a leer del disco
+struct EntradaPaquete {
+    offset: u64,
+    crc32: u32,
+    // None para los deltas hasta que se resuelven
+    hash: Option<[u8; 20]>,
+}
+
+/// Recorre un packfile ya guardado en disco, resuelve sus deltas y calcula el hash de cada objeto
+/// (lo mismo que hace `git index-pack`). Devuelve los objetos ordenados por hash y el checksum
+/// del paquete. Las bases de REF_DELTA que no estén en el paquete se buscan en la base de objetos.
+///
+/// Ningún objeto se guarda entero en memoria durante la primera pasada: los que no son deltas se
+/// hashean a medida que se descomprimen y de los deltas solo se anota dónde están. Después se
+/// reconstruyen leyendo del `.pack` con la misma caché de bases acotada que usan las lecturas.
+pub(crate) fn indexar(ruta_pack: &Path, base: &BaseObjetos) -> Result<(Vec<ObjetoIndexado>, [u8; 20])> {
+    indexar_con(ruta_pack, base, true)
+}
+
+// Con `completar` a false, un paquete thin es un error en vez de completarse: es lo que se
+// quiere al verificar un paquete que ya está guardado
+fn indexar_con(ruta_pack: &Path, base: &BaseObjetos, completar: bool) -> Result<(Vec<ObjetoIndexado>, [u8; 20])> {
+    let archivo = File::open(ruta_pack).with_context(|| format!("No se pudo abrir {}", ruta_pack.display()))?;
+    let mut lector = LectorContado::new(BufReader::with_capacity(64 * 1024, archivo));
+
+    let mut cabecera = [0u8; 12];
+    lector.read_exact(&mut cabecera).context("Formato de packfile inválido")?;
+    if &cabecera[..4] != b"PACK" {
+        anyhow::bail!("Formato de packfile inválido");
+    }
+    let version = leer_u32(&cabecera, 4);
+    if version != 2 && version != 3 {
+        anyhow::bail!("Versión de packfile no soportada: {}", version);
+    }
+    let cantidad = leer_u32(&cabecera, 8) as usize;
+
+    // Primera pasada: cabecera, CRC y hash (si no es un delta) de cada entrada
+    let mut entradas = Vec::with_capacity(cantidad.min(RESERVA_MAXIMA));
+    let mut bases_ref = BTreeSet::new();
+    for _ in 0..cantidad {
+        let offset = lector.posicion;
+        lector.crc = Crc::new();
+        let (numero_tipo, tamaño) = leer_cabecera_objeto(&mut lector)?;
+        let tipo = match numero_tipo {
+            6 => {
+                offset
+                    .checked_sub(leer_offset_delta(&mut lector)?)
+                    .context("OFS_DELTA apunta antes del inicio del paquete")?;
+                None
+            }
+            7 => {
+                let mut hash_base = [0u8; 20];
+                lector.read_exact(&mut hash_base)?;
+                bases_ref.insert(hash_base);
+                None
+            }
+            _ => Some(tipo_empacado(numero_tipo)?),
+        };
+
+        // El decodificador de bufread consume solo los bytes comprimidos de esta entrada
+        let mut decodificador = ZlibDecoder::new(&mut lector);
+        let (leidos, hash) = match tipo {
+            Some(tipo) => {
+                let mut hasher = Sha1::new();
+                hasher.update(format!("{} {}\0", tipo, tamaño));
+                let leidos = io::copy(&mut decodificador, &mut hasher)?;
+                (leidos, Some(hasher.finalize().into()))
+            }
+            None => (io::copy(&mut decodificador, &mut io::sink())?, None),
+        };
+        if leidos != tamaño {
+            anyhow::bail!("Objeto empacado truncado en el offset {}", offset);
+        }
+
+        entradas.push(EntradaPaquete {
+            offset,
+            crc32: lector.crc.sum(),
+            hash,
+        });
+    }
+
+    let fin_entradas = lector.posicion;
+    let checksum: [u8; 20] = lector.sha.clone().finalize().into();
+    let mut checksum_leido = [0u8; 20];
+    lector.read_exact(&mut checksum_leido).context("El packfile está truncado")?;
+    if checksum != checksum_leido {
+        anyhow::bail!("El checksum del packfile no coincide: los datos están corruptos");
+    }
+    if !lector.fill_buf()?.is_empty() {
+        anyhow::bail!("Hay datos de más al final del packfile");
+    }
+
+    // Segunda pasada: reconstruir los deltas. Las bases OFS_DELTA siempre van antes en el
+    // paquete, pero una base REF_DELTA puede aparecer después, así que se repite hasta no avanzar.
+    let paquete = Paquete::sin_indice(ruta_pack);
+    let mut por_hash: HashMap<[u8; 20], u64> =
+        entradas.iter().filter_map(|e| Some((e.hash?, e.offset))).collect();
+    let mut pendientes = entradas.iter().filter(|e| e.hash.is_none()).count();
+    while pendientes > 0 {
+        let mut progreso = false;
+        for entrada in entradas.iter_mut().filter(|e| e.hash.is_none()) {
+            if let Some((tipo, datos)) = leer_indexando(&paquete, entrada.offset, &por_hash, base)? {
+                let hash = calcular_hash_bytes(tipo, &datos);
+                por_hash.insert(hash, entrada.offset);
+                entrada.hash = Some(hash);
+                pendientes -= 1;
+                progreso = true;
+            }
+        }
+        if !progreso {
+            anyhow::bail!("El packfile tiene {} deltas cuya base no se encontró", pendientes);
+        }
+    }
+
+    if por_hash.len() != cantidad {
+        anyhow::bail!("El packfile contiene objetos repetidos");
+    }
+
+    // Un paquete "thin" usa como base de REF_DELTA objetos que no incluye. Como hace
+    // `git index-pack --fix-thin`, esas bases se añaden al final para que quede autocontenido.
+    let externas: Vec<[u8; 20]> = bases_ref.into_iter().filter(|hash| !por_hash.contains_key(hash)).collect();
+    let checksum = if externas.is_empty() {
+        checksum
+    } else if !completar {
+        anyhow::bail!("El packfile depende de {} objetos que no contiene", externas.len());
+    } else {
+        completar_paquete(ruta_pack, fin_entradas, &externas, base, &mut entradas)?
+    };
+
+    let mut objetos: Vec<ObjetoIndexado> = entradas
+        .into_iter()
+        .map(|entrada| ObjetoIndexado {
+            hash: entrada.hash.expect("todas las entradas están resueltas"),
+            offset: entrada.offset,
+            crc32: entrada.crc32,
+        })
+        .collect();
+    objetos.sort_by_key(|objeto| objeto.hash);
+    Ok((objetos, checksum))
+}
+
+// Añade las bases externas como objetos completos, actualiza el número de objetos de la
+// cabecera y recalcula el checksum final. Devuelve el checksum nuevo.
+fn completar_paquete(
+    ruta_pack: &Path,
+    fin_entradas: u64,
+    externas: &[[u8; 20]],
+    base: &BaseObjetos,
+    entradas: &mut Vec<EntradaPaquete>,
+) -> Result<[u8; 20]> {
+    let mut archivo = OpenOptions::new().read(true).write(true).open(ruta_pack)?;
+    // El checksum anterior se descarta: las entradas nuevas van en su lugar
+    archivo.set_len(fin_entradas)?;
+    archivo.seek(SeekFrom::Start(fin_entradas))?;
+
+    let mut offset = fin_entradas;
+    for hash in externas {
+        let (tipo, datos) = base.leer_completo(&hex::encode(hash))?;
+        let entrada = codificar_entrada(tipo, &datos)?;
+        archivo.write_all(&entrada)?;
+
+        let mut crc = Crc::new();
+        crc.update(&entrada);
+        entradas.push(EntradaPaquete {
+            offset,
+            crc32: crc.sum(),
+            hash: Some(*hash),
+        });
+        offset += entrada.len() as u64;
+    }
+
+    archivo.seek(SeekFrom::Start(8))?;
+    archivo.write_all(&(entradas.len() as u32).to_be_bytes())?;
+
+    archivo.seek(SeekFrom::Start(0))?;
+    let mut hasher = Sha1::new();
+    io::copy(&mut BufReader::new(&archivo), &mut hasher)?;
+    let checksum: [u8; 20] = hasher.finalize().into();
+    archivo.seek(SeekFrom::End(0))?;
+    archivo.write_all(&checksum)?;
+    archivo.sync_all()?;
+    Ok(checksum)
+}
+
+/// Codifica un objeto completo (sin delta) como entrada de packfile: cabecera con tipo y tamaño
+/// seguida del contenido comprimido con zlib
+fn codificar_entrada(tipo: Tipo, datos: &[u8]) -> Result<Vec<u8>> {
+    let mut entrada = Vec::new();
+    escribir_cabecera_objeto(&mut entrada, numero_empacado(tipo), datos.len() as u64);
+    let mut comprimido = ZlibEncoder::new(entrada, Compression::default());
+    comprimido.write_all(datos)?;
+    Ok(comprimido.finish()?)
+}
+
+// Las bases REF_DELTA se buscan primero entre los objetos ya resueltos del propio paquete y
+// después en la base de objetos (un paquete "thin" puede apoyarse en objetos que ya tenemos)
+fn leer_indexando(
+    paquete: &Paquete,
+    offset: u64,
+    por_hash: &HashMap<[u8; 20], u64>,
+    base: &BaseObjetos,
+) -> Result<Option<(Tipo, Vec<u8>)>> {
+    paquete.leer_con(offset, &mut |hash_base| match por_hash.get(hash_base) {
+        Some(&offset_base) => leer_indexando(paquete, offset_base, por_hash, base),
+        None => {
+            let hash_base = hex::encode(hash_base);
+            if base.existe(&hash_base) {
+                base.leer_completo(&hash_base).map(Some)
+            } else {
+                Ok(None)
+            }
+        }
+    })
+}
+
+// Lector que lleva la cuenta de la posición, el SHA-1 de todo lo leído (el trailer del paquete)
+// y el CRC32 de la entrada actual. Se actualizan en `consume`, así que solo cuentan los bytes
+// que el decodificador zlib realmente usó.
+struct LectorContado<R> {
+    interno: BufReader<R>,
+    posicion: u64,
+    sha: Sha1,
+    crc: Crc,
+}
+
+impl<R: Read> LectorContado<R> {
+    fn new(interno: BufReader<R>) -> Self {
+        LectorContado {
+            interno,
+            posicion: 0,
+            sha: Sha1::new(),
+            crc: Crc::new(),
+        }
+    }
+}
+
+impl<R: Read> Read for LectorContado<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let disponibles = self.fill_buf()?;
+        let n = disponibles.len().min(buf.len());
+        buf[..n].copy_from_slice(&disponibles[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for LectorContado<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.interno.fill_buf()
+    }
+
+    fn consume(&mut self, cantidad: usize) {
+        let consumidos = &self.interno.buffer()[..cantidad];
+        self.sha.update(consumidos);
+        self.crc.update(consumidos);
+        self.posicion += cantidad as u64;
+        self.interno.consume(cantidad);
+    }
+}
+
+/// Genera un `.idx` versión 2 para los objetos indicados, que deben estar ordenados por hash
+pub(crate) fn generar_idx(objetos: &[ObjetoIndexado], checksum_pack: &[u8]) -> Vec<u8> {
+    let mut idx = Vec::new();
+    idx.extend_from_slice(&FIRMA_IDX);
+    idx.extend_from_slice(&2u32.to_be_bytes());
+
+    // Tabla fanout: cuántos objetos tienen un primer byte menor o igual a cada valor
+    let mut acumulado = 0u32;
+    for byte in 0..=255u8 {
+        acumulado += objetos.iter().filter(|o| o.hash[0] == byte).count() as u32;
+        idx.extend_from_slice(&acumulado.to_be_bytes());
+    }
+
+    for objeto in objetos {
+        idx.extend_from_slice(&objeto.hash);
+    }
+    for objeto in objetos {
+        idx.extend_from_slice(&objeto.crc32.to_be_bytes());
+    }
+
+    // Los offsets que no caben en 31 bits van a una tabla aparte de 64 bits
+    let mut offsets_largos = Vec::new();
+    for objeto in objetos {
+        if objeto.offset < 0x8000_0000 {
+            idx.extend_from_slice(&(objeto.offset as u32).to_be_bytes());
+        } else {
+            let posicion = (offsets_largos.len() / 8) as u32;
+            idx.extend_from_slice(&(posicion | 0x8000_0000).to_be_bytes());
+            offsets_largos.extend_from_slice(&objeto.offset.to_be_bytes());
+        }
+    }
+    idx.extend_from_slice(&offsets_largos);
+
+    idx.extend_from_slice(checksum_pack);
+    let checksum_idx = Sha1::digest(&idx);
+    idx.extend_from_slice(&checksum_idx);
+    idx
+}
+
+pub(crate) fn tipo_empacado(numero: u8) -> Result<Tipo> {
+    Ok(match numero {
+        1 => Tipo::Commit,
+        2 => Tipo::Arbol,
+        3 => Tipo::Blob,
+        4 => Tipo::Tag,
+        _ => anyhow::bail!("Tipo de objeto desconocido en el paquete: {}", numero),
+    })
+}
+
+pub(crate) fn numero_empacado(tipo: Tipo) -> u8 {
+    match tipo {
+        Tipo::Commit => 1,
+        Tipo::Arbol => 2,
+        Tipo::Blob => 3,
+        Tipo::Tag => 4,
+    }
+}
+
+/// Tipo en los bits 4-6 del primer byte y tamaño en little-endian de 4 + 7*n bits
+pub(crate) fn escribir_cabecera_objeto(destino: &mut Vec<u8>, numero_tipo: u8, tamaño: u64) {
+    let mut byte = (numero_tipo << 4) | (tamaño & 0xf) as u8;
+    let mut resto = tamaño >> 4;
+    while resto != 0 {
+        destino.push(byte | 0x80);
+        byte = (resto & 0x7f) as u8;
+        resto >>= 7;
+    }
+    destino.push(byte);
+}
+
+fn leer_cabecera_objeto(lector: &mut impl Read) -> Result<(u8, u64)> {
+    let mut byte = [0u8; 1];
+    lector.read_exact(&mut byte)?;
+    let numero_tipo = (byte[0] >> 4) & 0x7;
+    let mut tamaño = (byte[0] & 0xf) as u64;
+    let mut desplazamiento = 4;
+    while byte[0] & 0x80 != 0 {
+        if desplazamiento >= 64 {
+            anyhow::bail!("Cabecera de objeto empacado demasiado larga");
+        }
+        lector.read_exact(&mut byte)?;
+        tamaño |= ((byte[0] & 0x7f) as u64) << desplazamiento;
+        desplazamiento += 7;
+    }
+    Ok((numero_tipo, tamaño))
+}
+
+// El offset va en big-endian y cada byte de continuación suma 1 (formato de git)
+fn leer_offset_delta(lector: &mut impl Read) -> Result<u64> {
+    let mut byte = [0u8; 1];
+    lector.read_exact(&mut byte)?;
+    let mut offset = (byte[0] & 0x7f) as u64;
+    while byte[0] & 0x80 != 0 {
+        // Igual que git: si otros 7 bits no caben en 64, el offset es basura
+        if offset >> 57 != 0 {
+            anyhow::bail!("Offset de delta desbordado");
+        }
+        lector.read_exact(&mut byte)?;
+        offset = ((offset + 1) << 7) | (byte[0] & 0x7f) as u64;
+    }
+    Ok(offset)
+}
+
+// Lo máximo que se reserva de antemano según un tamaño leído del paquete: un paquete dañado
+// podría pedir gigas, así que a partir de ahí el vector crece según llegan los datos
+const RESERVA_MAXIMA: usize = 1 << 20;
+
+fn descomprimir(lector: &mut impl BufRead, tamaño: u64) -> Result<Vec<u8>> {
+    let mut datos = Vec::with_capacity(tamaño.min(RESERVA_MAXIMA as u64) as usize);
+    ZlibDecoder::new(lector).take(tamaño).read_to_end(&mut datos)?;
+    if datos.len() as u64 != tamaño {
+        anyhow::bail!("Objeto empacado truncado: se esperaban {} bytes, se leyeron {}", tamaño, datos.len());
+    }
+    Ok(datos)
+}
+
+fn leer_u32(datos: &[u8], posicion: usize) -> u32 {
+    u32::from_be_bytes(datos[posicion..posicion + 4].try_into().expect("son 4 bytes"))
+}
+
+/// Reconstruye un objeto a partir de su base y las instrucciones de copia/inserción del delta
+pub(crate) fn aplicar_delta(delta: &[u8], base: &[u8]) -> Result<Vec<u8>> {
+    let mut resultado = Vec::new();
+    let mut i = 0;
+
+    // Leer el tamaño del objeto base (formato variable)
+    let tamaño_base = leer_tamaño_delta(delta, &mut i)?;
+
+    // Verificar que el tamaño base coincide
+    if tamaño_base != base.len() {
+        anyhow::bail!("Tamaño base incorrecto en delta");
+    }
+
+    // Leer el tamaño del objeto resultante
+    let tamaño_resultado = leer_tamaño_delta(delta, &mut i)?;
+
+    // Reservar espacio para el resultado, sin fiarse de un tamaño enorme
+    resultado.reserve(tamaño_resultado.min(RESERVA_MAXIMA));
+
+    // Aplicar las instrucciones del delta
+    while i < delta.len() {
+        let instruccion = delta[i];
+        i += 1;
+
+        if (instruccion & 0x80) != 0 {
+            // Instrucción de copia desde el objeto base: los bits 0-3 indican qué bytes del
+            // offset vienen a continuación y los bits 4-6 los del tamaño
+            let mut offset = 0;
+            let mut tamaño = 0;
+            for bit in 0..4 {
+                if (instruccion & (1 << bit)) != 0 {
+                    offset |= (*delta.get(i).context("Delta truncado")? as usize) << (8 * bit);
+                    i += 1;
+                }
+            }
+            for bit in 0..3 {
+                if (instruccion & (0x10 << bit)) != 0 {
+                    tamaño |= (*delta.get(i).context("Delta truncado")? as usize) << (8 * bit);
+                    i += 1;
+                }
+            }
+
+            // Si el tamaño es 0, usar 0x10000
+            if tamaño == 0 {
+                tamaño = 0x10000;
+            }
+
+            // Copiar datos desde el objeto base
+            if resultado.len() + tamaño > tamaño_resultado {
+                anyhow::bail!("El delta produce más datos de los que anuncia");
+            }
+            if offset + tamaño > base.len() {
+                anyhow::bail!("Delta fuera de límites: offset={}, tamaño={}, base.len()={}", offset, tamaño, base.len());
+            }
+            resultado.extend_from_slice(&base[offset..offset + tamaño]);
+        } else if instruccion != 0 {
+            // Instrucción de insertar datos literales
+            let tamaño = instruccion as usize;
+            if i + tamaño > delta.len() {
+                anyhow::bail!("Delta fuera de límites en datos literales");
+            }
+            if resultado.len() + tamaño > tamaño_resultado {
+                anyhow::bail!("El delta produce más datos de los que anuncia");
+            }
+            resultado.extend_from_slice(&delta[i..i + tamaño]);
+            i += tamaño;
+        } else {
+            anyhow::bail!("Instrucción delta inválida");
+        }
+    }
+
+    if resultado.len() != tamaño_resultado {
+        anyhow::bail!("Tamaño resultante incorrecto: esperado={}, actual={}", tamaño_resultado, resultado.len());
+    }
+
+    Ok(resultado)
+}
+
+// Tamaño en formato variable de la cabecera de un delta: 7 bits por byte, los menos
+// significativos primero
+fn leer_tamaño_delta(delta: &[u8], i: &mut usize) -> Result<usize> {
+    let mut tamaño = 0;
+    let mut desplazamiento = 0;
+    loop {
+        let byte = *delta.get(*i).context("Delta truncado")?;
+        *i += 1;
+        if desplazamiento >= usize::BITS {
+            anyhow::bail!("Tamaño de delta demasiado largo");
+        }
+        tamaño |= ((byte & 0x7F) as usize) << desplazamiento;
+        desplazamiento += 7;
+        if (byte & 0x80) == 0 {
+            return Ok(tamaño);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Un directorio temporal propio de cada prueba, que se borra al terminar
+    struct Temporal(PathBuf);
+
+    impl Temporal {
+        fn nuevo(nombre: &str) -> Temporal {
+            let ruta = std::env::temp_dir().join(format!("paquete-{}-{}", nombre, std::process::id()));
+            let _ = fs::remove_dir_all(&ruta);
+            fs::create_dir_all(ruta.join("objects/pack")).unwrap();
+            Temporal(ruta)
+        }
+    }
+
+    impl Drop for Temporal {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    // Cabecera, entradas ya codificadas y el SHA-1 final
+    fn armar_paquete(entradas: &[Vec<u8>]) -> Vec<u8> {
+        let mut paquete = b"PACK".to_vec();
+        paquete.extend_from_slice(&2u32.to_be_bytes());
+        paquete.extend_from_slice(&(entradas.len() as u32).to_be_bytes());
+        for entrada in entradas {
+            paquete.extend_from_slice(entrada);
+        }
+        let checksum: [u8; 20] = Sha1::digest(&paquete).into();
+        paquete.extend_from_slice(&checksum);
+        paquete
+    }
+
+    fn indexar_bytes(temporal: &Temporal, datos: &[u8]) -> Result<(Vec<ObjetoIndexado>, [u8; 20])> {
+        let ruta = temporal.0.join("objects/pack/prueba.pack");
+        fs::write(&ruta, datos).unwrap();
+        indexar(&ruta, &BaseObjetos::abrir(&temporal.0))
+    }
+
+    fn error_al_indexar(temporal: &Temporal, datos: &[u8]) -> String {
+        match indexar_bytes(temporal, datos) {
+            Ok(_) => panic!("el paquete dañado se ha indexado"),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn indexa_un_paquete_valido() {
+        let temporal = Temporal::nuevo("valido");
+        let entrada = codificar_entrada(Tipo::Blob, b"hola\n").unwrap();
+        let (objetos, _) = indexar_bytes(&temporal, &armar_paquete(&[entrada])).unwrap();
+        assert_eq!(objetos.len(), 1);
+        assert_eq!(hex::encode(objetos[0].hash), crate::base_objetos::calcular_hash(Tipo::Blob, b"hola\n"));
+    }
+
+    #[test]
+    fn cabecera_de_objeto_demasiado_larga() {
+        let temporal = Temporal::nuevo("cabecera");
+        let mut entrada = vec![0xb0];
+        entrada.extend([0xff; 12]);
+        entrada.push(0x01);
+        let error = error_al_indexar(&temporal, &armar_paquete(&[entrada]));
+        assert!(error.contains("demasiado larga"), "{}", error);
+    }
+
+    #[test]
+    fn offset_de_delta_desbordado() {
+        let temporal = Temporal::nuevo("offset");
+        let mut entrada = vec![0x60];
+        entrada.extend([0xff; 12]);
+        entrada.push(0x01);
+        let error = error_al_indexar(&temporal, &armar_paquete(&[entrada]));
+        assert!(error.contains("desbordado"), "{}", error);
+    }
+
+    #[test]
+    fn tamaño_anunciado_enorme() {
+        // Anuncia 2^60 bytes pero solo trae unos pocos
+        let temporal = Temporal::nuevo("enorme");
+        let mut entrada = Vec::new();
+        escribir_cabecera_objeto(&mut entrada, numero_empacado(Tipo::Blob), 1 << 60);
+        let mut comprimido = ZlibEncoder::new(entrada, Compression::default());
+        comprimido.write_all(b"poco").unwrap();
+        let entrada = comprimido.finish().unwrap();
+        error_al_indexar(&temporal, &armar_paquete(&[entrada]));
+    }
+
+    #[test]
+    fn verificar_un_paquete_dañado_devuelve_error() {
+        let temporal = Temporal::nuevo("verificar");
+        let entrada = codificar_entrada(Tipo::Blob, b"hola\n").unwrap();
+        let (objetos, checksum) = indexar_bytes(&temporal, &armar_paquete(&[entrada])).unwrap();
+        let ruta_idx = temporal.0.join("objects/pack/prueba.idx");
+        fs::write(&ruta_idx, generar_idx(&objetos, &checksum)).unwrap();
+        let base = BaseObjetos::abrir(&temporal.0);
+        assert_eq!(Paquete::abrir(&ruta_idx).unwrap().verificar(&base).unwrap(), 1);
+
+        // La misma cantidad de objetos, pero la entrada tiene una cabecera interminable
+        let mut entrada = vec![0xb0];
+        entrada.extend([0xff; 12]);
+        entrada.push(0x01);
+        fs::write(temporal.0.join("objects/pack/prueba.pack"), armar_paquete(&[entrada])).unwrap();
+        assert!(Paquete::abrir(&ruta_idx).unwrap().verificar(&base).is_err());
+    }
+
+    #[test]
+    fn delta_con_tamaño_interminable() {
+        let mut delta = vec![0xff; 11];
+        delta.push(0x01);
+        assert!(aplicar_delta(&delta, b"").is_err());
+    }
+
+    #[test]
+    fn delta_que_produce_mas_de_lo_que_anuncia() {
+        // Base de 4 bytes, resultado de 2^40 bytes y una inserción de 3
+        let mut delta = vec![0x04, 0x80, 0x80, 0x80, 0x80, 0x80, 0x20];
+        delta.extend([0x03, b'a', b'b', b'c']);
+        assert!(aplicar_delta(&delta, b"base").is_err());
+        // Anuncia 2 bytes e inserta 3
+        assert!(aplicar_delta(&[0x04, 0x02, 0x03, b'a', b'b', b'c'], b"base").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use std::io::{self, Read, Write};
+
+// Longitud máxima de una pkt-line, incluidos los 4 bytes de la longitud
+const LONGITUD_MAXIMA: usize = 65520;
+
+/// Una unidad del formato pkt-line de los protocolos de git: 4 dígitos hexadecimales con la
+/// longitud total seguidos de los datos. Las longitudes 0000, 0001 y 0002 son paquetes especiales.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum LineaPkt {
+    Datos(Vec<u8>),
+    /// 0000: fin de una lista o de un mensaje
+    Flush,
+    /// 0001: separa secciones dentro de un comando (protocolo v2)
+    Delimitador,
+    /// 0002: fin de la respuesta (protocolo v2, sin estado)
+    FinRespuesta,
+}
+
+/// Lee pkt-lines de cualquier `Read`, por ejemplo el cuerpo de una respuesta HTTP
+pub(crate) struct LectorPkt<R> {
+    interno: R,
+}
+
+impl<R: Read> LectorPkt<R> {
+    pub(crate) fn new(interno: R) -> Self {
+        LectorPkt { interno }
+    }
+
+    /// La siguiente pkt-line, o None si los datos terminan justo entre dos líneas.
+    /// Una línea `ERR <mensaje>` del servidor se convierte en un error.
+    pub(crate) fn leer(&mut self) -> Result<Option<LineaPkt>> {
+        let mut longitud = [0u8; 4];
+        let mut leidos = 0;
+        while leidos < longitud.len() {
+            match self.interno.read(&mut longitud[leidos..])? {
+                0 if leidos == 0 => return Ok(None),
+                0 => anyhow::bail!("pkt-line truncada: los datos terminan en mitad de la longitud"),
+                n => leidos += n,
+            }
+        }
+
+        let longitud = std::str::from_utf8(&longitud)
+            .ok()
+            .and_then(|hex| usize::from_str_radix(hex, 16).ok())
+            .with_context(|| format!("Longitud de pkt-line inválida: {:?}", String::from_utf8_lossy(&longitud)))?;
+        let linea = match longitud {
+            0 => LineaPkt::Flush,
+            1 => LineaPkt::Delimitador,
+            2 => LineaPkt::FinRespuesta,
+            3 => anyhow::bail!("Longitud de pkt-line inválida: 3"),
+            _ if longitud > LONGITUD_MAXIMA => anyhow::bail!("pkt-line demasiado larga: {} bytes", longitud),
+            _ => {
+                let mut datos = vec![0u8; longitud - 4];
+                self.interno.read_exact(&mut datos).context("pkt-line truncada")?;
+                if let Some(mensaje) = datos.strip_prefix(b"ERR ") {
+                    anyhow::bail!("El servidor respondió con un error: {}", String::from_utf8_lossy(mensaje).trim_end());
+                }
+                LineaPkt::Datos(datos)
+            }
+        };
+        Ok(Some(linea))
+    }
+
+    /// Como `leer`, pero devuelve el texto de una línea de datos sin el `\n` final
+    /// (None para los paquetes especiales y el final de los datos)
+    pub(crate) fn leer_texto(&mut self) -> Result<Option<String>> {
+        match self.leer()? {
+            Some(LineaPkt::Datos(datos)) => {
+                let texto = String::from_utf8(datos).context("La pkt-line no es texto UTF-8 válido")?;
+                Ok(Some(texto.strip_suffix('\n').map(str::to_string).unwrap_or(texto)))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Lo que queda de los datos, para cuando el servidor deja de usar pkt-lines (por ejemplo,
+    /// un packfile enviado sin side-band)
+    pub(crate) fn into_inner(self) -> R {
+        self.interno
+    }
+
+    /// Un lector del canal de datos de side-band; el progreso se escribe en stderr
+    pub(crate) fn banda_lateral(self) -> LectorBandaLateral<R> {
+        LectorBandaLateral {
+            pkt: self,
+            pendiente: Vec::new(),
+            posicion: 0,
+            terminado: false,
+            inicio_linea: true,
+        }
+    }
+}
+
+/// Separa los canales de side-band(-64k): el 1 lleva los datos (el packfile), el 2 mensajes de
+/// progreso y el 3 un error fatal. Se lee como un `Read` normal con solo los datos del canal 1,
+/// que terminan en el flush-pkt.
+pub(crate) struct LectorBandaLateral<R> {
+    pkt: LectorPkt<R>,
+    // Datos del canal 1 recibidos y todavía no entregados
+    pendiente: Vec<u8>,
+    posicion: usize,
+    terminado: bool,
+    // Si el próximo mensaje de progreso empieza una línea nueva en la terminal
+    inicio_linea: bool,
+}
+
+impl<R: Read> LectorBandaLateral<R> {
+    fn siguiente(&mut self) -> Result<()> {
+        let datos = match self.pkt.leer()? {
+            None | Some(LineaPkt::Flush) => {
+                self.terminado = true;
+                return Ok(());
+            }
+            Some(LineaPkt::Datos(datos)) if !datos.is_empty() => datos,
+            Some(otra) => anyhow::bail!("pkt-line inesperada en side-band: {:?}", otra),
+        };
+
+        match datos[0] {
+            1 => {
+                self.pendiente = datos;
+                self.posicion = 1;
+            }
+            2 => {
+                // Como git, cada línea de progreso del servidor lleva el prefijo "remote:". Los
+                // contadores se reescriben con '\r', así que también cuenta como fin de línea.
+                let mut error = io::stderr().lock();
+                for trozo in datos[1..].split_inclusive(|&byte| byte == b'\n' || byte == b'\r') {
+                    if self.inicio_linea {
+                        error.write_all(b"remote: ")?;
+                    }
+                    error.write_all(trozo)?;
+                    self.inicio_linea = trozo.ends_with(b"\n") || trozo.ends_with(b"\r");
+                }
+                error.flush()?;
+            }
+            3 => anyhow::bail!("Error del servidor: {}", String::from_utf8_lossy(&datos[1..]).trim_end()),
+            canal => anyhow::bail!("Canal de side-band desconocido: {}", canal),
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for LectorBandaLateral<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.posicion == self.pendiente.len() {
+            if self.terminado {
+                return Ok(0);
+            }
+            self.siguiente().map_err(io::Error::other)?;
+        }
+        let n = (self.pendiente.len() - self.posicion).min(buf.len());
+        buf[..n].copy_from_slice(&self.pendiente[self.posicion..self.posicion + n]);
+        self.posicion += n;
+        Ok(n)
+    }
+}
+
+/// Construye un mensaje en formato pkt-line, por ejemplo el cuerpo de una petición
+#[derive(Default)]
+pub(crate) struct EscritorPkt {
+    datos: Vec<u8>,
+}
+
+impl EscritorPkt {
+    pub(crate) fn linea(&mut self, datos: impl AsRef<[u8]>) -> Result<&mut Self> {
+        let datos = datos.as_ref();
+        if datos.len() + 4 > LONGITUD_MAXIMA {
+            anyhow::bail!("pkt-line demasiado larga: {} bytes (el máximo es {})", datos.len(), LONGITUD_MAXIMA - 4);
+        }
+        self.datos.extend_from_slice(format!("{:04x}", datos.len() + 4).as_bytes());
+        self.datos.extend_from_slice(datos);
+        Ok(self)
+    }
+
+    pub(crate) fn flush(&mut self) -> &mut Self {
+        self.datos.extend_from_slice(b"0000");
+        self
+    }
+
+    pub(crate) fn delimitador(&mut self) -> &mut Self {
+        self.datos.extend_from_slice(b"0001");
+        self
+    }
+
+    pub(crate) fn terminar(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.datos)
+    }
+}
@@ -0,0 +1,466 @@
+use crate::base_objetos::BaseObjetos;
+use crate::pktline::{EscritorPkt, LectorPkt, LineaPkt};
+use anyhow::{Context, Result};
+use reqwest::blocking::{Client, Response};
+use std::fmt;
+use std::io::Read;
+
+const AGENTE: &str = "git/2.0.0";
+
+/// Versión del protocolo de transferencia que se habla con el servidor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Version {
+    /// El protocolo original: el servidor anuncia todas sus referencias al conectar
+    V0,
+    /// Protocolo v2: el servidor anuncia comandos (`ls-refs`, `fetch`) y se piden por separado
+    V2,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::V0 => write!(f, "v0"),
+            Version::V2 => write!(f, "v2"),
+        }
+    }
+}
+
+/// Una referencia tal como la anuncia el servidor
+#[derive(Debug, Clone)]
+pub(crate) struct ReferenciaRemota {
+    pub(crate) nombre: String,
+    pub(crate) hash: String,
+    /// La referencia a la que apunta, si es simbólica (normalmente solo HEAD)
+    pub(crate) destino_simbolico: Option<String>,
+    /// En las etiquetas anotadas, el objeto al que apunta la etiqueta
+    pub(crate) pelado: Option<String>,
+}
+
+/// Cliente del servicio `git-upload-pack` por smart HTTP, para clonar y traer. Al conectar se pide el protocolo v2
+/// y, si el servidor no lo entiende, se sigue con v0.
+pub(crate) struct Servidor {
+    url: String,
+    cliente: Client,
+    version: Version,
+    capacidades: Vec<String>,
+    // En v0 las referencias llegan al conectar; en v2 se piden con ls-refs
+    referencias_v0: Vec<ReferenciaRemota>,
+}
+
+impl Servidor {
+    pub(crate) fn conectar(url: &str) -> Result<Servidor> {
+        let url = url.trim_end_matches('/').to_string();
+        let cliente = Client::new();
+        let lineas = pedir_anuncio(&cliente, &url, "git-upload-pack", true)?;
+
+        let mut servidor = Servidor {
+            url,
+            cliente,
+            version: Version::V0,
+            capacidades: Vec::new(),
+            referencias_v0: Vec::new(),
+        };
+        if lineas.first().is_some_and(|linea| linea == "version 2") {
+            servidor.version = Version::V2;
+            servidor.capacidades = lineas[1..].to_vec();
+            for comando in ["ls-refs", "fetch"] {
+                if !servidor.tiene_capacidad(comando) {
+                    anyhow::bail!("El servidor habla el protocolo v2 pero no ofrece el comando '{}'", comando);
+                }
+            }
+        } else {
+            (servidor.capacidades, servidor.referencias_v0) = parsear_anuncio_v0(&lineas);
+        }
+        if servidor.valor_capacidad("object-format").is_some_and(|formato| formato != "sha1") {
+            anyhow::bail!("El servidor usa un formato de objetos no soportado (solo se admite sha1)");
+        }
+        Ok(servidor)
+    }
+
+    pub(crate) fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Las referencias del servidor cuyo nombre empieza por alguno de los prefijos
+    pub(crate) fn listar_referencias(&self, prefijos: &[&str]) -> Result<Vec<ReferenciaRemota>> {
+        let coincide = |nombre: &str| prefijos.iter().any(|prefijo| nombre.starts_with(prefijo));
+        if self.version == Version::V0 {
+            return Ok(self.referencias_v0.iter().filter(|r| coincide(&r.nombre)).cloned().collect());
+        }
+
+        // ls-refs: los prefijos filtran en el servidor; symrefs y peel añaden los atributos
+        // "symref-target:" y "peeled:" a cada línea
+        let mut cuerpo = self.comando_v2("ls-refs")?;
+        cuerpo.linea("symrefs\n")?.linea("peel\n")?;
+        for prefijo in prefijos {
+            cuerpo.linea(format!("ref-prefix {}\n", prefijo))?;
+        }
+        cuerpo.flush();
+
+        let mut lector = LectorPkt::new(self.post(cuerpo.terminar())?);
+        let mut referencias = Vec::new();
+        while let Some(linea) = lector.leer_texto()? {
+            let mut partes = linea.split(' ');
+            let (Some(hash), Some(nombre)) = (partes.next(), partes.next()) else {
+                anyhow::bail!("Respuesta de ls-refs inválida: '{}'", linea);
+            };
+            let mut referencia = ReferenciaRemota {
+                nombre: nombre.to_string(),
+                hash: hash.to_string(),
+                destino_simbolico: None,
+                pelado: None,
+            };
+            for atributo in partes {
+                if let Some(destino) = atributo.strip_prefix("symref-target:") {
+                    referencia.destino_simbolico = Some(destino.to_string());
+                } else if let Some(pelado) = atributo.strip_prefix("peeled:") {
+                    referencia.pelado = Some(pelado.to_string());
+                }
+            }
+            // "unborn" es HEAD en un repositorio vacío: todavía no apunta a ningún commit
+            if hash != "unborn" && coincide(&referencia.nombre) {
+                referencias.push(referencia);
+            }
+        }
+        Ok(referencias)
+    }
+
+    /// Pide un packfile con los objetos `deseados` y todo lo que cuelga de ellos, salvo lo que
+    /// ya es alcanzable desde `comunes`, y lo guarda en la base de objetos. Devuelve cuántos
+    /// objetos llegaron.
+    pub(crate) fn descargar_paquete(
+        &self,
+        deseados: &[String],
+        comunes: &[String],
+        base: &mut BaseObjetos,
+    ) -> Result<usize> {
+        match self.version {
+            Version::V0 => self.descargar_paquete_v0(deseados, comunes, base),
+            Version::V2 => self.descargar_paquete_v2(deseados, comunes, base),
+        }
+    }
+
+    fn descargar_paquete_v0(&self, deseados: &[String], comunes: &[String], base: &mut BaseObjetos) -> Result<usize> {
+        // La primera línea want lleva las capacidades que pedimos de entre las anunciadas
+        let mut pedidas = Vec::new();
+        if self.tiene_capacidad("side-band-64k") {
+            pedidas.push("side-band-64k");
+        } else if self.tiene_capacidad("side-band") {
+            pedidas.push("side-band");
+        }
+        // include-tag: el servidor añade las etiquetas anotadas que apuntan a objetos del paquete.
+        // multi_ack_detailed: los ACK de los commits comunes llevan un estado y el último no, así
+        // se sabe dónde termina la negociación.
+        for capacidad in ["ofs-delta", "thin-pack", "include-tag", "multi_ack_detailed"] {
+            if self.tiene_capacidad(capacidad) {
+                pedidas.push(capacidad);
+            }
+        }
+        let banda_lateral = pedidas.first().is_some_and(|capacidad| capacidad.starts_with("side-band"));
+        let agente = format!("agent={}", AGENTE);
+        if self.tiene_capacidad("agent") {
+            pedidas.push(&agente);
+        }
+
+        let mut cuerpo = EscritorPkt::default();
+        for (i, hash) in deseados.iter().enumerate() {
+            if i == 0 && !pedidas.is_empty() {
+                cuerpo.linea(format!("want {} {}\n", hash, pedidas.join(" ")))?;
+            } else {
+                cuerpo.linea(format!("want {}\n", hash))?;
+            }
+        }
+        cuerpo.flush();
+        // Sin multi_ack_detailed no se sabría cuántos ACK esperar: se pide el paquete completo
+        if pedidas.contains(&"multi_ack_detailed") {
+            for hash in comunes {
+                cuerpo.linea(format!("have {}\n", hash))?;
+            }
+        }
+        cuerpo.linea("done\n")?;
+
+        // Antes del paquete el servidor responde a la negociación: "ACK <hash> common" (o ready)
+        // por cada commit en común y al final "ACK <hash>" o, si no hubo ninguno, "NAK"
+        let mut respuesta = LectorPkt::new(self.post(cuerpo.terminar())?);
+        loop {
+            match respuesta.leer_texto()? {
+                Some(linea) if linea == "NAK" => break,
+                Some(linea) if linea.starts_with("ACK ") && linea.split(' ').count() == 2 => break,
+                Some(_) => continue,
+                None => anyhow::bail!("La respuesta del servidor terminó antes del packfile"),
+            }
+        }
+
+        // Con side-band el paquete llega repartido en pkt-lines del canal 1; sin él, en crudo
+        if banda_lateral {
+            base.guardar_paquete(&mut respuesta.banda_lateral())
+        } else {
+            base.guardar_paquete(&mut respuesta.into_inner())
+        }
+    }
+
+    fn descargar_paquete_v2(&self, deseados: &[String], comunes: &[String], base: &mut BaseObjetos) -> Result<usize> {
+        let mut cuerpo = self.comando_v2("fetch")?;
+        cuerpo.linea("thin-pack\n")?.linea("ofs-delta\n")?.linea("include-tag\n")?;
+        for hash in deseados {
+            cuerpo.linea(format!("want {}\n", hash))?;
+        }
+        for hash in comunes {
+            cuerpo.linea(format!("have {}\n", hash))?;
+        }
+        // Con "done" el servidor no devuelve acknowledgments y manda directamente el paquete
+        cuerpo.linea("done\n")?.flush();
+
+        // La respuesta se divide en secciones separadas por delim-pkt; solo interesa "packfile",
+        // que siempre va multiplexada con side-band
+        let mut respuesta = LectorPkt::new(self.post(cuerpo.terminar())?);
+        loop {
+            match respuesta.leer()? {
+                Some(LineaPkt::Datos(datos)) if datos.strip_suffix(b"\n").unwrap_or(&datos) == b"packfile" => break,
+                Some(_) => continue,
+                None => anyhow::bail!("La respuesta del servidor no incluye un packfile"),
+            }
+        }
+        base.guardar_paquete(&mut respuesta.banda_lateral())
+    }
+
+    // Cabecera de una petición v2: el comando y las capacidades que lo acompañan, hasta el delim-pkt
+    fn comando_v2(&self, comando: &str) -> Result<EscritorPkt> {
+        let mut cuerpo = EscritorPkt::default();
+        cuerpo.linea(format!("command={}\n", comando))?;
+        if self.tiene_capacidad("agent") {
+            cuerpo.linea(format!("agent={}\n", AGENTE))?;
+        }
+        if self.tiene_capacidad("object-format") {
+            cuerpo.linea("object-format=sha1\n")?;
+        }
+        cuerpo.delimitador();
+        Ok(cuerpo)
+    }
+
+    fn post(&self, cuerpo: Vec<u8>) -> Result<Response> {
+        let mut peticion = self
+            .cliente
+            .post(format!("{}/git-upload-pack", self.url))
+            .header("Content-Type", "application/x-git-upload-pack-request")
+            .header("Accept", "application/x-git-upload-pack-result")
+            .header("User-Agent", AGENTE);
+        if self.version == Version::V2 {
+            peticion = peticion.header("Git-Protocol", "version=2");
+        }
+        // La respuesta no se carga entera en memoria: quien la lee decide qué hacer con ella
+        Ok(peticion.body(cuerpo).send()?.error_for_status()?)
+    }
+
+    fn tiene_capacidad(&self, nombre: &str) -> bool {
+        tiene_capacidad(&self.capacidades, nombre)
+    }
+
+    fn valor_capacidad(&self, nombre: &str) -> Option<&str> {
+        valor_capacidad(&self.capacidades, nombre)
+    }
+}
+
+/// Una orden para el servidor al empujar: mover `nombre` de `anterior` a `nuevo`. Un hash de
+/// ceros en `anterior` crea la referencia y en `nuevo` la borra.
+#[derive(Debug, Clone)]
+pub(crate) struct ActualizacionRemota {
+    pub(crate) nombre: String,
+    pub(crate) anterior: String,
+    pub(crate) nuevo: String,
+}
+
+/// Cliente del servicio `git-receive-pack` por smart HTTP. No existe una versión v2 de este
+/// servicio: las referencias y capacidades siempre llegan al conectar, como en v0.
+pub(crate) struct Receptor {
+    url: String,
+    cliente: Client,
+    capacidades: Vec<String>,
+    referencias: Vec<ReferenciaRemota>,
+}
+
+impl Receptor {
+    pub(crate) fn conectar(url: &str) -> Result<Receptor> {
+        let url = url.trim_end_matches('/').to_string();
+        let cliente = Client::new();
+        let lineas = pedir_anuncio(&cliente, &url, "git-receive-pack", false)?;
+        let (capacidades, referencias) = parsear_anuncio_v0(&lineas);
+        if valor_capacidad(&capacidades, "object-format").is_some_and(|formato| formato != "sha1") {
+            anyhow::bail!("El servidor usa un formato de objetos no soportado (solo se admite sha1)");
+        }
+        if !tiene_capacidad(&capacidades, "report-status") {
+            anyhow::bail!("El servidor no ofrece report-status: no se podría saber qué referencias aceptó");
+        }
+        Ok(Receptor {
+            url,
+            cliente,
+            capacidades,
+            referencias,
+        })
+    }
+
+    /// Todas las referencias del servidor
+    pub(crate) fn referencias(&self) -> &[ReferenciaRemota] {
+        &self.referencias
+    }
+
+    /// Si el servidor anunció una capacidad, por ejemplo `delete-refs` u `ofs-delta`
+    pub(crate) fn tiene_capacidad(&self, nombre: &str) -> bool {
+        tiene_capacidad(&self.capacidades, nombre)
+    }
+
+    /// Manda las órdenes y el packfile (obligatorio si alguna orden crea o mueve una referencia)
+    /// y devuelve, para cada referencia, None si el servidor la actualizó o el motivo del rechazo
+    pub(crate) fn enviar(
+        &self,
+        actualizaciones: &[ActualizacionRemota],
+        paquete: Option<Vec<u8>>,
+    ) -> Result<Vec<(String, Option<String>)>> {
+        // Las capacidades van tras un NUL en la primera orden
+        let mut pedidas = vec!["report-status"];
+        if tiene_capacidad(&self.capacidades, "side-band-64k") {
+            pedidas.push("side-band-64k");
+        }
+        let agente = format!("agent={}", AGENTE);
+        if tiene_capacidad(&self.capacidades, "agent") {
+            pedidas.push(&agente);
+        }
+        let mut cuerpo = EscritorPkt::default();
+        for (i, actualizacion) in actualizaciones.iter().enumerate() {
+            let orden = format!("{} {} {}", actualizacion.anterior, actualizacion.nuevo, actualizacion.nombre);
+            if i == 0 {
+                cuerpo.linea(format!("{}\0{}\n", orden, pedidas.join(" ")))?;
+            } else {
+                cuerpo.linea(format!("{}\n", orden))?;
+            }
+        }
+        cuerpo.flush();
+        let mut cuerpo = cuerpo.terminar();
+        if let Some(paquete) = paquete {
+            cuerpo.extend_from_slice(&paquete);
+        }
+
+        let respuesta = self
+            .cliente
+            .post(format!("{}/git-receive-pack", self.url))
+            .header("Content-Type", "application/x-git-receive-pack-request")
+            .header("Accept", "application/x-git-receive-pack-result")
+            .header("User-Agent", AGENTE)
+            .body(cuerpo)
+            .send()?
+            .error_for_status()?;
+
+        // Con side-band el informe son pkt-lines dentro del canal 1
+        let lector = LectorPkt::new(respuesta);
+        if pedidas.contains(&"side-band-64k") {
+            parsear_informe(LectorPkt::new(lector.banda_lateral()))
+        } else {
+            parsear_informe(lector)
+        }
+    }
+}
+
+// "unpack ok" (o el error al recibir el paquete) y después "ok <ref>" o "ng <ref> <motivo>"
+fn parsear_informe(mut lector: LectorPkt<impl Read>) -> Result<Vec<(String, Option<String>)>> {
+    let desempaquetado = lector.leer_texto()?.context("El servidor no devolvió el informe de estado")?;
+    match desempaquetado.strip_prefix("unpack ") {
+        Some("ok") => {}
+        Some(error) => anyhow::bail!("El servidor no pudo procesar el paquete: {}", error),
+        None => anyhow::bail!("Informe de estado inválido: '{}'", desempaquetado),
+    }
+
+    let mut resultados = Vec::new();
+    while let Some(linea) = lector.leer_texto()? {
+        if let Some(nombre) = linea.strip_prefix("ok ") {
+            resultados.push((nombre.to_string(), None));
+        } else if let Some(resto) = linea.strip_prefix("ng ") {
+            let (nombre, motivo) = resto.split_once(' ').unwrap_or((resto, "rechazada"));
+            resultados.push((nombre.to_string(), Some(motivo.to_string())));
+        } else {
+            anyhow::bail!("Informe de estado inválido: '{}'", linea);
+        }
+    }
+    Ok(resultados)
+}
+
+// Pide info/refs de un servicio y devuelve sus líneas de texto. Un servidor v0 anuncia sus
+// referencias; uno v2 empieza con "version 2" y sus capacidades. En ambos casos puede ir
+// delante la línea "# service=<servicio>" con su flush-pkt.
+fn pedir_anuncio(cliente: &Client, url: &str, servicio: &str, v2: bool) -> Result<Vec<String>> {
+    let mut peticion = cliente
+        .get(format!("{}/info/refs?service={}", url, servicio))
+        .header("User-Agent", AGENTE);
+    if v2 {
+        peticion = peticion.header("Git-Protocol", "version=2");
+    }
+    let respuesta = peticion
+        .send()
+        .with_context(|| format!("No se pudo conectar con {}", url))?
+        .error_for_status()?
+        .bytes()?;
+
+    let mut lineas = Vec::new();
+    let mut lector = LectorPkt::new(respuesta.as_ref());
+    while let Some(linea) = lector.leer()? {
+        if let LineaPkt::Datos(datos) = linea {
+            let texto = String::from_utf8_lossy(&datos);
+            let texto = texto.trim_end_matches('\n');
+            if !texto.starts_with("# service=") {
+                lineas.push(texto.to_string());
+            }
+        }
+    }
+    Ok(lineas)
+}
+
+// La primera referencia lleva las capacidades del servidor tras un NUL; las líneas
+// <etiqueta>^{} indican a qué apunta la etiqueta anotada anterior. Un repositorio vacío anuncia
+// solo las capacidades, con el nombre "capabilities^{}".
+fn parsear_anuncio_v0(lineas: &[String]) -> (Vec<String>, Vec<ReferenciaRemota>) {
+    let mut capacidades = Vec::new();
+    let mut referencias: Vec<ReferenciaRemota> = Vec::new();
+    for linea in lineas {
+        let (referencia, lista) = linea.split_once('\0').unwrap_or((linea, ""));
+        if !lista.is_empty() {
+            capacidades = lista.split(' ').map(str::to_string).collect();
+        }
+        let Some((hash, nombre)) = referencia.split_once(' ') else {
+            continue;
+        };
+        if let Some(etiqueta) = nombre.strip_suffix("^{}") {
+            if let Some(anterior) = referencias.last_mut().filter(|r| r.nombre == etiqueta) {
+                anterior.pelado = Some(hash.to_string());
+            }
+            continue;
+        }
+        referencias.push(ReferenciaRemota {
+            nombre: nombre.to_string(),
+            hash: hash.to_string(),
+            destino_simbolico: None,
+            pelado: None,
+        });
+    }
+
+    // En v0 las referencias simbólicas solo se conocen por la capacidad symref=<ref>:<destino>
+    for capacidad in &capacidades {
+        if let Some((nombre, destino)) = capacidad.strip_prefix("symref=").and_then(|s| s.split_once(':')) {
+            if let Some(referencia) = referencias.iter_mut().find(|r| r.nombre == nombre) {
+                referencia.destino_simbolico = Some(destino.to_string());
+            }
+        }
+    }
+    (capacidades, referencias)
+}
+
+// Las capacidades son "nombre" o "nombre=valor"
+fn tiene_capacidad(capacidades: &[String], nombre: &str) -> bool {
+    capacidades
+        .iter()
+        .any(|capacidad| capacidad == nombre || capacidad.split_once('=').is_some_and(|(n, _)| n == nombre))
+}
+
+fn valor_capacidad<'a>(capacidades: &'a [String], nombre: &str) -> Option<&'a str> {
+    capacidades
+        .iter()
+        .find_map(|capacidad| capacidad.split_once('=').filter(|(n, _)| *n == nombre).map(|(_, valor)| valor))
+}
@@ -0,0 +1,418 @@
+use crate::base_objetos::BaseObjetos;
+use crate::bloqueo::{self, Bloqueo};
+use crate::indice::Indice;
+use crate::objetos::{Etiqueta, Objeto, Tipo};
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A dónde apunta .git/HEAD
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Head {
+    /// Nombre completo de la referencia, por ejemplo `refs/heads/main`
+    Rama(String),
+    /// Hash del commit en estado detached
+    Separado(String),
+}
+
+pub(crate) fn leer_head() -> Result<Head> {
+    let contenido = fs::read_to_string(".git/HEAD").context("No se pudo leer el archivo .git/HEAD")?;
+    let contenido = contenido.trim();
+    match contenido.strip_prefix("ref: ") {
+        Some(nombre_ref) => Ok(Head::Rama(nombre_ref.trim().to_string())),
+        None => Ok(Head::Separado(contenido.to_string())),
+    }
+}
+
+/// Hace que HEAD apunte a una rama o, en estado detached, directamente a un commit
+pub(crate) fn escribir_head(head: &Head) -> Result<()> {
+    let contenido = match head {
+        Head::Rama(nombre_ref) => format!("ref: {}\n", nombre_ref),
+        Head::Separado(hash) => format!("{}\n", hash),
+    };
+    bloqueo::escribir_atomico(Path::new(".git/HEAD"), contenido.as_bytes())
+        .context("No se pudo escribir .git/HEAD")
+}
+
+/// Devuelve el commit al que apunta HEAD, o None si la rama actual todavía no tiene commits
+pub(crate) fn resolver_head() -> Result<Option<String>> {
+    match leer_head()? {
+        Head::Rama(nombre_ref) => resolver_referencia(&nombre_ref),
+        Head::Separado(hash) => Ok(Some(hash)),
+    }
+}
+
+/// Resuelve una referencia completa (`refs/heads/main`, `HEAD`...) buscando primero el archivo
+/// suelto y luego en packed-refs. Sigue las referencias simbólicas.
+pub(crate) fn resolver_referencia(nombre_ref: &str) -> Result<Option<String>> {
+    let mut nombre_ref = nombre_ref.to_string();
+    // Límite para no entrar en un ciclo de referencias simbólicas
+    for _ in 0..5 {
+        if nombre_ref.contains("..") {
+            anyhow::bail!("'{}' no es un nombre de referencia válido", nombre_ref);
+        }
+        let ruta = Path::new(".git").join(&nombre_ref);
+        if ruta.is_file() {
+            let contenido = fs::read_to_string(&ruta)
+                .with_context(|| format!("No se pudo leer la referencia {}", nombre_ref))?;
+            if let Some(destino) = contenido.strip_prefix("ref: ") {
+                nombre_ref = destino.trim().to_string();
+                continue;
+            }
+            // Un hash al principio de la primera línea; FETCH_HEAD añade más datos detrás
+            return match contenido.split_whitespace().next() {
+                Some(hash) if es_hash(hash) => Ok(Some(hash.to_string())),
+                _ => anyhow::bail!("La referencia {} está dañada: no contiene un hash", nombre_ref),
+            };
+        }
+        return leer_packed_ref(&nombre_ref);
+    }
+    anyhow::bail!("Demasiados niveles de referencias simbólicas en {}", nombre_ref)
+}
+
+fn leer_packed_ref(nombre_ref: &str) -> Result<Option<String>> {
+    Ok(leer_packed_refs()?
+        .into_iter()
+        .find(|(nombre, _)| nombre == nombre_ref)
+        .map(|(_, hash)| hash))
+}
+
+/// Lista las referencias que empiezan con el prefijo indicado (por ejemplo `refs/heads/`),
+/// combinando las sueltas y las de packed-refs. Las sueltas tienen prioridad.
+pub(crate) fn listar_referencias(prefijo: &str) -> Result<BTreeMap<String, String>> {
+    let mut referencias = BTreeMap::new();
+
+    for (nombre, hash) in leer_packed_refs()? {
+        if nombre.starts_with(prefijo) {
+            referencias.insert(nombre, hash);
+        }
+    }
+
+    let mut pendientes = vec![PathBuf::from(".git/refs")];
+    while let Some(directorio) = pendientes.pop() {
+        if !directorio.is_dir() {
+            continue;
+        }
+        for entrada in fs::read_dir(&directorio)? {
+            let ruta = entrada?.path();
+            if ruta.is_dir() {
+                pendientes.push(ruta);
+                continue;
+            }
+            let nombre = ruta
+                .strip_prefix(".git")
+                .expect("la ruta está dentro de .git")
+                .to_string_lossy()
+                .replace('\\', "/");
+            // Archivos temporales de otras escrituras en curso
+            if !nombre.starts_with(prefijo) || nombre.ends_with(".lock") {
+                continue;
+            }
+            // Como git, una referencia dañada se avisa y se salta en lugar de impedir el listado
+            match resolver_referencia(&nombre) {
+                Ok(Some(hash)) => {
+                    referencias.insert(nombre, hash);
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("aviso: se ignora {}: {}", nombre, e),
+            }
+        }
+    }
+
+    Ok(referencias)
+}
+
+/// Un objeto desde el que se recorre el repositorio para saber qué sigue siendo alcanzable
+pub(crate) struct Raiz {
+    pub(crate) origen: OrigenRaiz,
+    pub(crate) hash: String,
+}
+
+/// De dónde sale una raíz
+pub(crate) enum OrigenRaiz {
+    /// Una referencia bajo `refs/` o HEAD
+    Referencia(String),
+    /// Una entrada de un reflog, con la ruta del archivo
+    Reflog(PathBuf),
+    /// Una entrada del índice (añadida con `agregar` pero quizá todavía sin commit), con su ruta
+    Indice(String),
+}
+
+impl fmt::Display for OrigenRaiz {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrigenRaiz::Referencia(nombre) => write!(f, "{}", nombre),
+            OrigenRaiz::Reflog(ruta) => write!(f, "el reflog {}", ruta.display()),
+            OrigenRaiz::Indice(ruta) => write!(f, "la entrada '{}' del índice", ruta),
+        }
+    }
+}
+
+/// Las raíces de la alcanzabilidad, las mismas que usa git: las referencias, HEAD, las entradas
+/// del reflog y las del índice. De los reflogs solo se devuelven los objetos que todavía existen,
+/// porque pueden mencionar otros ya podados; las entradas del índice que son submódulos no se
+/// incluyen, porque apuntan a commits de otro repositorio.
+pub(crate) fn raices(base: &BaseObjetos) -> Result<Vec<Raiz>> {
+    let mut raices: Vec<Raiz> = listar_referencias("refs/")?
+        .into_iter()
+        .map(|(nombre, hash)| Raiz {
+            origen: OrigenRaiz::Referencia(nombre),
+            hash,
+        })
+        .collect();
+    if let Some(hash) = resolver_head()? {
+        raices.push(Raiz {
+            origen: OrigenRaiz::Referencia("HEAD".to_string()),
+            hash,
+        });
+    }
+
+    let mut pendientes = vec![PathBuf::from(".git/logs")];
+    while let Some(ruta) = pendientes.pop() {
+        if ruta.is_dir() {
+            for entrada in fs::read_dir(&ruta)? {
+                pendientes.push(entrada?.path());
+            }
+            continue;
+        }
+        let Ok(contenido) = fs::read_to_string(&ruta) else {
+            continue;
+        };
+        for linea in contenido.lines() {
+            let mut partes = linea.split(' ');
+            for hash in [partes.next(), partes.next()].into_iter().flatten() {
+                if hash.bytes().any(|b| b != b'0') && base.existe(hash) {
+                    raices.push(Raiz {
+                        origen: OrigenRaiz::Reflog(ruta.clone()),
+                        hash: hash.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    let ruta_indice = Path::new(".git/index");
+    if ruta_indice.exists() {
+        for entrada in Indice::leer(ruta_indice)?.entradas {
+            if entrada.modo >> 12 != 0b1110 {
+                raices.push(Raiz {
+                    hash: hex::encode(entrada.hash),
+                    origen: OrigenRaiz::Indice(entrada.ruta),
+                });
+            }
+        }
+    }
+    Ok(raices)
+}
+
+/// Escribe una referencia suelta apuntando al hash indicado (a través de `<ref>.lock`)
+pub(crate) fn escribir_referencia(nombre_ref: &str, hash: &str) -> Result<()> {
+    let ruta = Path::new(".git").join(nombre_ref);
+    bloqueo::escribir_atomico(&ruta, format!("{}\n", hash).as_bytes())
+        .with_context(|| format!("No se pudo escribir la referencia {}", nombre_ref))
+}
+
+/// Como `escribir_referencia`, pero solo si la referencia sigue apuntando a `anterior` (con None,
+/// si todavía no existe). Igual que `git update-ref` con el valor anterior, la comprobación se
+/// hace con `<ref>.lock` tomado, así que no se pisa lo que otro proceso haya escrito entretanto.
+pub(crate) fn actualizar_referencia(nombre_ref: &str, hash: &str, anterior: Option<&str>) -> Result<()> {
+    let ruta = Path::new(".git").join(nombre_ref);
+    let mut bloqueo = Bloqueo::adquirir(&ruta)?;
+    if fs::read_to_string(&ruta).is_ok_and(|contenido| contenido.starts_with("ref: ")) {
+        anyhow::bail!("{} es una referencia simbólica", nombre_ref);
+    }
+    let actual = resolver_referencia(nombre_ref)?;
+    if actual.as_deref() != anterior {
+        anyhow::bail!(
+            "No se pudo actualizar {}: ha cambiado mientras tanto (apunta a {}, se esperaba {})",
+            nombre_ref,
+            actual.as_deref().unwrap_or("nada"),
+            anterior.unwrap_or("nada")
+        );
+    }
+    bloqueo.escribir(format!("{}\n", hash).as_bytes())?;
+    bloqueo
+        .confirmar()
+        .with_context(|| format!("No se pudo escribir la referencia {}", nombre_ref))
+}
+
+/// Borra una referencia tanto suelta como de packed-refs
+pub(crate) fn borrar_referencia(nombre_ref: &str) -> Result<()> {
+    let ruta = Path::new(".git").join(nombre_ref);
+    // Mientras se borra, nadie más puede escribir la referencia ni reescribir packed-refs (que
+    // podría estar empaquetándola ahora mismo); los dos bloqueos se toman antes de leer nada
+    let bloqueo_ref = Bloqueo::adquirir(&ruta)?;
+    let ruta_packed = Path::new(".git/packed-refs");
+    let mut bloqueo_packed = Bloqueo::adquirir(ruta_packed)?;
+    if ruta.is_file() {
+        // Solo se borra lo que de verdad es una referencia, no cualquier archivo de .git al que
+        // se llegue con un nombre raro
+        let contenido = fs::read_to_string(&ruta).unwrap_or_default();
+        if !es_hash(contenido.trim()) {
+            anyhow::bail!("{} no es una referencia a un objeto; no se borra", nombre_ref);
+        }
+        fs::remove_file(&ruta)?;
+    }
+
+    if ruta_packed.exists() {
+        let contenido = fs::read_to_string(ruta_packed)?;
+        let mut resultado = String::new();
+        let mut saltar_peeled = false;
+        for linea in contenido.lines() {
+            // La línea ^hash pertenece a la referencia anterior
+            if linea.starts_with('^') && saltar_peeled {
+                continue;
+            }
+            saltar_peeled = linea.split_once(' ').is_some_and(|(_, nombre)| nombre == nombre_ref);
+            if !saltar_peeled {
+                resultado.push_str(linea);
+                resultado.push('\n');
+            }
+        }
+        if resultado != contenido {
+            bloqueo_packed.escribir(resultado.as_bytes())?;
+            bloqueo_packed.confirmar()?;
+        }
+    }
+    drop(bloqueo_ref);
+
+    limpiar_directorios(&ruta);
+    Ok(())
+}
+
+/// Pasa todas las referencias sueltas a `.git/packed-refs` (como `git pack-refs --all`) y
+/// devuelve cuántas se empaquetaron. Las simbólicas se quedan sueltas. Cada referencia se
+/// guarda con el objeto al que apunta ya pelado (`^<hash>`) si es una etiqueta anotada.
+pub(crate) fn empaquetar_referencias() -> Result<usize> {
+    let ruta_packed = Path::new(".git/packed-refs");
+    let mut bloqueo_packed = Bloqueo::adquirir(ruta_packed)?;
+
+    let mut referencias: BTreeMap<String, String> = leer_packed_refs()?.into_iter().collect();
+    let mut sueltas = Vec::new();
+    let mut pendientes = vec![PathBuf::from(".git/refs")];
+    while let Some(directorio) = pendientes.pop() {
+        if !directorio.is_dir() {
+            continue;
+        }
+        for entrada in fs::read_dir(&directorio)? {
+            let ruta = entrada?.path();
+            if ruta.is_dir() {
+                pendientes.push(ruta);
+                continue;
+            }
+            let nombre = ruta
+                .strip_prefix(".git")
+                .expect("la ruta está dentro de .git")
+                .to_string_lossy()
+                .replace('\\', "/");
+            if nombre.ends_with(".lock") {
+                continue;
+            }
+            let contenido = fs::read_to_string(&ruta)?;
+            let hash = contenido.trim();
+            if hash.starts_with("ref: ") {
+                continue;
+            }
+            referencias.insert(nombre.clone(), hash.to_string());
+            sueltas.push((nombre, hash.to_string()));
+        }
+    }
+
+    let mut contenido = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+    for (nombre, hash) in &referencias {
+        contenido.push_str(&format!("{} {}\n", hash, nombre));
+        let mut pelado = hash.clone();
+        while let Ok(objeto) = Objeto::leer(&pelado) {
+            if objeto.tipo != Tipo::Tag {
+                break;
+            }
+            pelado = Etiqueta::leer(&pelado)?.objeto;
+        }
+        if &pelado != hash {
+            contenido.push_str(&format!("^{}\n", pelado));
+        }
+    }
+    bloqueo_packed.escribir(contenido.as_bytes())?;
+    bloqueo_packed.confirmar()?;
+
+    // Una referencia suelta solo se borra si nadie la cambió mientras tanto
+    for (nombre, hash) in &sueltas {
+        let ruta = Path::new(".git").join(nombre);
+        let Ok(bloqueo_ref) = Bloqueo::adquirir(&ruta) else {
+            continue;
+        };
+        if fs::read_to_string(&ruta).is_ok_and(|actual| actual.trim() == hash) {
+            fs::remove_file(&ruta)?;
+            limpiar_directorios(&ruta);
+        }
+        drop(bloqueo_ref);
+    }
+    Ok(sueltas.len())
+}
+
+// Borra los directorios que quedaron vacíos (refs/heads/equipo/...), salvo los de siempre
+fn limpiar_directorios(ruta: &Path) {
+    let mut directorio = ruta.parent();
+    while let Some(actual) = directorio {
+        if actual.ends_with("refs/heads") || actual.ends_with("refs/tags") || actual.ends_with("refs") {
+            break;
+        }
+        if fs::remove_dir(actual).is_err() {
+            break;
+        }
+        directorio = actual.parent();
+    }
+}
+
+/// Comprueba que un nombre de rama o etiqueta cumpla las reglas de `git check-ref-format`
+pub(crate) fn validar_nombre(nombre: &str) -> Result<()> {
+    let invalido = nombre.is_empty()
+        || nombre == "@"
+        || nombre.starts_with('-')
+        || nombre.starts_with('/')
+        || nombre.ends_with('/')
+        || nombre.ends_with('.')
+        || nombre.contains("..")
+        || nombre.contains("//")
+        || nombre.contains("@{")
+        || nombre
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+        || nombre
+            .split('/')
+            .any(|parte| parte.starts_with('.') || parte.ends_with(".lock"));
+    if invalido {
+        anyhow::bail!("'{}' no es un nombre de referencia válido", nombre);
+    }
+    Ok(())
+}
+
+/// Como `validar_nombre`, con la regla que git añade para las ramas: no pueden llamarse HEAD
+pub(crate) fn validar_nombre_rama(nombre: &str) -> Result<()> {
+    validar_nombre(nombre)?;
+    if nombre == "HEAD" {
+        anyhow::bail!("'HEAD' no es un nombre de rama válido");
+    }
+    Ok(())
+}
+
+/// Si un texto es un hash completo (40 caracteres hexadecimales)
+pub(crate) fn es_hash(texto: &str) -> bool {
+    texto.len() == 40 && texto.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn leer_packed_refs() -> Result<Vec<(String, String)>> {
+    let ruta = Path::new(".git/packed-refs");
+    if !ruta.exists() {
+        return Ok(Vec::new());
+    }
+    let contenido = fs::read_to_string(ruta).context("No se pudo leer .git/packed-refs")?;
+    Ok(contenido
+        .lines()
+        .filter(|linea| !linea.starts_with('#') && !linea.starts_with('^'))
+        .filter_map(|linea| linea.split_once(' '))
+        .map(|(hash, nombre)| (nombre.to_string(), hash.to_string()))
+        .collect())
+}
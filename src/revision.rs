@@ -0,0 +1,490 @@
+use crate::base_objetos::BaseObjetos;
+use crate::comandos::leer_arbol;
+use crate::indice::Indice;
+use crate::objetos::{Commit, Etiqueta, Objeto, Tipo};
+use crate::referencias;
+use anyhow::{Context, Result};
+use std::collections::HashSet;
+use std::path::Path;
+
+// git no acepta abreviaturas de menos de 4 caracteres
+const LONGITUD_MINIMA_ABREVIATURA: usize = 4;
+
+/// Resuelve una revisión al hash completo del objeto que nombra.
+///
+/// Acepta hashes completos o abreviados, nombres de referencias (`main`, `v1.0`,
+/// `refs/heads/main`, `HEAD`), los sufijos `~N` y `^N` encadenados (`HEAD~3`, `main^2`)
+/// y la sintaxis `<rev>:<ruta>` para nombrar un archivo dentro del árbol de un commit.
+/// `:<ruta>` busca la ruta en el índice y `^{}`/`^{tipo}` pelan etiquetas anotadas.
+pub(crate) fn resolver(revision: &str) -> Result<String> {
+    if let Some((base, ruta)) = revision.split_once(':') {
+        let ruta = ruta.trim_start_matches("./").trim_end_matches('/');
+        if base.is_empty() {
+            return buscar_en_indice(ruta);
+        }
+        let arbol = resolver_arbol(base)?;
+        return buscar_en_arbol(&arbol, ruta)
+            .with_context(|| format!("la ruta '{}' no existe en '{}'", ruta, base));
+    }
+
+    // Separar el nombre base de los sufijos ~N y ^N
+    let fin_base = revision.find(['~', '^']).unwrap_or(revision.len());
+    let (base, mut sufijos) = revision.split_at(fin_base);
+    if base.is_empty() {
+        anyhow::bail!("Revisión inválida: '{}'", revision);
+    }
+    let mut hash = resolver_nombre(base)?;
+
+    while let Some(operador) = sufijos.chars().next() {
+        if operador != '~' && operador != '^' {
+            anyhow::bail!("Revisión inválida: '{}'", revision);
+        }
+        sufijos = &sufijos[1..];
+
+        // ^{} pela las etiquetas y ^{tipo} pela hasta llegar a un objeto de ese tipo
+        if operador == '^' && sufijos.starts_with('{') {
+            let fin = sufijos
+                .find('}')
+                .with_context(|| format!("Revisión inválida: '{}'", revision))?;
+            let tipo = &sufijos[1..fin];
+            sufijos = &sufijos[fin + 1..];
+            hash = match tipo {
+                "" => pelar(&hash)?.1,
+                "commit" => pelar_commit(&hash, revision)?,
+                "tree" => arbol_de(&hash, revision)?,
+                "object" => hash,
+                _ => anyhow::bail!("Tipo desconocido en '{}': '{}'", revision, tipo),
+            };
+            continue;
+        }
+
+        let digitos = sufijos.find(|c: char| !c.is_ascii_digit()).unwrap_or(sufijos.len());
+        let numero = if digitos == 0 {
+            1
+        } else {
+            sufijos[..digitos]
+                .parse::<usize>()
+                .with_context(|| format!("Número inválido en '{}'", revision))?
+        };
+        sufijos = &sufijos[digitos..];
+
+        match operador {
+            // ~N sigue N veces el primer padre
+            '~' => {
+                hash = pelar_commit(&hash, revision)?;
+                for _ in 0..numero {
+                    let commit = Commit::leer(&hash)?;
+                    hash = commit
+                        .padres
+                        .first()
+                        .cloned()
+                        .with_context(|| format!("'{}' no tiene tantos ancestros", revision))?;
+                }
+            }
+            // ^N elige el N-ésimo padre, ^0 es el propio commit
+            _ => {
+                hash = pelar_commit(&hash, revision)?;
+                let commit = Commit::leer(&hash)?;
+                if numero > 0 {
+                    hash = commit
+                        .padres
+                        .get(numero - 1)
+                        .cloned()
+                        .with_context(|| format!("'{}' no tiene el padre {}", revision, numero))?;
+                }
+            }
+        }
+    }
+
+    Ok(hash)
+}
+
+/// Resuelve una revisión y, si es un commit o una etiqueta, devuelve el árbol al que apunta
+pub(crate) fn resolver_arbol(revision: &str) -> Result<String> {
+    arbol_de(&resolver(revision)?, revision)
+}
+
+/// Resuelve una revisión que debe nombrar un commit (directamente o a través de etiquetas)
+pub(crate) fn resolver_commit(revision: &str) -> Result<String> {
+    pelar_commit(&resolver(revision)?, revision)
+}
+
+/// Sigue las etiquetas anotadas hasta llegar a un objeto que no sea un tag
+pub(crate) fn pelar(hash: &str) -> Result<(Tipo, String)> {
+    let mut hash = hash.to_string();
+    let mut tipo = Objeto::leer(&hash)?.tipo;
+    while tipo == Tipo::Tag {
+        let etiqueta = Etiqueta::leer(&hash)?;
+        hash = etiqueta.objeto;
+        tipo = etiqueta.tipo;
+    }
+    Ok((tipo, hash))
+}
+
+fn pelar_commit(hash: &str, revision: &str) -> Result<String> {
+    match pelar(hash)? {
+        (Tipo::Commit, hash) => Ok(hash),
+        (tipo, _) => anyhow::bail!("'{}' es un {}, no un commit", revision, tipo),
+    }
+}
+
+fn arbol_de(hash: &str, revision: &str) -> Result<String> {
+    match pelar(hash)? {
+        (Tipo::Arbol, hash) => Ok(hash),
+        (Tipo::Commit, hash) => Ok(Commit::leer(&hash)?.arbol),
+        (tipo, _) => anyhow::bail!("'{}' es un {}, no un árbol", revision, tipo),
+    }
+}
+
+fn resolver_nombre(nombre: &str) -> Result<String> {
+    if nombre == "HEAD" || nombre == "@" {
+        return referencias::resolver_head()?.context("La rama actual todavía no tiene commits");
+    }
+
+    // Un nombre con '..' podría salir de .git/refs
+    if nombre.contains("..") {
+        anyhow::bail!("Revisión desconocida: '{}'", nombre);
+    }
+
+    // Las referencias tienen prioridad sobre los hashes abreviados, igual que en git. En la raíz
+    // de .git solo se buscan las pseudorreferencias (HEAD, FETCH_HEAD, ORIG_HEAD...), que van en
+    // mayúsculas: `config` o `description` son archivos de git, no ramas
+    let directo = if nombre.starts_with("refs/") || es_pseudorreferencia(nombre) {
+        Some(nombre.to_string())
+    } else {
+        None
+    };
+    for candidato in directo.into_iter().chain([
+        format!("refs/{}", nombre),
+        format!("refs/tags/{}", nombre),
+        format!("refs/heads/{}", nombre),
+        format!("refs/remotes/{}", nombre),
+        format!("refs/remotes/{}/HEAD", nombre),
+    ]) {
+        if let Some(hash) = referencias::resolver_referencia(&candidato)? {
+            return Ok(hash);
+        }
+    }
+
+    if nombre.len() >= LONGITUD_MINIMA_ABREVIATURA
+        && nombre.len() <= 40
+        && nombre.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return buscar_abreviatura(&nombre.to_lowercase());
+    }
+
+    anyhow::bail!("Revisión desconocida: '{}'", nombre)
+}
+
+fn es_pseudorreferencia(nombre: &str) -> bool {
+    !nombre.is_empty() && nombre.chars().all(|c| c.is_ascii_uppercase() || c == '_')
+}
+
+fn buscar_abreviatura(prefijo: &str) -> Result<String> {
+    // Incluye los objetos empacados, no solo los sueltos
+    let mut candidatos: Vec<String> = BaseObjetos::abrir(".git").buscar_prefijo(prefijo)?.into_iter().collect();
+
+    match candidatos.len() {
+        0 => anyhow::bail!("Revisión desconocida: '{}'", prefijo),
+        1 => Ok(candidatos.remove(0)),
+        _ => {
+            let mut mensaje = format!("El hash abreviado '{}' es ambiguo. Los candidatos son:", prefijo);
+            for candidato in &candidatos {
+                let tipo = Objeto::leer(candidato)
+                    .map(|o| o.tipo.to_string())
+                    .unwrap_or_else(|_| "desconocido".to_string());
+                mensaje.push_str(&format!("\n  {} {}", candidato, tipo));
+            }
+            anyhow::bail!(mensaje)
+        }
+    }
+}
+
+fn buscar_en_arbol(hash_arbol: &str, ruta: &str) -> Result<String> {
+    let mut actual = hash_arbol.to_string();
+    if ruta.is_empty() {
+        return Ok(actual);
+    }
+    for componente in ruta.split('/') {
+        let entrada = leer_arbol::leer_entradas(&actual)?
+            .into_iter()
+            .find(|e| e.nombre == componente)
+            .context("ruta no encontrada")?;
+        actual = entrada.hash;
+    }
+    Ok(actual)
+}
+
+fn buscar_en_indice(ruta: &str) -> Result<String> {
+    let indice = Indice::leer(Path::new(".git/index"))?;
+    indice
+        .entradas
+        .iter()
+        .find(|e| e.ruta == ruta && e.etapa() == 0)
+        .map(|e| hex::encode(e.hash))
+        .with_context(|| format!("la ruta '{}' no está en el índice", ruta))
+}
+
+/// Indica si `ancestro` es alcanzable desde `descendiente` siguiendo los padres
+pub(crate) fn es_ancestro(ancestro: &str, descendiente: &str) -> Result<bool> {
+    let mut pendientes = vec![descendiente.to_string()];
+    let mut vistos = HashSet::new();
+    while let Some(hash) = pendientes.pop() {
+        if hash == ancestro {
+            return Ok(true);
+        }
+        if vistos.insert(hash.clone()) {
+            pendientes.extend(Commit::leer(&hash)?.padres);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_objetos;
+    use crate::indice::EntradaIndice;
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::{Mutex, MutexGuard};
+
+    // El directorio actual es de todo el proceso: las pruebas que se meten en un repositorio
+    // temporal se turnan
+    static DIRECTORIO_ACTUAL: Mutex<()> = Mutex::new(());
+
+    // Un repositorio temporal que pasa a ser el directorio actual mientras dura la prueba
+    struct Repositorio {
+        ruta: PathBuf,
+        anterior: PathBuf,
+        base: BaseObjetos,
+        _turno: MutexGuard<'static, ()>,
+    }
+
+    impl Repositorio {
+        fn nuevo(nombre: &str) -> Repositorio {
+            let turno = DIRECTORIO_ACTUAL.lock().unwrap_or_else(|e| e.into_inner());
+            let ruta = std::env::temp_dir().join(format!("revision-{}-{}", nombre, std::process::id()));
+            let _ = fs::remove_dir_all(&ruta);
+            fs::create_dir_all(ruta.join(".git/refs/heads")).unwrap();
+            fs::create_dir_all(ruta.join(".git/refs/tags")).unwrap();
+            fs::write(ruta.join(".git/HEAD"), "ref: refs/heads/main\n").unwrap();
+            let anterior = std::env::current_dir().unwrap();
+            std::env::set_current_dir(&ruta).unwrap();
+            Objeto::recargar_base();
+            Repositorio {
+                base: BaseObjetos::abrir(ruta.join(".git")),
+                ruta,
+                anterior,
+                _turno: turno,
+            }
+        }
+
+        fn blob(&self, contenido: &str) -> String {
+            self.base.escribir(Tipo::Blob, contenido.as_bytes()).unwrap()
+        }
+
+        fn arbol(&self, entradas: &[(&str, &str, &str)]) -> String {
+            let mut contenido = Vec::new();
+            for (modo, nombre, hash) in entradas {
+                contenido.extend_from_slice(format!("{} {}\0", modo, nombre).as_bytes());
+                contenido.extend_from_slice(&hex::decode(hash).unwrap());
+            }
+            self.base.escribir(Tipo::Arbol, &contenido).unwrap()
+        }
+
+        fn commit(&self, arbol: &str, padres: &[&str], mensaje: &str) -> String {
+            let mut contenido = format!("tree {}\n", arbol);
+            for padre in padres {
+                contenido.push_str(&format!("parent {}\n", padre));
+            }
+            contenido.push_str("author Prueba <prueba@ejemplo.com> 1700000000 +0000\n");
+            contenido.push_str("committer Prueba <prueba@ejemplo.com> 1700000000 +0000\n");
+            contenido.push_str(&format!("\n{}\n", mensaje));
+            self.base.escribir(Tipo::Commit, contenido.as_bytes()).unwrap()
+        }
+
+        fn referencia(&self, nombre: &str, hash: &str) {
+            fs::write(self.ruta.join(".git").join(nombre), format!("{}\n", hash)).unwrap();
+        }
+    }
+
+    impl Drop for Repositorio {
+        fn drop(&mut self) {
+            let _ = std::env::set_current_dir(&self.anterior);
+            let _ = fs::remove_dir_all(&self.ruta);
+        }
+    }
+
+    // La historia de las pruebas: c1 <- c2 <- c3 <- fusion -> lateral -> c1, con main en la
+    // fusión y la etiqueta anotada v1 en c2
+    struct Historia {
+        a: String,
+        b: String,
+        directorio: String,
+        arbol: String,
+        c1: String,
+        c2: String,
+        c3: String,
+        lateral: String,
+        fusion: String,
+        etiqueta: String,
+    }
+
+    fn historia(repositorio: &Repositorio) -> Historia {
+        let a = repositorio.blob("hola\n");
+        let b = repositorio.blob("adios\n");
+        let directorio = repositorio.arbol(&[("100644", "b.txt", &b)]);
+        let arbol = repositorio.arbol(&[("100644", "a.txt", &a), ("40000", "dir", &directorio)]);
+        let c1 = repositorio.commit(&arbol, &[], "uno");
+        let c2 = repositorio.commit(&arbol, &[&c1], "dos");
+        let c3 = repositorio.commit(&arbol, &[&c2], "tres");
+        let lateral = repositorio.commit(&arbol, &[&c1], "lateral");
+        let fusion = repositorio.commit(&arbol, &[&c3, &lateral], "fusión");
+        repositorio.referencia("refs/heads/main", &fusion);
+        let etiqueta = repositorio
+            .base
+            .escribir(
+                Tipo::Tag,
+                format!(
+                    "object {}\ntype commit\ntag v1\ntagger Prueba <prueba@ejemplo.com> 1700000000 +0000\n\nv1\n",
+                    c2
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        repositorio.referencia("refs/tags/v1", &etiqueta);
+        Historia { a, b, directorio, arbol, c1, c2, c3, lateral, fusion, etiqueta }
+    }
+
+    fn error(revision: &str) -> String {
+        match resolver(revision) {
+            Ok(hash) => panic!("'{}' se ha resuelto a {}", revision, hash),
+            Err(e) => format!("{:#}", e),
+        }
+    }
+
+    #[test]
+    fn hashes_y_referencias() {
+        let repositorio = Repositorio::nuevo("hashes");
+        let h = historia(&repositorio);
+        assert_eq!(resolver(&h.c1).unwrap(), h.c1);
+        assert_eq!(resolver(&h.c1[..7]).unwrap(), h.c1);
+        assert_eq!(resolver(&h.c1[..4].to_uppercase()).unwrap(), h.c1);
+        assert_eq!(resolver("HEAD").unwrap(), h.fusion);
+        assert_eq!(resolver("@").unwrap(), h.fusion);
+        assert_eq!(resolver("main").unwrap(), h.fusion);
+        assert_eq!(resolver("refs/heads/main").unwrap(), h.fusion);
+        assert_eq!(resolver("v1").unwrap(), h.etiqueta);
+        error(&h.c1[..3]);
+        error("0000000");
+        error("no-existe");
+        error("../HEAD");
+        // `config` es un archivo de .git, no una referencia
+        fs::write(".git/config", "").unwrap();
+        error("config");
+    }
+
+    #[test]
+    fn abreviatura_ambigua() {
+        let repositorio = Repositorio::nuevo("ambigua");
+        // Dos blobs cuyos hashes empiezan igual: por la paradoja del cumpleaños bastan unos
+        // cientos para que coincidan los 4 primeros caracteres
+        let mut vistos: HashMap<String, String> = HashMap::new();
+        let (primero, segundo) = (0..)
+            .find_map(|n| {
+                let contenido = format!("{}\n", n);
+                let hash = base_objetos::calcular_hash(Tipo::Blob, contenido.as_bytes());
+                let anterior = vistos.insert(hash[..4].to_string(), contenido.clone())?;
+                Some((anterior, contenido))
+            })
+            .unwrap();
+        let primero = repositorio.blob(&primero);
+        let segundo = repositorio.blob(&segundo);
+
+        let mensaje = error(&primero[..4]);
+        assert!(mensaje.contains("ambiguo"), "{}", mensaje);
+        assert!(mensaje.contains(&primero) && mensaje.contains(&segundo), "{}", mensaje);
+        // Con los caracteres suficientes para distinguirlos deja de ser ambiguo
+        let comunes = primero.bytes().zip(segundo.bytes()).take_while(|(x, y)| x == y).count();
+        assert_eq!(resolver(&primero[..=comunes]).unwrap(), primero);
+        assert_eq!(resolver(&segundo[..=comunes]).unwrap(), segundo);
+    }
+
+    #[test]
+    fn ancestros() {
+        let repositorio = Repositorio::nuevo("ancestros");
+        let h = historia(&repositorio);
+        assert_eq!(resolver("main~0").unwrap(), h.fusion);
+        assert_eq!(resolver("main~").unwrap(), h.c3);
+        assert_eq!(resolver("main~1").unwrap(), h.c3);
+        assert_eq!(resolver("main~3").unwrap(), h.c1);
+        assert_eq!(resolver("HEAD^").unwrap(), h.c3);
+        assert_eq!(resolver("HEAD^0").unwrap(), h.fusion);
+        assert_eq!(resolver("HEAD^2").unwrap(), h.lateral);
+        assert_eq!(resolver("HEAD^^").unwrap(), h.c2);
+        assert_eq!(resolver("HEAD~1~1").unwrap(), h.c2);
+        assert_eq!(resolver("HEAD^2~1").unwrap(), h.c1);
+        assert_eq!(resolver(&format!("{}~2", &h.c3[..8])).unwrap(), h.c1);
+        // Las etiquetas anotadas se pelan hasta el commit
+        assert_eq!(resolver("v1~1").unwrap(), h.c1);
+        assert_eq!(resolver("v1^0").unwrap(), h.c2);
+        error("HEAD^3");
+        error("main~4");
+        error("~1");
+        error("HEAD~99999999999999999999999");
+        error(&format!("{}^", h.arbol));
+    }
+
+    #[test]
+    fn tipos() {
+        let repositorio = Repositorio::nuevo("tipos");
+        let h = historia(&repositorio);
+        assert_eq!(resolver("v1^{}").unwrap(), h.c2);
+        assert_eq!(resolver("v1^{commit}").unwrap(), h.c2);
+        assert_eq!(resolver("v1^{tree}").unwrap(), h.arbol);
+        assert_eq!(resolver("v1^{object}").unwrap(), h.etiqueta);
+        assert_eq!(resolver("HEAD^{tree}").unwrap(), h.arbol);
+        assert_eq!(resolver("HEAD^{commit}~1").unwrap(), h.c3);
+        assert_eq!(resolver(&format!("{}^{{}}", h.a)).unwrap(), h.a);
+        assert!(error("v1^{blob}").contains("Tipo desconocido"));
+        error("HEAD^{commit");
+        error(&format!("{}^{{commit}}", h.arbol));
+        error(&format!("{}^{{tree}}", h.a));
+    }
+
+    #[test]
+    fn rutas() {
+        let repositorio = Repositorio::nuevo("rutas");
+        let h = historia(&repositorio);
+        assert_eq!(resolver("HEAD:a.txt").unwrap(), h.a);
+        assert_eq!(resolver("HEAD:dir/b.txt").unwrap(), h.b);
+        assert_eq!(resolver("HEAD:./dir/").unwrap(), h.directorio);
+        assert_eq!(resolver("HEAD:").unwrap(), h.arbol);
+        assert_eq!(resolver("v1:a.txt").unwrap(), h.a);
+        assert_eq!(resolver(&format!("{}:dir", h.arbol)).unwrap(), h.directorio);
+        error("HEAD:no-existe");
+        error("HEAD:a.txt/b");
+
+        // `:ruta` mira el índice, no HEAD
+        let metadata = fs::metadata(".git/HEAD").unwrap();
+        let mut hash = [0u8; 20];
+        hash.copy_from_slice(&hex::decode(&h.b).unwrap());
+        let indice = Indice {
+            entradas: vec![EntradaIndice::desde_metadata("a.txt", &metadata, hash)],
+        };
+        indice.escribir(Path::new(".git/index")).unwrap();
+        assert_eq!(resolver(":a.txt").unwrap(), h.b);
+        error(":dir/b.txt");
+    }
+
+    #[test]
+    fn caracteres_no_ascii() {
+        let repositorio = Repositorio::nuevo("no-ascii");
+        historia(&repositorio);
+        for revision in ["HEAD~0é", "HEAD^{commit}é", "HEAD^é", "HEAD~é", "HEAD~1ñ~1", "mäin", "HEAD^{é}"] {
+            error(revision);
+        }
+    }
+}